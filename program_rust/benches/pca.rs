@@ -0,0 +1,120 @@
+/*!
+ * @file pca.rs
+ * @brief Criterion benchmarks for the hot paths: variant calling, covariance
+ *        and eigendecomposition, on synthetic data.
+ *
+ * Sizes default to a small cohort and can be changed through the environment:
+ *   PCA_BENCH_N  number of individuals (default 200)
+ *   PCA_BENCH_D  number of positions   (default 2000)
+ *   PCA_BENCH_G  genome length for variant calling (default 1000000)
+ * e.g. `PCA_BENCH_N=500 PCA_BENCH_D=5000 cargo bench --bench pca`.
+ *
+ * Author: Namir Garib
+ * Created: January 2025
+ */
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use pca_population_strat::pca::{
+    center_data, compute_covariance_matrix, compute_covariance_matrix_blocked, covariance_from_raw,
+    eigen_decomposition, eigen_decomposition_ql,
+};
+use pca_population_strat::variant_calling::call_variants;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+const SEED: u64 = 42;
+
+fn env_size(name: &str, default: usize) -> usize {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+/// n x d matrix of 0/1 calls whose frequencies differ between two populations.
+fn synthetic_calls(n: usize, d: usize) -> Vec<f64> {
+    let mut rng = StdRng::seed_from_u64(SEED);
+    let freqs: Vec<[f64; 2]> = (0..d)
+        .map(|_| [rng.gen_range(0.05..0.95), rng.gen_range(0.05..0.95)])
+        .collect();
+    let mut data = Vec::with_capacity(n * d);
+    for row in 0..n {
+        let population = row % 2;
+        for freq in &freqs {
+            data.push(if rng.gen::<f64>() < freq[population] {
+                1.0
+            } else {
+                0.0
+            });
+        }
+    }
+    data
+}
+
+/// A random genome and a copy with about one base in a hundred changed.
+fn synthetic_genomes(len: usize) -> (Vec<u8>, Vec<u8>) {
+    const BASES: [u8; 4] = [b'A', b'C', b'G', b'T'];
+    let mut rng = StdRng::seed_from_u64(SEED);
+    let reference: Vec<u8> = (0..len).map(|_| BASES[rng.gen_range(0..4)]).collect();
+    let individual = reference
+        .iter()
+        .map(|&base| {
+            if rng.gen::<f64>() < 0.01 {
+                BASES[rng.gen_range(0..4)]
+            } else {
+                base
+            }
+        })
+        .collect();
+    (reference, individual)
+}
+
+fn bench_call_variants(c: &mut Criterion) {
+    let len = env_size("PCA_BENCH_G", 1_000_000);
+    let (reference, individual) = synthetic_genomes(len);
+    c.bench_with_input(BenchmarkId::new("call_variants", len), &len, |b, _| {
+        b.iter(|| call_variants(black_box(&reference), black_box(&individual)))
+    });
+}
+
+fn bench_covariance(c: &mut Criterion) {
+    let n = env_size("PCA_BENCH_N", 200);
+    let d = env_size("PCA_BENCH_D", 2000);
+    let data = synthetic_calls(n, d);
+    let centered = center_data(&data, n, d);
+    let size = format!("{}x{}", n, d);
+
+    let mut group = c.benchmark_group("covariance");
+    group.sample_size(10);
+    group.bench_with_input(BenchmarkId::new("naive", &size), &size, |b, _| {
+        b.iter(|| compute_covariance_matrix(black_box(&centered), n, d))
+    });
+    group.bench_with_input(BenchmarkId::new("blocked", &size), &size, |b, _| {
+        b.iter(|| compute_covariance_matrix_blocked(black_box(&centered), n, d, 64))
+    });
+    group.bench_with_input(BenchmarkId::new("from_raw", &size), &size, |b, _| {
+        b.iter(|| covariance_from_raw(black_box(&data), n, d))
+    });
+    group.finish();
+}
+
+fn bench_eigen(c: &mut Criterion) {
+    // The pipeline decomposes a d x d covariance; keep d moderate since Jacobi is O(d^3) per sweep
+    let n = env_size("PCA_BENCH_N", 200);
+    let d = env_size("PCA_BENCH_D", 2000).min(400);
+    let data = synthetic_calls(n, d);
+    let cov = compute_covariance_matrix(&center_data(&data, n, d), n, d);
+
+    let mut group = c.benchmark_group("eigen_decomposition");
+    group.sample_size(10);
+    group.bench_with_input(BenchmarkId::new("jacobi", d), &d, |b, _| {
+        b.iter(|| eigen_decomposition(black_box(&cov), d))
+    });
+    group.bench_with_input(BenchmarkId::new("ql", d), &d, |b, _| {
+        b.iter(|| eigen_decomposition_ql(black_box(&cov), d))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_call_variants, bench_covariance, bench_eigen);
+criterion_main!(benches);
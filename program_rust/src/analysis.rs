@@ -6,105 +6,3785 @@
  * Created: January 2025
  */
 
-use crate::pca::{center_data, compute_covariance_matrix, eigen_decomposition, project_data};
-use crate::utils::{get_file_length, read_file_in_chunks};
-use crate::variant_calling::call_variants;
-use std::fs::File;
+use crate::checkpoint::Checkpoint;
+use crate::cluster::kmeans;
+use crate::error::PcaError;
+use crate::fastq::read_fastq_sequence;
+use crate::model::PCAModel;
+use crate::pca::{
+    center_data, center_data_in_place, center_data_weighted, column_means,
+    compute_correlation_matrix, compute_covariance_matrix_weighted, covariance_to_correlation,
+    dual_pca, dual_pca_from_gram, eigen_decomposition_ql, gram_matrix, impute_column_means,
+    normalize_scores, project_data, standardize_data, CovarianceAccumulator, PCAResult,
+};
+use crate::progress::{Progress, StageTimer};
+use crate::reference::Reference;
+use crate::stats::{
+    bartlett_sphericity, kmo, significant_components, tracy_widom_test, BartlettTest,
+};
+use crate::utils::{
+    apply_base_policy, group_haplotype_files, haplotype_of, is_fasta_path, is_fastq_path,
+    is_gzip_file, read_multifasta_with, read_sequence_with, sample_name, sequence_length_with,
+    write_atomically, FloatFormat, InvalidBasePolicy,
+};
+use crate::variant_calling::{
+    allele_frequencies, call_variants, call_variants_diploid, site_frequency_spectrum_with,
+};
+use crate::window::{read_window_map, run_windowed_pca};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::{HashMap, HashSet};
 use std::io::Write;
+use std::path::Path;
+
+/**
+ * @enum LengthPolicy
+ * @brief What to do when an individual's length differs from the reference.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LengthPolicy {
+    /// Abort the whole run on the first mismatch.
+    Strict,
+    /// Truncate every sequence to the shortest length seen, with a warning.
+    Truncate,
+    /// Drop the offending individual and continue with the rest.
+    Skip,
+}
+
+/**
+ * @enum MissingPolicy
+ * @brief What happens to missing calls (an `N` in an individual).
+ *
+ * Positions missing in every individual, such as an `N` in the reference,
+ * carry no information and are always dropped; the policy covers the rest.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MissingPolicy {
+    /// Abort the run, naming how many positions have missing calls.
+    Error,
+    /// Fill each missing call with its position's mean over called samples.
+    MeanImpute,
+    /// Drop every position where any individual is missing.
+    DropSnp,
+}
+
+/**
+ * @enum SoftMask
+ * @brief How lowercase (soft-masked, usually repeat) reference bases are treated.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SoftMask {
+    /// Uppercase them on load and analyse them like any other base.
+    Normalize,
+    /// Leave soft-masked positions out of the analysis entirely.
+    Exclude,
+}
+
+/**
+ * @enum Scaling
+ * @brief How each SNP column is normalized before PCA.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scaling {
+    /// Subtract the column mean only.
+    Center,
+    /// Subtract the column mean and divide by the column standard deviation.
+    Standardize,
+    /// Leave columns untouched: PCA of the raw second-moment matrix rather than
+    /// the covariance. Scores are projections of the raw calls. For debugging.
+    Raw,
+}
+
+/**
+ * @enum PcaAlgorithm
+ * @brief Which matrix the in-memory pipeline eigendecomposes.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PcaAlgorithm {
+    /// The d x d covariance (or correlation) matrix.
+    Primal,
+    /// The n x n Gram matrix (see pca::dual_pca); covariance only.
+    Dual,
+}
+
+/**
+ * @struct AnalysisOptions
+ * @brief Knobs for perform_full_analysis.
+ */
+#[derive(Debug, Clone)]
+pub struct AnalysisOptions {
+    /// How to handle individuals whose length differs from the reference.
+    pub length_policy: LengthPolicy,
+    /// Individuals up to this many bases longer than the reference (e.g. a
+    /// stray trailing character) are trimmed to its length with a warning
+    /// instead of counting as mismatches. Whitespace is never counted.
+    pub length_slack: usize,
+    /// Centering, z-score standardization, or (for debugging) no scaling at all.
+    pub scaling: Scaling,
+    /// Eigendecompose the covariance or the correlation matrix.
+    pub matrix: MatrixKind,
+    /// Number of principal components to compute and write.
+    pub num_components: usize,
+    /// Accumulate covariance one individual at a time instead of building
+    /// the n x d variant matrix.
+    pub streaming: bool,
+    /// Each individuals file is a multi-FASTA with one sample per record
+    /// (record ids become sample labels).
+    pub multi_fasta: bool,
+    /// Individuals are phased haplotype files, two per sample
+    /// (`<sample>.hap1.<ext>`, `<sample>.hap2.<ext>`), called as 0/1/2 dosages.
+    pub haplotype_pairs: bool,
+    /// Mask FASTQ bases below this Phred quality as missing (`N`).
+    pub min_qual: Option<u8>,
+    /// What to do with bytes other than A, C, G, T and N in any input.
+    pub invalid_bases: InvalidBasePolicy,
+    /// Analyse soft-masked reference positions, or exclude them.
+    pub soft_mask: SoftMask,
+    /// Fail on, mean-impute, or drop positions with missing calls.
+    pub missing: MissingPolicy,
+    /// Precision and notation of every number written to an output file.
+    pub float_format: FloatFormat,
+    /// Also write loadings.csv (one row per retained SNP position).
+    pub write_loadings: bool,
+    /// Also write distances.csv (pairwise distances between samples, O(n^2 * d)).
+    pub write_distances: bool,
+    /// Also write grm.csv: the n x n sample covariance (genetic relationship
+    /// matrix) of the columns that enter PCA.
+    pub emit_grm: bool,
+    /// Also write maf.csv (alternate and minor allele frequency per position).
+    pub write_frequencies: bool,
+    /// Also write sfs.csv (the site frequency spectrum, see
+    /// variant_calling::site_frequency_spectrum).
+    pub write_sfs: bool,
+    /// Flag pairs of samples at most this far apart (fraction of differing
+    /// positions) as likely duplicates in duplicates.txt; None skips the check.
+    pub duplicate_threshold: Option<f64>,
+    /// Drop SNPs whose minor allele frequency is below this (e.g. 0.05) before
+    /// PCA; 0 keeps every variable SNP.
+    pub min_maf: f64,
+    /// Also write diagnostics.csv (Bartlett's sphericity test and KMO on the
+    /// correlation matrix of the variable positions), printing them too.
+    pub write_diagnostics: bool,
+    /// Bootstrap replicates for bootstrap.csv (eigenvalue stability); 0 skips it.
+    pub bootstrap: usize,
+    /// Run PCA on a random subset of this many variable SNPs (chosen with
+    /// `seed`) for a quick preview; None uses every SNP.
+    pub sample_snps: Option<usize>,
+    /// Rescale each PC column of results.csv (and centroids.csv) to a maximum
+    /// absolute value of 1 for plotting; eigenvalues are unaffected.
+    pub normalize_scores: bool,
+    /// Report per-stage progress on stderr.
+    pub verbose: bool,
+    /// Print how long each pipeline stage took on stderr.
+    pub timing: bool,
+    /// Store every sequence read in this directory as it is read (see
+    /// checkpoint::Checkpoint), so an interrupted run can be resumed.
+    pub checkpoint_dir: Option<String>,
+    /// Keep what checkpoint_dir already holds and read only the files it
+    /// has not finished; otherwise the checkpoint starts empty.
+    pub resume: bool,
+    /// Cluster the PCA scores into this many groups with k-means and add a
+    /// cluster column to results.csv.
+    pub num_clusters: Option<usize>,
+    /// Seed for every randomized step (k-means++ initialization).
+    pub seed: u64,
+    /// Memory budget in bytes for the PCA stage: pick the primal or dual
+    /// algorithm that fits (see choose_algorithm); None always uses primal.
+    pub max_memory: Option<u64>,
+    /// Also write model.json (see PCAModel) for projecting samples later.
+    /// Only centered fits can be saved.
+    pub save_model: bool,
+    /// Known populations (see read_population_labels): adds a population column
+    /// to results.csv and writes per-population centroids to centroids.csv.
+    pub labels_file: Option<String>,
+    /// Only analyse the samples listed in this file (see read_sample_list).
+    pub include_file: Option<String>,
+    /// Leave out the samples listed in this file; applied after include_file.
+    pub exclude_file: Option<String>,
+    /// Position map (see window::read_window_map): also run PCA separately per
+    /// chromosome window and write windows.csv.
+    pub window_map: Option<String>,
+    /// Split each chromosome of window_map into windows of this many base
+    /// pairs; None gives one window per chromosome.
+    pub window_size: Option<u64>,
+}
+
+impl Default for AnalysisOptions {
+    fn default() -> Self {
+        AnalysisOptions {
+            length_policy: LengthPolicy::Strict,
+            length_slack: 0,
+            scaling: Scaling::Center,
+            matrix: MatrixKind::Covariance,
+            num_components: 10,
+            streaming: false,
+            multi_fasta: false,
+            haplotype_pairs: false,
+            min_qual: None,
+            invalid_bases: InvalidBasePolicy::Error,
+            soft_mask: SoftMask::Normalize,
+            missing: MissingPolicy::MeanImpute,
+            float_format: FloatFormat::default(),
+            write_loadings: false,
+            write_distances: false,
+            emit_grm: false,
+            write_frequencies: false,
+            write_sfs: false,
+            duplicate_threshold: None,
+            min_maf: 0.0,
+            write_diagnostics: false,
+            bootstrap: 0,
+            sample_snps: None,
+            normalize_scores: false,
+            verbose: false,
+            timing: false,
+            checkpoint_dir: None,
+            resume: false,
+            num_clusters: None,
+            seed: 0,
+            max_memory: None,
+            save_model: false,
+            labels_file: None,
+            include_file: None,
+            exclude_file: None,
+            window_map: None,
+            window_size: None,
+        }
+    }
+}
+
+/**
+ * @brief Configuration of a pipeline run. PcaConfig is the same type as
+ *        AnalysisOptions; build one field by field with PcaConfig::builder().
+ */
+pub type PcaConfig = AnalysisOptions;
+
+impl AnalysisOptions {
+    /**
+     * @brief Start a PcaConfigBuilder from the defaults, e.g.
+     *        `PcaConfig::builder().components(10).maf(0.05).build()`.
+     */
+    pub fn builder() -> PcaConfigBuilder {
+        PcaConfigBuilder::default()
+    }
+}
+
+/**
+ * @struct PcaConfigBuilder
+ * @brief Chainable setters for AnalysisOptions; every field left unset keeps
+ *        its AnalysisOptions::default() value.
+ */
+#[derive(Debug, Clone, Default)]
+pub struct PcaConfigBuilder {
+    options: AnalysisOptions,
+}
+
+impl PcaConfigBuilder {
+    /// How to handle individuals whose length differs from the reference.
+    pub fn length_policy(mut self, length_policy: LengthPolicy) -> Self {
+        self.options.length_policy = length_policy;
+        self
+    }
+
+    /// Extra bases tolerated (and trimmed) past the reference length.
+    pub fn length_slack(mut self, length_slack: usize) -> Self {
+        self.options.length_slack = length_slack;
+        self
+    }
+
+    /// Column scaling before PCA.
+    pub fn scaling(mut self, scaling: Scaling) -> Self {
+        self.options.scaling = scaling;
+        self
+    }
+
+    /// Eigendecompose the covariance or the correlation matrix.
+    pub fn matrix(mut self, matrix: MatrixKind) -> Self {
+        self.options.matrix = matrix;
+        self
+    }
+
+    /// Number of principal components (num_components).
+    pub fn components(mut self, components: usize) -> Self {
+        self.options.num_components = components;
+        self
+    }
+
+    /// Accumulate covariance one individual at a time.
+    pub fn streaming(mut self, streaming: bool) -> Self {
+        self.options.streaming = streaming;
+        self
+    }
+
+    /// Read each individuals file as a multi-FASTA.
+    pub fn multi_fasta(mut self, multi_fasta: bool) -> Self {
+        self.options.multi_fasta = multi_fasta;
+        self
+    }
+
+    /// Pair `.hap1`/`.hap2` files per sample and call diploid dosages.
+    pub fn haplotype_pairs(mut self, haplotype_pairs: bool) -> Self {
+        self.options.haplotype_pairs = haplotype_pairs;
+        self
+    }
+
+    /// Mask FASTQ bases below this Phred quality.
+    pub fn min_qual(mut self, min_qual: Option<u8>) -> Self {
+        self.options.min_qual = min_qual;
+        self
+    }
+
+    /// What to do with bytes other than A, C, G, T and N.
+    pub fn invalid_bases(mut self, invalid_bases: InvalidBasePolicy) -> Self {
+        self.options.invalid_bases = invalid_bases;
+        self
+    }
+
+    /// Analyse or exclude soft-masked reference positions.
+    pub fn soft_mask(mut self, soft_mask: SoftMask) -> Self {
+        self.options.soft_mask = soft_mask;
+        self
+    }
+
+    /// Fail on, mean-impute, or drop positions with missing calls.
+    pub fn missing(mut self, missing: MissingPolicy) -> Self {
+        self.options.missing = missing;
+        self
+    }
+
+    /// Precision and notation of the numbers in output files.
+    pub fn float_format(mut self, float_format: FloatFormat) -> Self {
+        self.options.float_format = float_format;
+        self
+    }
+
+    /// Also write loadings.csv.
+    pub fn write_loadings(mut self, write_loadings: bool) -> Self {
+        self.options.write_loadings = write_loadings;
+        self
+    }
+
+    /// Also write distances.csv.
+    pub fn write_distances(mut self, write_distances: bool) -> Self {
+        self.options.write_distances = write_distances;
+        self
+    }
+
+    /// Also write grm.csv.
+    pub fn emit_grm(mut self, emit_grm: bool) -> Self {
+        self.options.emit_grm = emit_grm;
+        self
+    }
+
+    /// Also write maf.csv.
+    pub fn write_frequencies(mut self, write_frequencies: bool) -> Self {
+        self.options.write_frequencies = write_frequencies;
+        self
+    }
+
+    /// Also write sfs.csv.
+    pub fn write_sfs(mut self, write_sfs: bool) -> Self {
+        self.options.write_sfs = write_sfs;
+        self
+    }
+
+    /// Flag near-identical samples at most this far apart.
+    pub fn duplicate_threshold(mut self, duplicate_threshold: Option<f64>) -> Self {
+        self.options.duplicate_threshold = duplicate_threshold;
+        self
+    }
+
+    /// Minor allele frequency filter (min_maf); 0 keeps every variable SNP.
+    pub fn maf(mut self, maf: f64) -> Self {
+        self.options.min_maf = maf;
+        self
+    }
+
+    /// Also write diagnostics.csv.
+    pub fn write_diagnostics(mut self, write_diagnostics: bool) -> Self {
+        self.options.write_diagnostics = write_diagnostics;
+        self
+    }
+
+    /// Bootstrap replicates for bootstrap.csv; 0 skips it.
+    pub fn bootstrap(mut self, bootstrap: usize) -> Self {
+        self.options.bootstrap = bootstrap;
+        self
+    }
+
+    /// Run PCA on a random subset of this many SNPs.
+    pub fn sample_snps(mut self, sample_snps: Option<usize>) -> Self {
+        self.options.sample_snps = sample_snps;
+        self
+    }
+
+    /// Rescale each PC column of results.csv to [-1, 1].
+    pub fn normalize_scores(mut self, normalize_scores: bool) -> Self {
+        self.options.normalize_scores = normalize_scores;
+        self
+    }
+
+    /// Report per-stage progress on stderr.
+    pub fn verbose(mut self, verbose: bool) -> Self {
+        self.options.verbose = verbose;
+        self
+    }
+
+    /// Print per-stage timings on stderr.
+    pub fn timing(mut self, timing: bool) -> Self {
+        self.options.timing = timing;
+        self
+    }
+
+    /// Checkpoint directory for resumable runs.
+    pub fn checkpoint_dir(mut self, checkpoint_dir: Option<String>) -> Self {
+        self.options.checkpoint_dir = checkpoint_dir;
+        self
+    }
+
+    /// Resume from checkpoint_dir instead of starting it afresh.
+    pub fn resume(mut self, resume: bool) -> Self {
+        self.options.resume = resume;
+        self
+    }
+
+    /// Cluster the scores into this many groups with k-means.
+    pub fn num_clusters(mut self, num_clusters: Option<usize>) -> Self {
+        self.options.num_clusters = num_clusters;
+        self
+    }
+
+    /// Seed for every randomized step.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.options.seed = seed;
+        self
+    }
+
+    /// Memory budget in bytes for choosing the PCA algorithm.
+    pub fn max_memory(mut self, max_memory: Option<u64>) -> Self {
+        self.options.max_memory = max_memory;
+        self
+    }
+
+    /// Also write model.json.
+    pub fn save_model(mut self, save_model: bool) -> Self {
+        self.options.save_model = save_model;
+        self
+    }
+
+    /// Known populations file for results.csv and centroids.csv.
+    pub fn labels_file(mut self, labels_file: Option<String>) -> Self {
+        self.options.labels_file = labels_file;
+        self
+    }
+
+    /// Only analyse the samples listed in this file.
+    pub fn include_file(mut self, include_file: Option<String>) -> Self {
+        self.options.include_file = include_file;
+        self
+    }
+
+    /// Leave out the samples listed in this file.
+    pub fn exclude_file(mut self, exclude_file: Option<String>) -> Self {
+        self.options.exclude_file = exclude_file;
+        self
+    }
+
+    /// Position map for per-window PCA (windows.csv).
+    pub fn window_map(mut self, window_map: Option<String>) -> Self {
+        self.options.window_map = window_map;
+        self
+    }
+
+    /// Window size in base pairs within each chromosome of window_map.
+    pub fn window_size(mut self, window_size: Option<u64>) -> Self {
+        self.options.window_size = window_size;
+        self
+    }
+
+    /// The finished configuration.
+    pub fn build(self) -> PcaConfig {
+        self.options
+    }
+}
+
+/**
+ * @enum MatrixKind
+ * @brief Which matrix is eigendecomposed.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatrixKind {
+    /// Sample covariance of the scaled columns.
+    Covariance,
+    /// Pearson correlation: covariance divided by the column standard deviations.
+    Correlation,
+}
+
+/**
+ * @brief Pre-flight check that individuals files match the reference length.
+ *
+ * @param individuals_files Paths to individuals' genomes.
+ * @param expected          Reference sequence length.
+ * @param policy            Invalid-base policy the files will be read with.
+ * @return Ok(()) or a single LengthMismatches error listing every offending file.
+ *
+ * Lengths come from sequence_length, so line breaks and FASTA headers are not
+ * counted. Sequences are only counted, never kept or compared. Missing and
+ * empty files fail here immediately.
+ */
+pub fn check_individual_lengths(
+    individuals_files: &[String],
+    expected: usize,
+    policy: InvalidBasePolicy,
+) -> Result<(), PcaError> {
+    check_individual_lengths_with(individuals_files, expected, policy, 0)
+}
+
+/**
+ * @brief check_individual_lengths, accepting files up to `slack` bases longer
+ *        than `expected` (see AnalysisOptions::length_slack).
+ */
+pub fn check_individual_lengths_with(
+    individuals_files: &[String],
+    expected: usize,
+    policy: InvalidBasePolicy,
+    slack: usize,
+) -> Result<(), PcaError> {
+    let mut mismatches = Vec::new();
+    for path in individuals_files {
+        let length = sequence_length_with(path, policy)?;
+        if length == 0 {
+            return Err(PcaError::EmptyFile(path.clone()));
+        }
+        if !(expected..=expected + slack).contains(&length) {
+            mismatches.push((path.clone(), length));
+        }
+    }
+    if mismatches.is_empty() {
+        Ok(())
+    } else {
+        Err(PcaError::LengthMismatches {
+            expected,
+            files: mismatches,
+        })
+    }
+}
+
+/**
+ * @struct InputCheck
+ * @brief One row of the --validate summary.
+ */
+#[derive(Debug, Clone)]
+pub struct InputCheck {
+    /// The file path (with the record id for multi-FASTA samples).
+    pub file: String,
+    /// Detected format: "raw", "fasta", "multi-fasta", with "+gzip" if compressed.
+    pub format: String,
+    /// Sequence length after parsing, if the file could be read.
+    pub length: Option<usize>,
+    /// Why the file would make the run fail, if it would.
+    pub error: Option<String>,
+}
+
+impl InputCheck {
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/**
+ * @brief Check every input without computing anything or writing any file.
+ *
+ * @param ref_file          Path to the reference genome (first row of the result).
+ * @param individuals_files Paths to individuals' genomes.
+ * @param multi_fasta       Parse individuals as multi-FASTA (one row per record).
+ * @param policy            What to do with bytes other than A, C, G, T and N.
+ * @param length_slack      Accept individuals up to this many bases longer than
+ *                          the reference, as a run would (it trims them).
+ * @return One InputCheck per file or record. Each file is opened, its format
+ *         detected and its sequence parsed; lengths are compared with the
+ *         reference's. Problems are recorded per row rather than returned early.
+ */
+pub fn validate_inputs(
+    ref_file: &str,
+    individuals_files: &[String],
+    multi_fasta: bool,
+    policy: InvalidBasePolicy,
+    length_slack: usize,
+) -> Vec<InputCheck> {
+    let format_of = |path: &str, multi: bool| {
+        let base = if is_fastq_path(path) {
+            "fastq"
+        } else if multi {
+            "multi-fasta"
+        } else if is_fasta_path(path) {
+            "fasta"
+        } else {
+            "raw"
+        };
+        match is_gzip_file(path) {
+            Ok(true) => format!("{}+gzip", base),
+            _ => base.to_string(),
+        }
+    };
+
+    let mut checks = Vec::new();
+    let reference = read_sequence_with(ref_file, policy).and_then(|seq| {
+        if seq.is_empty() {
+            Err(PcaError::EmptyFile(ref_file.to_string()))
+        } else {
+            Ok(seq.len())
+        }
+    });
+    let expected = reference.as_ref().ok().copied();
+    checks.push(InputCheck {
+        file: ref_file.to_string(),
+        format: format_of(ref_file, false),
+        length: expected,
+        error: reference.err().map(|e| e.to_string()),
+    });
+
+    for path in individuals_files {
+        let format = format_of(path, multi_fasta);
+        match load_individual_file(path, multi_fasta, None, policy) {
+            Ok(samples) => {
+                for (label, _, sequence) in samples {
+                    let error = match expected {
+                        Some(expected)
+                            if !(expected..=expected + length_slack).contains(&sequence.len()) =>
+                        {
+                            Some(
+                                PcaError::LengthMismatch {
+                                    file: path.clone(),
+                                    expected,
+                                    found: sequence.len(),
+                                }
+                                .to_string(),
+                            )
+                        }
+                        _ => None,
+                    };
+                    checks.push(InputCheck {
+                        file: if multi_fasta {
+                            format!("{} ({})", path, label)
+                        } else {
+                            path.clone()
+                        },
+                        format: format.clone(),
+                        length: Some(sequence.len()),
+                        error,
+                    });
+                }
+            }
+            Err(e) => checks.push(InputCheck {
+                file: path.clone(),
+                format,
+                length: None,
+                error: Some(e.to_string()),
+            }),
+        }
+    }
+    checks
+}
+
+/**
+ * @brief Load the samples held in one individuals file as (label, source file, sequence).
+ *
+ * @param path        Path to the file.
+ * @param multi_fasta Treat the file as a multi-FASTA holding one sample per
+ *                    record, labelled by record id; otherwise it is one sample
+ *                    labelled by its file name.
+ * @param min_qual    For FASTQ files, mask bases below this Phred quality as `N`.
+ *                    A FASTQ file is always a single sample.
+ * @param policy      What to do with bytes other than A, C, G, T and N.
+ *
+ * An empty sequence would leave a zero-length row in the n x d matrix, so it is
+ * an error: EmptyFile for a whole file, Parse for an empty multi-FASTA record.
+ */
+fn load_individual_file(
+    path: &str,
+    multi_fasta: bool,
+    min_qual: Option<u8>,
+    policy: InvalidBasePolicy,
+) -> Result<Vec<(String, String, Vec<u8>)>, PcaError> {
+    if !multi_fasta || is_fastq_path(path) {
+        let sequence = if is_fastq_path(path) {
+            apply_base_policy(path, read_fastq_sequence(path, min_qual)?, policy)?
+        } else {
+            read_sequence_with(path, policy)?
+        };
+        if sequence.is_empty() {
+            return Err(PcaError::EmptyFile(path.to_string()));
+        }
+        return Ok(vec![(sample_name(path), path.to_string(), sequence)]);
+    }
+    let records = read_multifasta_with(path, policy)?;
+    if records.is_empty() {
+        return Err(PcaError::EmptyFile(path.to_string()));
+    }
+    if let Some((id, _)) = records.iter().find(|(_, sequence)| sequence.is_empty()) {
+        return Err(PcaError::Parse(format!(
+            "{}: record {} has no sequence",
+            path, id
+        )));
+    }
+    Ok(records
+        .into_iter()
+        .map(|(id, sequence)| (id, path.to_string(), sequence))
+        .collect())
+}
+
+/**
+ * @brief Load every individual as (label, source file, sequence), in file order.
+ *
+ * With the `rayon` feature, files are read on separate threads; samples are
+ * still returned in the order of individuals_files.
+ */
+fn load_individuals(
+    individuals_files: &[String],
+    multi_fasta: bool,
+    min_qual: Option<u8>,
+    policy: InvalidBasePolicy,
+    progress: &mut Progress,
+) -> Result<Vec<(String, String, Vec<u8>)>, PcaError> {
+    #[cfg(feature = "rayon")]
+    let per_file: Vec<Vec<(String, String, Vec<u8>)>> = {
+        use rayon::prelude::*;
+        let per_file = individuals_files
+            .par_iter()
+            .map(|path| load_individual_file(path, multi_fasta, min_qual, policy))
+            .collect::<Result<_, _>>()?;
+        progress.inc(individuals_files.len());
+        per_file
+    };
+    #[cfg(not(feature = "rayon"))]
+    let per_file: Vec<Vec<(String, String, Vec<u8>)>> = {
+        let mut per_file = Vec::with_capacity(individuals_files.len());
+        for path in individuals_files {
+            per_file.push(load_individual_file(path, multi_fasta, min_qual, policy)?);
+            progress.inc(1);
+        }
+        per_file
+    };
+    progress.finish();
+    Ok(per_file.into_iter().flatten().collect())
+}
+
+/**
+ * @brief Symmetric eigensolver for the pipeline: nalgebra's when the `nalgebra`
+ *        feature is on, the built-in Jacobi solver otherwise.
+ */
+fn decompose(cov: &[f64], d: usize) -> PCAResult {
+    #[cfg(feature = "nalgebra")]
+    {
+        crate::pca::eigen_decomposition_nalgebra(cov, d)
+    }
+    #[cfg(not(feature = "nalgebra"))]
+    {
+        crate::pca::eigen_decomposition(cov, d)
+    }
+}
+
+/**
+ * @brief Indices of the columns that vary across individuals.
+ *
+ * @param data Data in row-major format: n x d
+ * @param n    Number of samples
+ * @param d    Dimension
+ * @return Original column positions whose values are not all identical.
+ */
+pub fn variable_columns(data: &[f64], n: usize, d: usize) -> Vec<usize> {
+    (0..d)
+        .filter(|&col| {
+            let first = data[col];
+            (1..n).any(|row| data[row * d + col] != first)
+        })
+        .collect()
+}
+
+/**
+ * @brief Keep only the given columns of a row-major n x d matrix, in place.
+ *
+ * @param data    Data in row-major format: n x d (resized to n x columns.len()).
+ * @param n       Number of samples
+ * @param d       Current dimension
+ * @param columns Sorted column indices to retain.
+ */
+pub fn retain_columns(data: &mut Vec<f64>, n: usize, d: usize, columns: &[usize]) {
+    let new_d = columns.len();
+    // Writing row by row never overtakes the read cursor, since new_d <= d
+    for row in 0..n {
+        for (new_col, &col) in columns.iter().enumerate() {
+            data[row * new_d + new_col] = data[row * d + col];
+        }
+    }
+    data.truncate(n * new_d);
+}
+
+/**
+ * @brief Resolve missing (NaN) calls according to a MissingPolicy, in place.
+ *
+ * @param data   Data in row-major format: n x d; reduced to the kept columns.
+ * @param n      Number of samples
+ * @param d      Dimension
+ * @param policy What to do with columns that are missing in some samples.
+ * @return The original indices of the kept columns, or InvalidInput under
+ *         MissingPolicy::Error. Columns missing in every sample are never kept.
+ */
+pub fn apply_missing_policy(
+    data: &mut Vec<f64>,
+    n: usize,
+    d: usize,
+    policy: MissingPolicy,
+) -> Result<Vec<usize>, PcaError> {
+    let missing: Vec<usize> = (0..d)
+        .map(|col| (0..n).filter(|&row| data[row * d + col].is_nan()).count())
+        .collect();
+    if policy == MissingPolicy::Error {
+        let partial: Vec<usize> = (0..d)
+            .filter(|&col| missing[col] > 0 && missing[col] < n)
+            .collect();
+        if let Some(&first) = partial.first() {
+            return Err(PcaError::InvalidInput(format!(
+                "{} position(s) have missing calls (first at position {}); choose mean imputation or dropping them.",
+                partial.len(),
+                first
+            )));
+        }
+    }
+    let kept: Vec<usize> = (0..d)
+        .filter(|&col| match policy {
+            MissingPolicy::DropSnp => missing[col] == 0,
+            MissingPolicy::Error | MissingPolicy::MeanImpute => missing[col] < n,
+        })
+        .collect();
+    retain_columns(data, n, d, &kept);
+    if policy == MissingPolicy::MeanImpute {
+        impute_column_means(data, n, kept.len());
+    }
+    Ok(kept)
+}
+
+/**
+ * @brief Remove monomorphic (zero-variance) columns from the data matrix.
+ *
+ * @param data Data in row-major format: n x d (shrunk in place).
+ * @param n    Number of samples
+ * @param d    Dimension
+ * @return The reduced dimension.
+ *
+ * Use variable_columns() + retain_columns() directly when the mapping back
+ * to original positions is needed.
+ */
+pub fn filter_invariant_columns(data: &mut Vec<f64>, n: usize, d: usize) -> usize {
+    let columns = variable_columns(data, n, d);
+    retain_columns(data, n, d, &columns);
+    columns.len()
+}
+
+/**
+ * @brief Indices of the columns whose minor allele frequency is at least `min_maf`.
+ *
+ * @param data    Data in row-major format: n x d (0/1 calls, NaN for missing)
+ * @param n       Number of samples
+ * @param d       Dimension
+ * @param min_maf Smallest allowed min(p, 1 - p), p from allele_frequencies.
+ * @return Original column positions that pass. Columns with no observed
+ *         call never pass.
+ */
+pub fn maf_columns(data: &[f64], n: usize, d: usize, min_maf: f64) -> Vec<usize> {
+    maf_columns_with(data, n, d, min_maf, 1)
+}
+
+/**
+ * @brief maf_columns for dosages of the given ploidy (0..=ploidy per call),
+ *        whose allele frequency is the column mean divided by the ploidy.
+ */
+pub fn maf_columns_with(
+    data: &[f64],
+    n: usize,
+    d: usize,
+    min_maf: f64,
+    ploidy: usize,
+) -> Vec<usize> {
+    allele_frequencies(data, n, d)
+        .iter()
+        .map(|&freq| freq / ploidy as f64)
+        .enumerate()
+        .filter(|(_, freq)| freq.min(1.0 - freq) >= min_maf)
+        .map(|(col, _)| col)
+        .collect()
+}
+
+/**
+ * @brief Remove SNPs whose minor allele frequency is below `min_maf`.
+ *
+ * @param data    Data in row-major format: n x d
+ * @param n       Number of samples
+ * @param d       Dimension
+ * @param min_maf Threshold, e.g. 0.05; a column exactly at it is kept.
+ * @return (matrix, new_d): the reduced row-major matrix and its dimension.
+ *
+ * Use maf_columns() + retain_columns() when the surviving positions are needed.
+ */
+pub fn filter_by_maf(data: &[f64], n: usize, d: usize, min_maf: f64) -> (Vec<f64>, usize) {
+    let columns = maf_columns(data, n, d, min_maf);
+    let mut matrix = data.to_vec();
+    retain_columns(&mut matrix, n, d, &columns);
+    (matrix, columns.len())
+}
+
+/**
+ * @brief Pick `m` of `columns` uniformly at random by reservoir sampling.
+ *
+ * @param columns Candidate column indices, e.g. the variable positions.
+ * @param m       How many to keep.
+ * @param seed    Seed for the choice, so a preview run can be repeated.
+ * @return The chosen columns in their original (genome) order; all of them
+ *         when m >= columns.len().
+ *
+ * A single pass (Algorithm R), so the candidates never need to be counted first.
+ */
+pub fn reservoir_sample(columns: &[usize], m: usize, seed: u64) -> Vec<usize> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut reservoir: Vec<usize> = Vec::with_capacity(m.min(columns.len()));
+    for (i, &col) in columns.iter().enumerate() {
+        if i < m {
+            reservoir.push(col);
+        } else {
+            let j = rng.gen_range(0..=i);
+            if j < m {
+                reservoir[j] = col;
+            }
+        }
+    }
+    reservoir.sort_unstable();
+    reservoir
+}
+
+/**
+ * @brief Windowed LD pruning, like PLINK's --indep-pairwise.
+ *
+ * @param data         Data in row-major format: n x d, columns in genome order
+ * @param n            Number of samples
+ * @param d            Dimension
+ * @param window       Number of consecutive SNPs compared with each other (at least 2).
+ * @param step         How many SNPs the window advances by (at least 1).
+ * @param r2_threshold A SNP is dropped when its squared correlation with an
+ *                     earlier retained SNP in the same window exceeds this.
+ * @return (matrix, columns): the pruned row-major matrix and the original
+ *         indices of the surviving SNPs.
+ *
+ * r^2 is computed over the samples where both SNPs are called; a constant
+ * column has r^2 = 0 with everything, so it is never pruned here.
+ */
+pub fn ld_prune(
+    data: &[f64],
+    n: usize,
+    d: usize,
+    window: usize,
+    step: usize,
+    r2_threshold: f64,
+) -> (Vec<f64>, Vec<usize>) {
+    let window = window.max(2);
+    let step = step.max(1);
+    let by_column = crate::pca::transpose(data, n, d);
+    let column = |col: usize| &by_column[col * n..(col + 1) * n];
+
+    let mut kept = vec![true; d];
+    let mut start = 0;
+    while start < d {
+        let end = (start + window).min(d);
+        for i in start..end {
+            if !kept[i] {
+                continue;
+            }
+            for (offset, keep) in kept[(i + 1)..end].iter_mut().enumerate() {
+                if *keep && squared_correlation(column(i), column(i + 1 + offset)) > r2_threshold {
+                    *keep = false;
+                }
+            }
+        }
+        if end == d {
+            break;
+        }
+        start += step;
+    }
+
+    let columns: Vec<usize> = (0..d).filter(|&col| kept[col]).collect();
+    let mut matrix = data.to_vec();
+    retain_columns(&mut matrix, n, d, &columns);
+    (matrix, columns)
+}
+
+/**
+ * @brief Squared Pearson correlation of two columns over the rows where both
+ *        are non-missing (0 if either is constant there).
+ */
+fn squared_correlation(x: &[f64], y: &[f64]) -> f64 {
+    let pairs = || {
+        x.iter()
+            .zip(y.iter())
+            .filter(|(a, b)| !a.is_nan() && !b.is_nan())
+    };
+    let count = pairs().count() as f64;
+    if count < 2.0 {
+        return 0.0;
+    }
+    let mean_x = pairs().map(|(a, _)| a).sum::<f64>() / count;
+    let mean_y = pairs().map(|(_, b)| b).sum::<f64>() / count;
+    let (mut sxy, mut sxx, mut syy) = (0.0, 0.0, 0.0);
+    for (a, b) in pairs() {
+        sxy += (a - mean_x) * (b - mean_y);
+        sxx += (a - mean_x) * (a - mean_x);
+        syy += (b - mean_y) * (b - mean_y);
+    }
+    if sxx <= 0.0 || syy <= 0.0 {
+        return 0.0;
+    }
+    sxy * sxy / (sxx * syy)
+}
+
+/**
+ * @brief Drop samples and SNPs with too many missing (NaN) calls, like PLINK's
+ *        --mind and --geno.
+ *
+ * @param data               Data in row-major format: n x d
+ * @param n                  Number of samples
+ * @param d                  Dimension
+ * @param max_snp_missing    Largest allowed fraction of NaN in a column.
+ * @param max_sample_missing Largest allowed fraction of NaN in a row.
+ * @return (matrix, rows, columns): the filtered row-major matrix and the
+ *         original indices of the surviving samples and SNPs.
+ *
+ * Samples are filtered first over all SNPs, then SNPs over the surviving
+ * samples (PLINK's order). A fraction equal to the threshold is kept. Run this
+ * before impute_column_means, which would otherwise hide the NaNs.
+ */
+pub fn apply_missingness_filters(
+    data: &[f64],
+    n: usize,
+    d: usize,
+    max_snp_missing: f64,
+    max_sample_missing: f64,
+) -> (Vec<f64>, Vec<usize>, Vec<usize>) {
+    let rows: Vec<usize> = (0..n)
+        .filter(|&row| {
+            let missing = data[row * d..(row + 1) * d]
+                .iter()
+                .filter(|v| v.is_nan())
+                .count();
+            d == 0 || missing as f64 / d as f64 <= max_sample_missing
+        })
+        .collect();
+
+    let columns: Vec<usize> = (0..d)
+        .filter(|&col| {
+            let missing = rows
+                .iter()
+                .filter(|&&row| data[row * d + col].is_nan())
+                .count();
+            rows.is_empty() || missing as f64 / rows.len() as f64 <= max_snp_missing
+        })
+        .collect();
+
+    let mut matrix = Vec::with_capacity(rows.len() * columns.len());
+    for &row in &rows {
+        matrix.extend(columns.iter().map(|&col| data[row * d + col]));
+    }
+
+    (matrix, rows, columns)
+}
+
+/**
+ * @brief Pairwise Manhattan distance between all samples' variant vectors.
+ *
+ * @param data Data in row-major format: n x d
+ * @param n    Number of samples
+ * @param d    Dimension
+ * @return The symmetric n x n distance matrix, row-major, with a zero diagonal.
+ *
+ * For 0/1 calls this is the Hamming distance (count of differing positions).
+ * Positions where either sample is missing (NaN) are skipped.
+ */
+pub fn pairwise_distance_matrix(data: &[f64], n: usize, d: usize) -> Vec<f64> {
+    let mut distances = vec![0.0; n * n];
+    for i in 0..n {
+        let row_i = &data[i * d..(i + 1) * d];
+        for j in (i + 1)..n {
+            let row_j = &data[j * d..(j + 1) * d];
+            let dist: f64 = row_i
+                .iter()
+                .zip(row_j.iter())
+                .map(|(a, b)| (a - b).abs())
+                .filter(|diff| !diff.is_nan())
+                .sum();
+            distances[i * n + j] = dist;
+            distances[j * n + i] = dist;
+        }
+    }
+    distances
+}
+
+/**
+ * @brief Pairs of samples that look like the same genome given twice.
+ *
+ * @param data         Data in row-major format: n x d
+ * @param n            Number of samples
+ * @param d            Dimension
+ * @param max_distance Largest fraction of differing positions still flagged
+ *                     (0 flags only identical call vectors).
+ * @return (i, j, distance) with i < j for every flagged pair, where distance
+ *         is the fraction of positions called in both samples that differ.
+ *         Pairs with no position called in both are never flagged.
+ */
+pub fn find_duplicates(
+    data: &[f64],
+    n: usize,
+    d: usize,
+    max_distance: f64,
+) -> Vec<(usize, usize, f64)> {
+    let mut duplicates = Vec::new();
+    for i in 0..n {
+        let row_i = &data[i * d..(i + 1) * d];
+        for j in (i + 1)..n {
+            let row_j = &data[j * d..(j + 1) * d];
+            let (differing, shared) = row_i
+                .iter()
+                .zip(row_j.iter())
+                .map(|(a, b)| (a - b).abs())
+                .filter(|diff| !diff.is_nan())
+                .fold((0.0, 0usize), |(sum, count), diff| (sum + diff, count + 1));
+            if shared == 0 {
+                continue;
+            }
+            let distance = differing / shared as f64;
+            if distance <= max_distance {
+                duplicates.push((i, j, distance));
+            }
+        }
+    }
+    duplicates
+}
+
+/**
+ * @brief Classical (Torgerson) multidimensional scaling.
+ *
+ * @param distances Symmetric n x n distance matrix, row-major (e.g. from
+ *                  pairwise_distance_matrix).
+ * @param n         Number of samples.
+ * @param k         Number of output dimensions.
+ * @return n x k coordinates, row-major, or InvalidInput for non-finite
+ *         distances. For Euclidean distances they reproduce the original
+ *         points up to rotation, reflection and translation.
+ *
+ * Double-centers the squared distances, B = -1/2 J D^2 J with J = I - 11^T/n,
+ * and scales the top k eigenvectors of B by the square roots of their
+ * eigenvalues. Dimensions beyond the positive eigenvalues (possible for
+ * non-Euclidean distances such as Hamming) are left at zero.
+ */
+pub fn classical_mds(distances: &[f64], n: usize, k: usize) -> Result<Vec<f64>, PcaError> {
+    let mut b: Vec<f64> = distances[..n * n].iter().map(|dist| dist * dist).collect();
+    let row_means: Vec<f64> = b
+        .chunks(n.max(1))
+        .map(|row| row.iter().sum::<f64>() / n as f64)
+        .collect();
+    let grand_mean = row_means.iter().sum::<f64>() / n as f64;
+    for i in 0..n {
+        for j in 0..n {
+            // D^2 is symmetric, so column means equal row means
+            b[i * n + j] = -0.5 * (b[i * n + j] - row_means[i] - row_means[j] + grand_mean);
+        }
+    }
+
+    let eigen = eigen_decomposition_ql(&b, n)?;
+    let k = k.min(n);
+    let mut coords = vec![0.0; n * k];
+    for comp in 0..k {
+        let eigenvalue = eigen.eigenvalues[comp];
+        if eigenvalue <= 0.0 {
+            break;
+        }
+        let scale = eigenvalue.sqrt();
+        for i in 0..n {
+            coords[i * k + comp] = eigen.eigenvectors[comp * n + i] * scale;
+        }
+    }
+    Ok(coords)
+}
+
+/**
+ * @brief Top-k eigenvalues of PCA re-run on SNPs resampled with replacement.
+ *
+ * @param data   Data in row-major format: n x d (no missing values).
+ * @param n      Number of samples
+ * @param d      Dimension
+ * @param k      Number of eigenvalues kept per replicate (clamped to min(n, d)).
+ * @param n_boot Number of bootstrap replicates.
+ * @param seed   Seed for the column resampling; equal seeds give equal output.
+ * @return One Vec of k eigenvalues (descending) per replicate, or
+ *         InvalidInput if the data hold a NaN or infinite value.
+ *
+ * Each replicate draws d columns with replacement, centers them and takes the
+ * eigenvalues of the n x n Gram matrix (see dual_pca), which equal the
+ * covariance eigenvalues but cost O(n^2 * d).
+ */
+pub fn bootstrap_eigenvalues(
+    data: &[f64],
+    n: usize,
+    d: usize,
+    k: usize,
+    n_boot: usize,
+    seed: u64,
+) -> Result<Vec<Vec<f64>>, PcaError> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut resampled = vec![0.0; n * d];
+    (0..n_boot)
+        .map(|_| {
+            for col in 0..d {
+                let src = rng.gen_range(0..d);
+                for row in 0..n {
+                    resampled[row * d + col] = data[row * d + src];
+                }
+            }
+            let centered = center_data(&resampled, n, d);
+            Ok(dual_pca(&centered, n, d, k)?.eigenvalues)
+        })
+        .collect()
+}
+
+/**
+ * @brief Per-component mean and sample standard deviation over bootstrap replicates.
+ *
+ * @param replicates Output of bootstrap_eigenvalues.
+ * @return (means, std_devs), one entry per component. The standard deviation
+ *         is 0 with fewer than two replicates.
+ */
+pub fn bootstrap_summary(replicates: &[Vec<f64>]) -> (Vec<f64>, Vec<f64>) {
+    let k = replicates.first().map_or(0, |rep| rep.len());
+    let count = replicates.len() as f64;
+    let means: Vec<f64> = (0..k)
+        .map(|comp| replicates.iter().map(|rep| rep[comp]).sum::<f64>() / count)
+        .collect();
+    let std_devs = (0..k)
+        .map(|comp| {
+            if replicates.len() < 2 {
+                return 0.0;
+            }
+            let sum_sq: f64 = replicates
+                .iter()
+                .map(|rep| (rep[comp] - means[comp]).powi(2))
+                .sum();
+            (sum_sq / (count - 1.0)).sqrt()
+        })
+        .collect();
+    (means, std_devs)
+}
+
+/**
+ * @brief Read a sample -> population table.
+ *
+ * @param path Path to a text file with one `sample population` pair per line,
+ *             separated by a tab, comma or spaces. Blank lines and lines
+ *             starting with `#` are ignored.
+ * @return Map from sample label (as written in results.csv) to population.
+ */
+pub fn read_population_labels(path: &str) -> Result<HashMap<String, String>, PcaError> {
+    let raw = crate::utils::read_maybe_gzipped(path)?;
+    let mut labels = HashMap::new();
+    for (line_no, line) in String::from_utf8_lossy(&raw).lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut fields = line
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|field| !field.is_empty());
+        match (fields.next(), fields.next(), fields.next()) {
+            (Some(sample), Some(population), None) => {
+                labels.insert(sample.to_string(), population.to_string());
+            }
+            _ => {
+                return Err(PcaError::Parse(format!(
+                    "{}:{}: expected 'sample population'",
+                    path,
+                    line_no + 1
+                )))
+            }
+        }
+    }
+    Ok(labels)
+}
+
+/**
+ * @brief Read a list of sample IDs, one per line.
+ *
+ * @param path Path to a text file; the first tab-, comma- or space-separated
+ *             field of each line is the ID. Blank lines and lines starting
+ *             with `#` are ignored.
+ * @return The IDs. An ID matches a sample by its label (the file name without
+ *         extension, or the record id for multi-FASTA input) or by its path.
+ */
+pub fn read_sample_list(path: &str) -> Result<HashSet<String>, PcaError> {
+    let raw = crate::utils::read_maybe_gzipped(path)?;
+    Ok(String::from_utf8_lossy(&raw)
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            line.split(|c: char| c == ',' || c.is_whitespace())
+                .find(|field| !field.is_empty())
+                .map(str::to_string)
+        })
+        .collect())
+}
+
+/**
+ * @brief Whether a sample passes the include and exclude lists.
+ */
+fn is_selected(
+    label: &str,
+    source: &str,
+    include: Option<&HashSet<String>>,
+    exclude: Option<&HashSet<String>>,
+) -> bool {
+    let listed = |ids: &HashSet<String>| ids.contains(label) || ids.contains(source);
+    include.is_none_or(listed) && !exclude.is_some_and(listed)
+}
+
+/**
+ * @brief Mean score of each labelled group.
+ *
+ * @param scores      Row-major scores (n x k).
+ * @param n           Number of samples.
+ * @param k           Number of components.
+ * @param populations Population of each sample; None leaves it out.
+ * @return (population, member count, k centroid coordinates) in order of
+ *         first appearance.
+ */
+pub fn population_centroids(
+    scores: &[f64],
+    n: usize,
+    k: usize,
+    populations: &[Option<String>],
+) -> Vec<(String, usize, Vec<f64>)> {
+    let mut centroids: Vec<(String, usize, Vec<f64>)> = Vec::new();
+    for row in 0..n {
+        let Some(population) = &populations[row] else {
+            continue;
+        };
+        let idx = match centroids.iter().position(|(name, _, _)| name == population) {
+            Some(idx) => idx,
+            None => {
+                centroids.push((population.clone(), 0, vec![0.0; k]));
+                centroids.len() - 1
+            }
+        };
+        let (_, count, sums) = &mut centroids[idx];
+        *count += 1;
+        for comp in 0..k {
+            sums[comp] += scores[row * k + comp];
+        }
+    }
+    for (_, count, sums) in centroids.iter_mut() {
+        for sum in sums.iter_mut() {
+            *sum /= *count as f64;
+        }
+    }
+    centroids
+}
+
+/**
+ * @struct PcaOutput
+ * @brief In-memory result of the pipeline: fitted components plus sample scores.
+ */
+pub struct PcaOutput {
+    /// Eigenvalues (all of them) and the first num_components eigenvectors.
+    pub result: PCAResult,
+    /// Projected scores, row-major: num_samples x result.num_components.
+    pub scores: Vec<f64>,
+    /// Number of individuals (rows of `scores`).
+    pub num_samples: usize,
+    /// Genome positions of the columns that entered PCA (invariant ones removed).
+    pub positions: Vec<usize>,
+    /// Training mean of each column in `positions`, for project_new_sample.
+    pub means: Vec<f64>,
+    /// The n x n sample covariance of the scaled columns (see pca::gram_matrix),
+    /// when AnalysisOptions::emit_grm asked for it.
+    pub grm: Option<Vec<f64>>,
+}
+
+/**
+ * @brief Rough peak memory, in bytes, of the PCA stage for an n x d matrix.
+ *
+ * Counts the f64 buffers alive at the peak: the centered matrix and its
+ * copy (transpose or loadings) plus the decomposed matrix and its
+ * eigenvectors, i.e. 8 (2 n d + 2 d^2) for Primal and 8 (2 n d + 2 n^2) for
+ * Dual. Reading the sequences is not included.
+ */
+pub fn estimate_memory(n: usize, d: usize, algorithm: PcaAlgorithm) -> u64 {
+    let (n, d) = (n as u64, d as u64);
+    let side = match algorithm {
+        PcaAlgorithm::Primal => d,
+        PcaAlgorithm::Dual => n,
+    };
+    8u64.saturating_mul(
+        (2 * n)
+            .saturating_mul(d)
+            .saturating_add((2 * side).saturating_mul(side)),
+    )
+}
+
+/**
+ * @brief Pick the PCA algorithm for an n x d matrix under a memory budget.
+ *
+ * @param n          Number of samples.
+ * @param d          Number of columns entering PCA.
+ * @param matrix     Correlation PCA is only implemented by Primal.
+ * @param max_memory Budget in bytes; None keeps Primal.
+ * @return The cheaper algorithm whose estimate_memory fits the budget, or
+ *         InvalidInput giving both estimates when neither fits. Both give
+ *         the same components (up to sign); only memory and time differ.
+ */
+pub fn choose_algorithm(
+    n: usize,
+    d: usize,
+    matrix: MatrixKind,
+    max_memory: Option<u64>,
+) -> Result<PcaAlgorithm, PcaError> {
+    let budget = match max_memory {
+        Some(budget) => budget,
+        None => return Ok(PcaAlgorithm::Primal),
+    };
+    let primal = estimate_memory(n, d, PcaAlgorithm::Primal);
+    let dual = estimate_memory(n, d, PcaAlgorithm::Dual);
+    let dual_allowed = matrix == MatrixKind::Covariance;
+    if dual_allowed && dual < primal && dual <= budget {
+        Ok(PcaAlgorithm::Dual)
+    } else if primal <= budget {
+        Ok(PcaAlgorithm::Primal)
+    } else if dual_allowed && dual <= budget {
+        Ok(PcaAlgorithm::Dual)
+    } else {
+        Err(PcaError::InvalidInput(format!(
+            "PCA of {} samples x {} positions needs about {} bytes (primal) or {} bytes (dual{}), over the --max-memory budget of {} bytes.",
+            n,
+            d,
+            primal,
+            dual,
+            if dual_allowed { "" } else { ", covariance only" },
+            budget
+        )))
+    }
+}
+
+/**
+ * @brief Run variant calling and PCA entirely in memory (no file I/O).
+ *
+ * @param ref_data    Reference genome sequence.
+ * @param individuals One sequence per individual, each the same length as the reference.
+ * @param k           Number of principal components to keep.
+ * @return PcaOutput, or a PcaError.
+ */
+pub fn run_pca(ref_data: &[u8], individuals: &[Vec<u8>], k: usize) -> Result<PcaOutput, PcaError> {
+    run_pca_scaled(ref_data, individuals, k, Scaling::Center)
+}
+
+/**
+ * @brief Same as run_pca, with a choice of column scaling.
+ */
+pub fn run_pca_scaled(
+    ref_data: &[u8],
+    individuals: &[Vec<u8>],
+    k: usize,
+    scaling: Scaling,
+) -> Result<PcaOutput, PcaError> {
+    run_pca_reporting(
+        ref_data,
+        individuals,
+        k,
+        scaling,
+        MatrixKind::Covariance,
+        0.0,
+        false,
+    )
+}
+
+/**
+ * @brief Same as run_pca, with per-sample importance weights (e.g. to upweight
+ *        reference-panel individuals).
+ *
+ * @param ref_data    Reference genome sequence.
+ * @param individuals One sequence per individual, each the same length as the reference.
+ * @param k           Number of principal components to keep.
+ * @param weights     One non-negative weight per individual, summing to more
+ *                    than 1; None (or all ones) gives the run_pca result.
+ * @return PcaOutput with the weighted means in `means`, or a PcaError.
+ *
+ * Columns are centered on their weighted means and the covariance is
+ * sum_k w_k x_ki x_kj / (sum_k w_k - 1); every sample is still projected.
+ */
+pub fn run_pca_weighted(
+    ref_data: &[u8],
+    individuals: &[Vec<u8>],
+    k: usize,
+    weights: Option<&[f64]>,
+) -> Result<PcaOutput, PcaError> {
+    let weights = match weights {
+        Some(weights) => weights,
+        None => return run_pca(ref_data, individuals, k),
+    };
+    let n = individuals.len();
+    let d = ref_data.len();
+    if weights.len() != n {
+        return Err(PcaError::InvalidInput(format!(
+            "Expected one weight per individual ({}), found {}.",
+            n,
+            weights.len()
+        )));
+    }
+    if weights.iter().any(|w| !w.is_finite() || *w < 0.0) {
+        return Err(PcaError::InvalidInput(
+            "Sample weights must be finite and non-negative.".to_string(),
+        ));
+    }
+    if weights.iter().sum::<f64>() <= 1.0 {
+        return Err(PcaError::InvalidInput(
+            "Sample weights must sum to more than 1.".to_string(),
+        ));
+    }
+
+    let mut data_matrix: Vec<f64> = Vec::with_capacity(n * d);
+    for (i, indiv_data) in individuals.iter().enumerate() {
+        if indiv_data.len() != d {
+            return Err(PcaError::LengthMismatch {
+                file: format!("individual {}", i),
+                expected: d,
+                found: indiv_data.len(),
+            });
+        }
+        data_matrix.extend(call_variants(ref_data, indiv_data));
+    }
+    impute_column_means(&mut data_matrix, n, d);
+
+    let positions = variable_columns(&data_matrix, n, d);
+    if positions.is_empty() {
+        return Err(PcaError::InvalidInput(
+            "All positions are invariant; nothing to analyse.".to_string(),
+        ));
+    }
+    retain_columns(&mut data_matrix, n, d, &positions);
+    let d = positions.len();
+
+    let (centered, means) = center_data_weighted(&data_matrix, n, d, weights);
+    let cov = compute_covariance_matrix_weighted(&centered, n, d, weights);
+    let mut result = decompose(&cov, d);
+    result.truncate(k);
+    let scores = project_data(&centered, n, d, &result);
+
+    Ok(PcaOutput {
+        result,
+        scores,
+        num_samples: n,
+        positions,
+        means,
+        grm: None,
+    })
+}
+
+/**
+ * @brief Same as run_pca_scaled, with a choice of covariance or correlation
+ *        matrix and a minor allele frequency filter (see maf_columns; 0 turns
+ *        it off), reporting variant-calling and covariance progress on stderr
+ *        when `verbose` is set. Results do not depend on `verbose`.
+ */
+pub fn run_pca_reporting(
+    ref_data: &[u8],
+    individuals: &[Vec<u8>],
+    k: usize,
+    scaling: Scaling,
+    matrix: MatrixKind,
+    min_maf: f64,
+    verbose: bool,
+) -> Result<PcaOutput, PcaError> {
+    let options = AnalysisOptions {
+        num_components: k,
+        scaling,
+        matrix,
+        min_maf,
+        verbose,
+        ..AnalysisOptions::default()
+    };
+    run_pca_timed(ref_data, individuals, &options, &mut StageTimer::disabled())
+}
+
+/**
+ * @brief Run variant calling and PCA in memory with every knob taken from
+ *        `config` (see run_pca_timed for the fields that apply).
+ *
+ * @param ref_data    Reference genome sequence.
+ * @param individuals One sequence per individual, each the same length as the reference.
+ * @param config      Built with PcaConfig::builder() or AnalysisOptions literals.
+ * @return PcaOutput, or a PcaError.
+ */
+pub fn run_pca_with_config(
+    ref_data: &[u8],
+    individuals: &[Vec<u8>],
+    config: &PcaConfig,
+) -> Result<PcaOutput, PcaError> {
+    let mut timer = StageTimer::new(config.timing);
+    let output = run_pca_timed(ref_data, individuals, config, &mut timer)?;
+    timer.report();
+    Ok(output)
+}
+
+/**
+ * @brief Call variants for every individual into an n x d matrix, one row per
+ *        individual in input order.
+ *
+ * With the `rayon` feature, individuals are called on separate threads; the
+ * rows are still assembled in input order, so the matrix is the same either way.
+ */
+fn call_matrix(ref_data: &[u8], individuals: &[Vec<u8>], progress: &mut Progress) -> Vec<f64> {
+    let mut data_matrix: Vec<f64> = Vec::with_capacity(individuals.len() * ref_data.len());
+    #[cfg(feature = "rayon")]
+    {
+        use rayon::prelude::*;
+        let rows: Vec<Vec<f64>> = individuals
+            .par_iter()
+            .map(|indiv_data| call_variants(ref_data, indiv_data))
+            .collect();
+        for variants in rows {
+            data_matrix.extend_from_slice(&variants);
+            progress.inc(1);
+        }
+    }
+    #[cfg(not(feature = "rayon"))]
+    for indiv_data in individuals {
+        let variants = call_variants(ref_data, indiv_data);
+        data_matrix.extend_from_slice(&variants);
+        progress.inc(1);
+    }
+    progress.finish();
+    data_matrix
+}
+
+/**
+ * @brief run_pca_reporting driven by AnalysisOptions (num_components, scaling,
+ *        matrix, min_maf, missing, sample_snps, seed and verbose are used), charging each stage to `timer`:
+ *        "variant call", "filter", "center", "covariance", "eigen" and "project".
+ *        Results do not depend on the timer.
+ */
+pub fn run_pca_timed(
+    ref_data: &[u8],
+    individuals: &[Vec<u8>],
+    options: &AnalysisOptions,
+    timer: &mut StageTimer,
+) -> Result<PcaOutput, PcaError> {
+    let verbose = options.verbose;
+    let n = individuals.len();
+    let d = ref_data.len();
+    if n < 2 {
+        // Sample covariance divides by n - 1
+        return Err(PcaError::InvalidInput(format!(
+            "At least 2 individuals are required for PCA, found {}.",
+            n
+        )));
+    }
+    if d == 0 {
+        return Err(PcaError::InvalidInput(
+            "No positions left to compare.".to_string(),
+        ));
+    }
+
+    for (i, indiv_data) in individuals.iter().enumerate() {
+        if indiv_data.len() != d {
+            return Err(PcaError::LengthMismatch {
+                file: format!("individual {}", i),
+                expected: d,
+                found: indiv_data.len(),
+            });
+        }
+    }
+
+    let mut calling = Progress::new("Calling variants", n, verbose);
+    let data_matrix = call_matrix(ref_data, individuals, &mut calling);
+    timer.lap("variant call");
+
+    pca_on_calls(data_matrix, n, d, options, timer)
+}
+
+/**
+ * @brief PCA of a precomputed call or dosage matrix, with no reference and no
+ *        variant calling: the building block for genotype matrices from
+ *        read_vcf, read_plink or read_dosage_matrix.
+ *
+ * @param data n x d matrix, row-major, one row per sample; NaN marks a
+ *             missing call.
+ * @param n    Number of samples.
+ * @param d    Number of columns (SNPs).
+ * @param k    Number of principal components to keep.
+ * @return PcaOutput whose `positions` are column indices of `data`, or a PcaError.
+ *
+ * Columns are mean-imputed, invariant ones dropped and the rest centered, as
+ * for sequence input.
+ */
+pub fn run_pca_on_matrix(
+    data: &[f64],
+    n: usize,
+    d: usize,
+    k: usize,
+) -> Result<PcaOutput, PcaError> {
+    let options = AnalysisOptions {
+        num_components: k,
+        ..AnalysisOptions::default()
+    };
+    run_pca_on_matrix_with(data, n, d, &options)
+}
+
+/**
+ * @brief run_pca_on_matrix driven by AnalysisOptions; the same fields as
+ *        run_pca_timed apply. min_maf treats each column's mean as the allele
+ *        frequency, so halve dosages (0/1/2) first if filtering on them.
+ */
+pub fn run_pca_on_matrix_with(
+    data: &[f64],
+    n: usize,
+    d: usize,
+    options: &AnalysisOptions,
+) -> Result<PcaOutput, PcaError> {
+    if n < 2 {
+        return Err(PcaError::InvalidInput(format!(
+            "At least 2 individuals are required for PCA, found {}.",
+            n
+        )));
+    }
+    if d == 0 || data.len() != n * d {
+        return Err(PcaError::InvalidInput(format!(
+            "Expected a {} x {} matrix ({} values), found {} values.",
+            n,
+            d,
+            n * d,
+            data.len()
+        )));
+    }
+    let mut timer = StageTimer::new(options.timing);
+    let output = pca_on_calls(data.to_vec(), n, d, options, &mut timer)?;
+    timer.report();
+    Ok(output)
+}
+
+/**
+ * @brief Shared tail of run_pca_timed and run_pca_on_matrix_with: missing-call
+ *        policy, filters, scaling, decomposition and projection of an n x d
+ *        call matrix, consumed to reuse its buffer.
+ */
+fn pca_on_calls(
+    mut data_matrix: Vec<f64>,
+    n: usize,
+    d: usize,
+    options: &AnalysisOptions,
+    timer: &mut StageTimer,
+) -> Result<PcaOutput, PcaError> {
+    let AnalysisOptions {
+        num_components: k,
+        scaling,
+        matrix,
+        min_maf,
+        missing,
+        sample_snps,
+        seed,
+        ..
+    } = *options;
+
+    // Resolve missing calls (N bases) before any column statistics are taken;
+    // imputing the mean leaves allele frequencies unchanged
+    let called = apply_missing_policy(&mut data_matrix, n, d, missing)?;
+    let d = called.len();
+
+    // Drop monomorphic positions; `columns` index the called columns
+    let mut columns = variable_columns(&data_matrix, n, d);
+    if columns.is_empty() {
+        return Err(PcaError::InvalidInput(
+            "All positions are invariant; nothing to analyse.".to_string(),
+        ));
+    }
+    if min_maf > 0.0 {
+        let ploidy = if options.haplotype_pairs { 2 } else { 1 };
+        let common = maf_columns_with(&data_matrix, n, d, min_maf, ploidy);
+        columns.retain(|col| common.binary_search(col).is_ok());
+        if columns.is_empty() {
+            return Err(PcaError::InvalidInput(format!(
+                "No position has a minor allele frequency of at least {}.",
+                min_maf
+            )));
+        }
+    }
+    if let Some(m) = sample_snps {
+        columns = reservoir_sample(&columns, m, seed);
+    }
+    retain_columns(&mut data_matrix, n, d, &columns);
+    // `positions` maps the remaining columns back to the genome
+    let positions: Vec<usize> = columns.iter().map(|&col| called[col]).collect();
+    let d = positions.len();
+    timer.lap("filter");
+
+    // Perform PCA (n = number of individuals, d = number of variable positions)
+    // The raw calls are not needed after scaling, so centering reuses their buffer
+    let (centered, means) = match scaling {
+        Scaling::Center => {
+            let means = center_data_in_place(&mut data_matrix, n, d);
+            (data_matrix, means)
+        }
+        Scaling::Standardize => {
+            let means = column_means(&data_matrix, n, d);
+            (standardize_data(&data_matrix, n, d), means)
+        }
+        // Zero means keep downstream projection (and saved models) un-centered too
+        Scaling::Raw => (data_matrix, vec![0.0; d]),
+    };
+    timer.lap("center");
+    let algorithm = choose_algorithm(n, d, matrix, options.max_memory)?;
+    let grm = options.emit_grm.then(|| gram_matrix(&centered, n, d));
+    let mut result = match algorithm {
+        PcaAlgorithm::Primal => {
+            #[cfg(feature = "nalgebra")]
+            let mut cov = crate::pca::compute_covariance_matrix_nalgebra(&centered, n, d);
+            #[cfg(all(feature = "rayon", not(feature = "nalgebra")))]
+            let mut cov = crate::pca::compute_covariance_matrix_parallel(&centered, n, d);
+            #[cfg(not(any(feature = "rayon", feature = "nalgebra")))]
+            let mut cov = crate::pca::compute_covariance_matrix_blocked_reporting(
+                &centered,
+                n,
+                d,
+                crate::pca::COVARIANCE_BLOCK,
+                &mut Progress::new("Covariance rows", d, options.verbose),
+            );
+            if matrix == MatrixKind::Correlation {
+                covariance_to_correlation(&mut cov, d);
+            }
+            timer.lap("covariance");
+            decompose(&cov, d)
+        }
+        // Every Gram eigenvalue is kept so variance ratios match the primal path
+        PcaAlgorithm::Dual => match &grm {
+            Some(gram) => dual_pca_from_gram(&centered, gram, n, d, n)?,
+            None => dual_pca(&centered, n, d, n)?,
+        },
+    };
+    result.truncate(k);
+    timer.lap("eigen");
+    let scores = project_data(&centered, n, d, &result);
+    timer.lap("project");
+
+    Ok(PcaOutput {
+        result,
+        scores,
+        num_samples: n,
+        positions,
+        means,
+        grm,
+    })
+}
+
+/**
+ * @brief Streaming variant of run_pca_scaled that never builds the n x d matrix.
+ *
+ * @param ref_data Reference genome sequence.
+ * @param n        Number of individuals.
+ * @param load     Returns the i-th individual's sequence; called twice per
+ *                 individual (once to accumulate, once to project).
+ * @param k        Number of principal components to keep.
+ * @param scaling  Centering, z-score standardization or raw columns.
+ * @param matrix   Eigendecompose the covariance or the correlation matrix.
+ * @param min_maf  Drop positions whose minor allele frequency is below this (0 keeps all).
+ * @return PcaOutput matching the in-memory path, or a PcaError.
+ *
+ * Memory is O(d^2) for the co-moments instead of O(n * d). Missing calls are
+ * mean-imputed (see run_pca_streaming_with for the other policies).
+ */
+pub fn run_pca_streaming<F>(
+    ref_data: &[u8],
+    n: usize,
+    load: F,
+    k: usize,
+    scaling: Scaling,
+    matrix: MatrixKind,
+    min_maf: f64,
+) -> Result<PcaOutput, PcaError>
+where
+    F: FnMut(usize) -> Result<Vec<u8>, PcaError>,
+{
+    let options = AnalysisOptions {
+        num_components: k,
+        scaling,
+        matrix,
+        min_maf,
+        ..AnalysisOptions::default()
+    };
+    run_pca_streaming_with(ref_data, n, load, &options)
+}
+
+/**
+ * @brief run_pca_streaming driven by AnalysisOptions (num_components, scaling,
+ *        matrix, min_maf and missing are used).
+ *
+ * `load` is called twice per individual, or three times when some calls are
+ * missing under MissingPolicy::MeanImpute: the means to impute with are only
+ * known after the first pass, so the co-moments are accumulated again.
+ */
+pub fn run_pca_streaming_with<F>(
+    ref_data: &[u8],
+    n: usize,
+    mut load: F,
+    options: &AnalysisOptions,
+) -> Result<PcaOutput, PcaError>
+where
+    F: FnMut(usize) -> Result<Vec<u8>, PcaError>,
+{
+    let AnalysisOptions {
+        num_components: k,
+        scaling,
+        matrix,
+        min_maf,
+        missing,
+        ..
+    } = *options;
+    let d = ref_data.len();
+    if n < 2 {
+        // Sample covariance divides by n - 1
+        return Err(PcaError::InvalidInput(format!(
+            "At least 2 individuals are required for PCA, found {}.",
+            n
+        )));
+    }
+    if d == 0 {
+        return Err(PcaError::InvalidInput(
+            "No positions left to compare.".to_string(),
+        ));
+    }
+    // The accumulator and its decomposition are d x d whatever n is
+    let needed = estimate_memory(0, d, PcaAlgorithm::Primal);
+    if let Some(budget) = options.max_memory.filter(|&budget| needed > budget) {
+        return Err(PcaError::InvalidInput(format!(
+            "Streaming PCA of {} positions needs about {} bytes, over the --max-memory budget of {} bytes.",
+            d, needed, budget
+        )));
+    }
+
+    let variants_for = |i: usize, indiv_data: Vec<u8>| -> Result<Vec<f64>, PcaError> {
+        if indiv_data.len() != d {
+            return Err(PcaError::LengthMismatch {
+                file: format!("individual {}", i),
+                expected: d,
+                found: indiv_data.len(),
+            });
+        }
+        Ok(call_variants(ref_data, &indiv_data))
+    };
+    // Missing calls take their column's fill value (0 until means are known)
+    let fill_missing = |variants: &mut [f64], fill: &[f64]| {
+        for (v, &f) in variants.iter_mut().zip(fill) {
+            if v.is_nan() {
+                *v = f;
+            }
+        }
+    };
+
+    // Pass 1: running means and co-moments, counting missing calls per column.
+    // A zero-filled column only disturbs its own co-moments, so columns that
+    // end up dropped leave the others exact.
+    let mut fill = vec![0.0; d];
+    let mut missing_count = vec![0usize; d];
+    let mut observed_sum = vec![0.0; d];
+    let mut acc = CovarianceAccumulator::new(d);
+    for i in 0..n {
+        let mut variants = variants_for(i, load(i)?)?;
+        for (col, &v) in variants.iter().enumerate() {
+            if v.is_nan() {
+                missing_count[col] += 1;
+            } else {
+                observed_sum[col] += v;
+            }
+        }
+        fill_missing(&mut variants, &fill);
+        acc.add_sample(&variants);
+    }
+
+    let partial: Vec<usize> = (0..d)
+        .filter(|&col| missing_count[col] > 0 && missing_count[col] < n)
+        .collect();
+    match missing {
+        MissingPolicy::Error => {
+            if let Some(&first) = partial.first() {
+                return Err(PcaError::InvalidInput(format!(
+                    "{} position(s) have missing calls (first at position {}); choose mean imputation or dropping them.",
+                    partial.len(),
+                    first
+                )));
+            }
+        }
+        MissingPolicy::MeanImpute if !partial.is_empty() => {
+            // Pass 1b: accumulate again with the observed means filled in
+            for &col in &partial {
+                fill[col] = observed_sum[col] / (n - missing_count[col]) as f64;
+            }
+            acc = CovarianceAccumulator::new(d);
+            for i in 0..n {
+                let mut variants = variants_for(i, load(i)?)?;
+                fill_missing(&mut variants, &fill);
+                acc.add_sample(&variants);
+            }
+        }
+        MissingPolicy::MeanImpute | MissingPolicy::DropSnp => {}
+    }
+
+    let full_cov = acc.covariance();
+    let all_means = acc.means();
+    // Columns missing everywhere are constant 0 here, so they fail the variance test
+    let mut positions: Vec<usize> = (0..d)
+        .filter(|&col| full_cov[col * d + col] > 0.0)
+        .filter(|&col| missing != MissingPolicy::DropSnp || missing_count[col] == 0)
+        .collect();
+    if positions.is_empty() {
+        return Err(PcaError::InvalidInput(
+            "All positions are invariant; nothing to analyse.".to_string(),
+        ));
+    }
+    if min_maf > 0.0 {
+        // Imputing the mean leaves it unchanged, so these are the allele frequencies
+        positions.retain(|&col| all_means[col].min(1.0 - all_means[col]) >= min_maf);
+        if positions.is_empty() {
+            return Err(PcaError::InvalidInput(format!(
+                "No position has a minor allele frequency of at least {}.",
+                min_maf
+            )));
+        }
+    }
+    let reduced_d = positions.len();
+
+    // Per-column divisor: 1 for centering, the standard deviation for z-scores
+    let scale: Vec<f64> = positions
+        .iter()
+        .map(|&col| match scaling {
+            Scaling::Center | Scaling::Raw => 1.0,
+            Scaling::Standardize => full_cov[col * d + col].sqrt(),
+        })
+        .collect();
+
+    // Raw mode puts the mean back: sum x_i x_j / (n - 1) = cov_ij + n / (n - 1) m_i m_j
+    let mean_term = if scaling == Scaling::Raw {
+        n as f64 / (n as f64 - 1.0)
+    } else {
+        0.0
+    };
+    let mut cov = vec![0.0; reduced_d * reduced_d];
+    for (i, &pi) in positions.iter().enumerate() {
+        for (j, &pj) in positions.iter().enumerate() {
+            let moment = full_cov[pi * d + pj] + mean_term * all_means[pi] * all_means[pj];
+            cov[i * reduced_d + j] = moment / (scale[i] * scale[j]);
+        }
+    }
+    drop(full_cov);
+    if matrix == MatrixKind::Correlation {
+        covariance_to_correlation(&mut cov, reduced_d);
+    }
+
+    let mut result = decompose(&cov, reduced_d);
+    result.truncate(k);
+
+    // Pass 2: project each individual as it is re-read
+    let zeros = vec![0.0; d];
+    let means = if scaling == Scaling::Raw {
+        &zeros[..]
+    } else {
+        all_means
+    };
+    let num_components = result.num_components;
+    let mut scores = Vec::with_capacity(n * num_components);
+    let mut centered = vec![0.0; reduced_d];
+    for i in 0..n {
+        let mut variants = variants_for(i, load(i)?)?;
+        fill_missing(&mut variants, &fill);
+        for (j, &col) in positions.iter().enumerate() {
+            centered[j] = (variants[col] - means[col]) / scale[j];
+        }
+        scores.extend_from_slice(&project_data(&centered, 1, reduced_d, &result));
+    }
+    let means = positions.iter().map(|&col| means[col]).collect();
+
+    Ok(PcaOutput {
+        result,
+        scores,
+        num_samples: n,
+        positions,
+        means,
+        grm: None,
+    })
+}
+
+/**
+ * @struct MismatchSummary
+ * @brief Per-individual count of bases that differ from the reference.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MismatchSummary {
+    /// Number of positions called as a variant (missing calls excluded).
+    pub count: usize,
+    /// The first few differing positions, in ascending order.
+    pub first_positions: Vec<usize>,
+}
+
+/**
+ * @brief Diagnostic report of where each individual differs from the reference.
+ *
+ * @param ref_data      Reference genome sequence.
+ * @param individuals   One sequence per individual.
+ * @param max_positions How many leading differing positions to record per individual.
+ * @return One MismatchSummary per individual, in input order.
+ *
+ * Positions are those call_variants scores as non-zero; `N` bases are not
+ * counted. Only the common prefix of the two sequences is compared, so this
+ * never aborts. A count near the genome length usually means a frame-shifted
+ * or misaligned input.
+ */
+pub fn mismatch_report(
+    ref_data: &[u8],
+    individuals: &[Vec<u8>],
+    max_positions: usize,
+) -> Vec<MismatchSummary> {
+    individuals
+        .iter()
+        .map(|indiv_data| {
+            let d = ref_data.len().min(indiv_data.len());
+            let variants = call_variants(&ref_data[..d], &indiv_data[..d]);
+            let mut summary = MismatchSummary {
+                count: 0,
+                first_positions: Vec::new(),
+            };
+            for (pos, &v) in variants.iter().enumerate() {
+                if v.is_nan() || v == 0.0 {
+                    continue;
+                }
+                summary.count += 1;
+                if summary.first_positions.len() < max_positions {
+                    summary.first_positions.push(pos);
+                }
+            }
+            summary
+        })
+        .collect()
+}
+
+/**
+ * @brief Write qc.txt: per-sample mismatch count and first differing positions.
+ */
+fn write_qc(
+    path: &Path,
+    labels: &[String],
+    report: &[MismatchSummary],
+    d: usize,
+    fmt: FloatFormat,
+) -> std::io::Result<()> {
+    write_atomically(path, |f_qc| {
+        writeln!(f_qc, "sample\tmismatches\tfraction\tfirst_positions")?;
+        for (label, summary) in labels.iter().zip(report.iter()) {
+            let positions: Vec<String> = summary
+                .first_positions
+                .iter()
+                .map(|pos| pos.to_string())
+                .collect();
+            writeln!(
+                f_qc,
+                "{}\t{}\t{}\t{}",
+                label,
+                summary.count,
+                fmt.format(summary.count as f64 / d as f64),
+                positions.join(",")
+            )?;
+        }
+        Ok(())
+    })
+}
+
+/**
+ * @brief Write results.csv: one row per sample, labelled, with k PC scores and,
+ *        when given, trailing population (empty if unknown) and k-means cluster
+ *        columns.
+ */
+fn write_scores(
+    path: &Path,
+    labels: &[String],
+    scores: &[f64],
+    k: usize,
+    populations: Option<&[Option<String>]>,
+    clusters: Option<&[usize]>,
+    fmt: FloatFormat,
+) -> std::io::Result<()> {
+    let n = labels.len();
+    write_atomically(path, |f_scores| {
+        let mut header: Vec<String> = (1..=k).map(|comp| format!("PC{}", comp)).collect();
+        if populations.is_some() {
+            header.push("population".to_string());
+        }
+        if clusters.is_some() {
+            header.push("cluster".to_string());
+        }
+        writeln!(f_scores, "sample,{}", header.join(","))?;
+        for row in 0..n {
+            write!(f_scores, "{},", labels[row])?;
+            for comp in 0..k {
+                let val = fmt.format(scores[row * k + comp]);
+                if comp < (k - 1) {
+                    write!(f_scores, "{},", val)?;
+                } else {
+                    write!(f_scores, "{}", val)?;
+                }
+            }
+            if let Some(populations) = populations {
+                write!(f_scores, ",{}", populations[row].as_deref().unwrap_or(""))?;
+            }
+            match clusters {
+                Some(clusters) => writeln!(f_scores, ",{}", clusters[row])?,
+                None => writeln!(f_scores)?,
+            }
+        }
+        Ok(())
+    })
+}
+
+/**
+ * @brief Write eigenvalues.csv with explained and cumulative variance ratios.
+ */
+fn write_eigenvalues(path: &Path, pca_res: &PCAResult, fmt: FloatFormat) -> std::io::Result<()> {
+    write_atomically(path, |f_evals| {
+        let ratios = pca_res.explained_variance_ratio();
+        let cumulative = pca_res.cumulative_variance();
+        writeln!(
+            f_evals,
+            "component,eigenvalue,explained_variance_ratio,cumulative_variance"
+        )?;
+        for (i, &val) in pca_res.eigenvalues.iter().enumerate() {
+            writeln!(
+                f_evals,
+                "{},{},{},{}",
+                i + 1,
+                fmt.format(val),
+                fmt.format(ratios[i]),
+                fmt.format(cumulative[i])
+            )?;
+        }
+        Ok(())
+    })
+}
+
+/**
+ * @brief Write scree.csv: per-component eigenvalue, variance proportion and
+ *        cumulative proportion, for choosing how many components to keep.
+ */
+fn write_scree(path: &Path, pca_res: &PCAResult, fmt: FloatFormat) -> std::io::Result<()> {
+    write_atomically(path, |f_scree| {
+        let proportions = pca_res.explained_variance_ratio();
+        let cumulative = pca_res.cumulative_variance();
+        writeln!(f_scree, "component,eigenvalue,proportion,cumulative")?;
+        for (i, &val) in pca_res.eigenvalues.iter().enumerate() {
+            writeln!(
+                f_scree,
+                "{},{},{},{}",
+                i + 1,
+                fmt.format(val),
+                fmt.format(proportions[i]),
+                fmt.format(cumulative[i])
+            )?;
+        }
+        Ok(())
+    })
+}
+
+/**
+ * @brief Write an n x n sample matrix with sample labels: distances.csv, and
+ *        grm.csv with the same layout.
+ */
+fn write_distances(
+    path: &Path,
+    labels: &[String],
+    distances: &[f64],
+    fmt: FloatFormat,
+) -> std::io::Result<()> {
+    write_atomically(path, |f_dist| {
+        let n = labels.len();
+        writeln!(f_dist, "sample,{}", labels.join(","))?;
+        for (row, label) in labels.iter().enumerate() {
+            let values: Vec<String> = distances[row * n..(row + 1) * n]
+                .iter()
+                .map(|&dist| fmt.format(dist))
+                .collect();
+            writeln!(f_dist, "{},{}", label, values.join(","))?;
+        }
+        Ok(())
+    })
+}
+
+/**
+ * @brief Write sfs.csv: `alt_count,sites` for every count from 0 up.
+ */
+fn write_sfs(path: &Path, spectrum: &[usize]) -> std::io::Result<()> {
+    write_atomically(path, |f_sfs| {
+        writeln!(f_sfs, "alt_count,sites")?;
+        for (count, sites) in spectrum.iter().enumerate() {
+            writeln!(f_sfs, "{},{}", count, sites)?;
+        }
+        Ok(())
+    })
+}
+
+/**
+ * @brief Write duplicates.txt: one line per suspected duplicate pair with the
+ *        fraction of co-called positions at which they differ.
+ */
+fn write_duplicates(
+    path: &Path,
+    labels: &[String],
+    duplicates: &[(usize, usize, f64)],
+    fmt: FloatFormat,
+) -> std::io::Result<()> {
+    write_atomically(path, |f_dup| {
+        writeln!(f_dup, "sample1\tsample2\tdistance")?;
+        for &(i, j, distance) in duplicates {
+            writeln!(
+                f_dup,
+                "{}\t{}\t{}",
+                labels[i],
+                labels[j],
+                fmt.format(distance)
+            )?;
+        }
+        Ok(())
+    })
+}
+
+/**
+ * @brief Write maf.csv: alternate and minor allele frequency of every position.
+ */
+fn write_frequencies(path: &Path, frequencies: &[f64], fmt: FloatFormat) -> std::io::Result<()> {
+    write_atomically(path, |f_maf| {
+        writeln!(f_maf, "position,frequency,maf")?;
+        for (pos, &freq) in frequencies.iter().enumerate() {
+            writeln!(
+                f_maf,
+                "{},{},{}",
+                pos,
+                fmt.format(freq),
+                fmt.format(freq.min(1.0 - freq))
+            )?;
+        }
+        Ok(())
+    })
+}
+
+/**
+ * @brief Write diagnostics.csv: one `statistic,value` row each; NA when undefined.
+ */
+fn write_diagnostics(
+    path: &Path,
+    num_positions: usize,
+    bartlett: Option<&BartlettTest>,
+    kmo_value: Option<f64>,
+    fmt: FloatFormat,
+) -> std::io::Result<()> {
+    write_atomically(path, |f_diag| {
+        let value = |v: Option<f64>| v.map_or("NA".to_string(), |v| fmt.format(v));
+        writeln!(f_diag, "statistic,value")?;
+        writeln!(f_diag, "variable_positions,{}", num_positions)?;
+        writeln!(
+            f_diag,
+            "bartlett_chi_square,{}",
+            value(bartlett.map(|b| b.chi_square))
+        )?;
+        writeln!(f_diag, "bartlett_df,{}", value(bartlett.map(|b| b.df)))?;
+        writeln!(
+            f_diag,
+            "bartlett_p_value,{}",
+            value(bartlett.map(|b| b.p_value))
+        )?;
+        writeln!(f_diag, "kmo,{}", value(kmo_value))?;
+        Ok(())
+    })
+}
+
+/**
+ * @brief Write bootstrap.csv: per-component mean and standard deviation of the
+ *        bootstrapped eigenvalues.
+ */
+fn write_bootstrap(
+    path: &Path,
+    means: &[f64],
+    std_devs: &[f64],
+    fmt: FloatFormat,
+) -> std::io::Result<()> {
+    write_atomically(path, |f_boot| {
+        writeln!(f_boot, "component,mean_eigenvalue,stddev")?;
+        for (i, (mean, sd)) in means.iter().zip(std_devs.iter()).enumerate() {
+            writeln!(
+                f_boot,
+                "{},{},{}",
+                i + 1,
+                fmt.format(*mean),
+                fmt.format(*sd)
+            )?;
+        }
+        Ok(())
+    })
+}
+
+/**
+ * @brief Write loadings.csv: one row per retained SNP position, one column per component.
+ */
+fn write_loadings(
+    path: &Path,
+    positions: &[usize],
+    pca_res: &PCAResult,
+    fmt: FloatFormat,
+) -> std::io::Result<()> {
+    write_atomically(path, |f_loadings| {
+        let k = pca_res.num_components;
+        let d = pca_res.dimension;
+        let header: Vec<String> = (1..=k).map(|comp| format!("PC{}", comp)).collect();
+        writeln!(f_loadings, "position,{}", header.join(","))?;
+        for (col, &pos) in positions.iter().enumerate() {
+            let row: Vec<String> = (0..k)
+                .map(|comp| fmt.format(pca_res.eigenvectors[comp * d + col]))
+                .collect();
+            writeln!(f_loadings, "{},{}", pos, row.join(","))?;
+        }
+        Ok(())
+    })
+}
+
+/**
+ * @brief Write centroids.csv: one row per population with its size and mean scores.
+ */
+fn write_centroids(
+    path: &Path,
+    centroids: &[(String, usize, Vec<f64>)],
+    k: usize,
+    fmt: FloatFormat,
+) -> std::io::Result<()> {
+    write_atomically(path, |f_centroids| {
+        let header: Vec<String> = (1..=k).map(|comp| format!("PC{}", comp)).collect();
+        writeln!(f_centroids, "population,count,{}", header.join(","))?;
+        for (population, count, coords) in centroids {
+            let row: Vec<String> = coords.iter().map(|&val| fmt.format(val)).collect();
+            writeln!(f_centroids, "{},{},{}", population, count, row.join(","))?;
+        }
+        Ok(())
+    })
+}
+
+/**
+ * @brief Write sampled_snps.txt: the 0-based genome position of each SNP a
+ *        --sample-snps run used, one per line.
+ */
+fn write_sampled_snps(path: &Path, positions: &[usize]) -> std::io::Result<()> {
+    write_atomically(path, |f_snps| {
+        for pos in positions {
+            writeln!(f_snps, "{}", pos)?;
+        }
+        Ok(())
+    })
+}
+
+/**
+ * @brief Write windows.csv: window,sample,PC1..PCk, one row per sample and
+ *        window. Windows with fewer components leave the extra columns empty.
+ */
+fn write_windows(
+    path: &Path,
+    labels: &[String],
+    outputs: &[(String, PcaOutput)],
+    fmt: FloatFormat,
+) -> std::io::Result<()> {
+    let k = outputs
+        .iter()
+        .map(|(_, output)| output.result.num_components)
+        .max()
+        .unwrap_or(0);
+    write_atomically(path, |file| {
+        write!(file, "window,sample")?;
+        for pc in 1..=k {
+            write!(file, ",PC{}", pc)?;
+        }
+        writeln!(file)?;
+        for (name, output) in outputs {
+            let num_components = output.result.num_components;
+            for (i, label) in labels.iter().enumerate().take(output.num_samples) {
+                write!(file, "{},{}", name, label)?;
+                for pc in 0..k {
+                    if pc < num_components {
+                        let value = output.scores[i * num_components + pc];
+                        write!(file, ",{}", fmt.format(value))?;
+                    } else {
+                        write!(file, ",")?;
+                    }
+                }
+                writeln!(file)?;
+            }
+        }
+        Ok(())
+    })
+}
+
+/**
+ * @brief Write samples.txt mapping each results.csv row to its label and input file.
+ */
+fn write_samples(path: &Path, labels: &[String], sources: &[String]) -> std::io::Result<()> {
+    write_atomically(path, |f_samples| {
+        for (row, (label, file)) in labels.iter().zip(sources.iter()).enumerate() {
+            writeln!(f_samples, "{}\t{}\t{}", row, label, file)?;
+        }
+        Ok(())
+    })
+}
+
+/**
+ * @struct Batch
+ * @brief Individuals read for one run, after the length policy was applied.
+ */
+#[derive(Debug, Clone)]
+pub struct Batch {
+    /// Sample labels (file name, or record id for multi-FASTA inputs).
+    pub labels: Vec<String>,
+    /// File each sample was read from.
+    pub sources: Vec<String>,
+    /// Sequences, all truncated to `length`.
+    pub genomes: Vec<Vec<u8>>,
+    /// Number of reference positions compared (shorter than the reference
+    /// only under LengthPolicy::Truncate).
+    pub length: usize,
+}
+
+/**
+ * @brief The include/exclude lists of `options` as a predicate on
+ *        (sample label, source file).
+ */
+fn sample_filter(options: &AnalysisOptions) -> Result<impl Fn(&str, &str) -> bool, PcaError> {
+    let include = options
+        .include_file
+        .as_deref()
+        .map(read_sample_list)
+        .transpose()?;
+    let exclude = options
+        .exclude_file
+        .as_deref()
+        .map(read_sample_list)
+        .transpose()?;
+    let haplotype_pairs = options.haplotype_pairs;
+    // Haplotype files are listed under their sample id, not their file stem
+    Ok(move |label: &str, source: &str| {
+        let sample = match haplotype_of(source) {
+            Some((sample, _)) if haplotype_pairs => sample,
+            _ => label.to_string(),
+        };
+        is_selected(&sample, source, include.as_ref(), exclude.as_ref())
+    })
+}
+
+/**
+ * @brief Read a batch of individuals and reconcile their lengths with the reference.
+ *
+ * @param reference         The loaded reference.
+ * @param individuals_files Paths of the batch's genomes.
+ * @param options           Length policy and input modes (multi_fasta, min_qual, verbose).
+ * @return The kept samples, or the first PcaError (LengthMismatch under Strict).
+ */
+pub fn load_batch(
+    reference: &Reference,
+    individuals_files: &[String],
+    options: &AnalysisOptions,
+) -> Result<Batch, PcaError> {
+    let selected = sample_filter(options)?;
+
+    // One sample per file: filter by file name before anything is read, so an
+    // excluded file cannot fail the run. Multi-FASTA records are filtered below.
+    let filtered: Vec<String>;
+    let individuals_files = if options.multi_fasta {
+        individuals_files
+    } else {
+        filtered = individuals_files
+            .iter()
+            .filter(|path| selected(&sample_name(path), path))
+            .cloned()
+            .collect();
+        &filtered[..]
+    };
+    if options.haplotype_pairs {
+        // Unpaired files fail here, before any sequence is read
+        group_haplotype_files(individuals_files)?;
+    }
+
+    // Fail fast, before reading any individual, when Strict would abort anyway
+    if options.length_policy == LengthPolicy::Strict && !options.multi_fasta {
+        check_individual_lengths_with(
+            individuals_files,
+            reference.len(),
+            options.invalid_bases,
+            options.length_slack,
+        )?;
+    }
+    read_batch(reference, individuals_files, options, &selected)
+}
+
+/**
+ * @brief The reading half of load_batch: parse the files, keep the samples
+ *        `selected` accepts and apply the length policy.
+ */
+fn read_batch(
+    reference: &Reference,
+    individuals_files: &[String],
+    options: &AnalysisOptions,
+    selected: &dyn Fn(&str, &str) -> bool,
+) -> Result<Batch, PcaError> {
+    let mut d = reference.len();
+    let individuals = load_individuals(
+        individuals_files,
+        options.multi_fasta,
+        options.min_qual,
+        options.invalid_bases,
+        &mut Progress::new(
+            "Reading individuals",
+            individuals_files.len(),
+            options.verbose,
+        ),
+    )?;
+    let mut genomes: Vec<Vec<u8>> = Vec::with_capacity(individuals.len());
+    let mut dropped: Vec<String> = Vec::new();
+    let mut kept: Vec<String> = Vec::new();
+    let mut kept_sources: Vec<String> = Vec::new();
+    let individuals = individuals
+        .into_iter()
+        .filter(|(label, source, _)| selected(label, source));
+    for (i, (label, source, mut indiv_data)) in individuals.enumerate() {
+        // Name a sample by its file, plus the record id when a file holds several
+        let indiv = if options.multi_fasta {
+            format!("{} ({})", source, label)
+        } else {
+            source.clone()
+        };
+        let extra = indiv_data.len().saturating_sub(reference.len());
+        if extra > 0 && extra <= options.length_slack {
+            eprintln!(
+                "Warning: {} is {} base(s) longer than the reference; trimming the end",
+                indiv, extra
+            );
+            indiv_data.truncate(reference.len());
+        }
+        if indiv_data.len() != reference.len() {
+            match options.length_policy {
+                LengthPolicy::Strict => {
+                    return Err(PcaError::LengthMismatch {
+                        file: indiv,
+                        expected: reference.len(),
+                        found: indiv_data.len(),
+                    });
+                }
+                LengthPolicy::Truncate => {
+                    eprintln!(
+                        "Warning: individual {} length {} != reference length {}, truncating",
+                        i,
+                        indiv_data.len(),
+                        reference.len()
+                    );
+                    d = d.min(indiv_data.len());
+                }
+                LengthPolicy::Skip => {
+                    eprintln!(
+                        "Warning: skipping individual {} ({}): length {} != reference length {}",
+                        i,
+                        indiv,
+                        indiv_data.len(),
+                        reference.len()
+                    );
+                    dropped.push(indiv);
+                    continue;
+                }
+            }
+        }
+        genomes.push(indiv_data);
+        kept.push(label);
+        kept_sources.push(source);
+    }
+
+    if options.include_file.is_some() || options.exclude_file.is_some() {
+        println!(
+            "{} individual(s) selected by the include/exclude lists",
+            kept.len() + dropped.len()
+        );
+    }
+    if !dropped.is_empty() {
+        println!(
+            "Dropped {} individual(s) due to length mismatch: {}",
+            dropped.len(),
+            dropped.join(", ")
+        );
+    }
+    if d == 0 {
+        return Err(PcaError::InvalidInput(
+            "No positions left to compare after truncation.".to_string(),
+        ));
+    }
+
+    // Truncate policy: compare only the common prefix
+    for genome in genomes.iter_mut() {
+        genome.truncate(d);
+    }
+    Ok(Batch {
+        labels: kept,
+        sources: kept_sources,
+        genomes,
+        length: d,
+    })
+}
 
 /**
  * @brief Orchestrates the pipeline for multiple individuals.
  *
  * @param ref_file        Path to the reference genome.
  * @param individuals_files A slice of paths to individuals' genomes.
+ * @param output_dir      Directory that receives the CSV outputs; created if missing.
+ * @param options         Length policy, scaling, component count and input modes.
+ * @return Ok(()) once all outputs are written, or the first PcaError.
  */
-pub fn perform_full_analysis(ref_file: &str, individuals_files: &[String]) {
-    let ref_length = get_file_length(ref_file);
-    if ref_length == 0 {
-        eprintln!(
-            "Reference genome size is 0 or error reading file: {}",
-            ref_file
+pub fn perform_full_analysis(
+    ref_file: &str,
+    individuals_files: &[String],
+    output_dir: &Path,
+    options: &AnalysisOptions,
+) -> Result<(), PcaError> {
+    let mut timer = StageTimer::new(options.timing);
+    let reference = Reference::load_with(ref_file, options.invalid_bases)?;
+    println!("Reference genome length: {}", reference.len());
+    analyze_timed(
+        &reference,
+        individuals_files,
+        output_dir,
+        options,
+        &mut timer,
+    )
+}
+
+/**
+ * @brief perform_full_analysis against an already loaded reference, so several
+ *        batches can share one read of it.
+ *
+ * @param reference       The reference genome.
+ * @param individuals_files A slice of paths to this batch's genomes.
+ * @param output_dir      Directory that receives the CSV outputs; created if missing.
+ * @param options         Length policy, scaling, component count and input modes.
+ * @return Ok(()) once all outputs are written, or the first PcaError.
+ */
+pub fn analyze_with_reference(
+    reference: &Reference,
+    individuals_files: &[String],
+    output_dir: &Path,
+    options: &AnalysisOptions,
+) -> Result<(), PcaError> {
+    let mut timer = StageTimer::new(options.timing);
+    analyze_timed(
+        reference,
+        individuals_files,
+        output_dir,
+        options,
+        &mut timer,
+    )
+}
+
+/**
+ * @brief load_batch one input file at a time, storing each file's samples in
+ *        a checkpoint as soon as it is read.
+ *
+ * @param reference         The reference every sequence is compared with.
+ * @param individuals_files Paths to individuals' genomes.
+ * @param dir               Checkpoint directory (see checkpoint::Checkpoint).
+ * @param options           As for load_batch; `resume` keeps the samples of
+ *                          files an earlier run finished and skips reading them.
+ * @return The Batch of every stored sample whose file is in
+ *         `individuals_files` and that the include/exclude lists select, in
+ *         file order, or a PcaError.
+ *
+ * Every sample of a file is stored, selected or not, and the lists are applied
+ * only when the Batch is built, so a resumed run may change them. Files the
+ * lists exclude entirely are not read (nor marked finished). Files are read
+ * sequentially, so the `rayon` feature does not speed up reading here. A
+ * resumed run gives the same Batch as an uninterrupted one.
+ */
+pub fn load_batch_checkpointed(
+    reference: &Reference,
+    individuals_files: &[String],
+    dir: &Path,
+    options: &AnalysisOptions,
+) -> Result<Batch, PcaError> {
+    let selected = sample_filter(options)?;
+    // Same file-level filter as load_batch; multi-FASTA records are filtered below
+    let wanted: Vec<String> = individuals_files
+        .iter()
+        .filter(|path| options.multi_fasta || selected(&sample_name(path), path))
+        .cloned()
+        .collect();
+    if options.haplotype_pairs {
+        // Pair over every wanted file; each is then read on its own
+        group_haplotype_files(&wanted)?;
+    }
+
+    let mut checkpoint = Checkpoint::open(dir, reference.sequence(), options.resume)?;
+    let finished = checkpoint.finished_files();
+    let remaining: Vec<String> = wanted
+        .iter()
+        .filter(|path| !finished.contains(path.as_str()))
+        .cloned()
+        .collect();
+    if options.resume {
+        println!(
+            "Resuming from {}: {} of {} file(s) already read ({} sample(s))",
+            dir.display(),
+            wanted.len() - remaining.len(),
+            wanted.len(),
+            checkpoint.len()
+        );
+    }
+
+    if options.length_policy == LengthPolicy::Strict && !options.multi_fasta {
+        check_individual_lengths_with(
+            &remaining,
+            reference.len(),
+            options.invalid_bases,
+            options.length_slack,
+        )?;
+    }
+
+    // Store every sample of a file; selection happens when the Batch is built
+    let unfiltered = AnalysisOptions {
+        include_file: None,
+        exclude_file: None,
+        ..options.clone()
+    };
+    for path in &remaining {
+        let batch = read_batch(
+            reference,
+            std::slice::from_ref(path),
+            &unfiltered,
+            &|_, _| true,
+        )?;
+        for ((label, source), genome) in batch.labels.iter().zip(&batch.sources).zip(&batch.genomes)
+        {
+            checkpoint.add_sample(label, source, genome)?;
+        }
+        checkpoint.finish_file(path)?;
+    }
+
+    // Rows follow the current file list, whatever order earlier runs read in
+    let stored = checkpoint.batch()?;
+    let listed: HashMap<&str, usize> = wanted
+        .iter()
+        .enumerate()
+        .map(|(i, path)| (path.as_str(), i))
+        .collect();
+    let mut order: Vec<usize> = (0..stored.labels.len())
+        .filter(|&i| {
+            listed.contains_key(stored.sources[i].as_str())
+                && selected(&stored.labels[i], &stored.sources[i])
+        })
+        .collect();
+    order.sort_by_key(|&i| listed[stored.sources[i].as_str()]);
+    if options.include_file.is_some() || options.exclude_file.is_some() {
+        println!(
+            "{} individual(s) selected by the include/exclude lists",
+            order.len()
         );
-        return;
     }
-    println!("Reference genome length: {}", ref_length);
+    if order.is_empty() {
+        return Err(PcaError::InvalidInput(
+            "No individuals left to analyse.".to_string(),
+        ));
+    }
+    Ok(Batch {
+        labels: order.iter().map(|&i| stored.labels[i].clone()).collect(),
+        sources: order.iter().map(|&i| stored.sources[i].clone()).collect(),
+        genomes: order.iter().map(|&i| stored.genomes[i].clone()).collect(),
+        length: stored.length,
+    })
+}
+
+/**
+ * @struct DiploidBatch
+ * @brief Haplotype genomes regrouped into one row per sample.
+ */
+struct DiploidBatch {
+    /// Sample ids.
+    labels: Vec<String>,
+    /// "hap1,hap2" file paths per sample.
+    sources: Vec<String>,
+    /// n x d dosage matrix (see call_variants_diploid).
+    dosages: Vec<f64>,
+}
+
+/**
+ * @brief Pair haplotype genomes by sample and call diploid dosages.
+ *
+ * @param ref_data Reference bases, the length of every genome.
+ * @param sources  File each genome was read from (`<sample>.hap1.<ext>` etc.).
+ * @param genomes  The haplotype sequences, parallel to `sources`.
+ * @return One DiploidBatch row per sample, or a PcaError when a sample lacks
+ *         a haplotype (e.g. one was skipped for its length).
+ */
+fn diploid_dosages(
+    ref_data: &[u8],
+    sources: &[String],
+    genomes: &[Vec<u8>],
+) -> Result<DiploidBatch, PcaError> {
+    let pairs = group_haplotype_files(sources)?;
+    let index: HashMap<&str, usize> = sources
+        .iter()
+        .enumerate()
+        .map(|(i, source)| (source.as_str(), i))
+        .collect();
+    let mut samples = Vec::with_capacity(pairs.len());
+    let mut pair_sources = Vec::with_capacity(pairs.len());
+    let mut dosages = Vec::with_capacity(pairs.len() * ref_data.len());
+    for (sample, hap1, hap2) in pairs {
+        dosages.extend(call_variants_diploid(
+            ref_data,
+            &genomes[index[hap1.as_str()]],
+            &genomes[index[hap2.as_str()]],
+        ));
+        samples.push(sample);
+        pair_sources.push(format!("{},{}", hap1, hap2));
+    }
+    Ok(DiploidBatch {
+        labels: samples,
+        sources: pair_sources,
+        dosages,
+    })
+}
+
+/**
+ * @brief The body of analyze_with_reference; with options.timing, prints the
+ *        per-stage breakdown collected in `timer` once every output is written.
+ */
+fn analyze_timed(
+    reference: &Reference,
+    individuals_files: &[String],
+    output_dir: &Path,
+    options: &AnalysisOptions,
+    timer: &mut StageTimer,
+) -> Result<(), PcaError> {
+    let population_labels = match &options.labels_file {
+        Some(path) => Some(read_population_labels(path)?),
+        None => None,
+    };
+
+    if options
+        .duplicate_threshold
+        .is_some_and(|threshold| !(0.0..=1.0).contains(&threshold))
+    {
+        return Err(PcaError::InvalidInput(
+            "Duplicate threshold must be a fraction between 0 and 1.".to_string(),
+        ));
+    }
+
+    if !(0.0..=0.5).contains(&options.min_maf) {
+        return Err(PcaError::InvalidInput(format!(
+            "Minimum minor allele frequency must be between 0 and 0.5, got {}.",
+            options.min_maf
+        )));
+    }
+
+    if options.save_model && options.scaling == Scaling::Standardize {
+        return Err(PcaError::InvalidInput(
+            "Standardized fits cannot be saved as a model; drop --standardize.".to_string(),
+        ));
+    }
+
+    if options.haplotype_pairs && (options.streaming || options.multi_fasta || options.save_model) {
+        return Err(PcaError::InvalidInput(
+            "Haplotype pairs cannot be combined with --streaming, --multi-fasta or --save-model."
+                .to_string(),
+        ));
+    }
+
+    if options.checkpoint_dir.is_some() && options.length_policy == LengthPolicy::Truncate {
+        return Err(PcaError::InvalidInput(
+            "Checkpointing stores full-length sequences; it cannot be combined with truncation."
+                .to_string(),
+        ));
+    }
+
+    if options.streaming && options.emit_grm {
+        return Err(PcaError::InvalidInput(
+            "The GRM needs the in-memory call matrix; drop --streaming.".to_string(),
+        ));
+    }
+
+    if options.streaming && options.window_map.is_some() {
+        return Err(PcaError::InvalidInput(
+            "Windowed PCA needs the in-memory call matrix; drop --streaming.".to_string(),
+        ));
+    }
+
+    if options.streaming && options.sample_snps.is_some() {
+        return Err(PcaError::InvalidInput(
+            "SNP sampling needs the in-memory call matrix; drop --streaming.".to_string(),
+        ));
+    }
+
+    let unmasked;
+    let reference = if options.soft_mask == SoftMask::Exclude {
+        println!(
+            "Excluding {} soft-masked reference positions",
+            reference.soft_masked_count()
+        );
+        unmasked = reference.without_soft_masked();
+        &unmasked
+    } else {
+        reference
+    };
 
-    let ref_data = match read_file_in_chunks(ref_file, ref_length) {
-        Ok(buf) => buf,
-        Err(e) => {
-            eprintln!("Error reading reference file: {}", e);
-            return;
+    let Batch {
+        labels: mut kept,
+        sources: mut kept_sources,
+        genomes,
+        length: d,
+    } = match &options.checkpoint_dir {
+        Some(dir) => {
+            load_batch_checkpointed(reference, individuals_files, Path::new(dir), options)?
         }
+        None => load_batch(reference, individuals_files, options)?,
     };
+    let ref_data = &reference.sequence()[..d];
+    timer.lap("read");
 
-    let n = individuals_files.len();
-    let d = ref_length;
+    std::fs::create_dir_all(output_dir)
+        .map_err(|e| PcaError::io(&output_dir.to_string_lossy(), e))?;
 
-    // Prepare data matrix for variant calls: n x d
-    let mut data_matrix: Vec<f64> = Vec::with_capacity(n * d);
+    let qc_path = output_dir.join("qc.txt");
+    let report = mismatch_report(ref_data, &genomes, 10);
+    write_qc(&qc_path, &kept, &report, d, options.float_format)
+        .map_err(|e| PcaError::io(&qc_path.to_string_lossy(), e))?;
+
+    // qc.txt has one row per haplotype file; from here on a row is a sample
+    let dosages = if options.haplotype_pairs {
+        let diploid = diploid_dosages(ref_data, &kept_sources, &genomes)?;
+        println!(
+            "Paired {} haplotype files into {} diploid samples",
+            genomes.len(),
+            diploid.labels.len()
+        );
+        kept = diploid.labels;
+        kept_sources = diploid.sources;
+        Some(diploid.dosages)
+    } else {
+        None
+    };
+    let ploidy = if options.haplotype_pairs { 2.0 } else { 1.0 };
+    let call_matrix = || match &dosages {
+        Some(dosages) => dosages.clone(),
+        None => genomes
+            .iter()
+            .flat_map(|genome| call_variants(ref_data, genome))
+            .collect(),
+    };
+
+    // Distances, frequencies, the SFS, duplicates, diagnostics and the bootstrap
+    // need the full call matrix; it is dropped again before PCA starts
+    if options.write_distances
+        || options.write_frequencies
+        || options.write_sfs
+        || options.duplicate_threshold.is_some()
+        || options.write_diagnostics
+        || options.bootstrap > 0
+    {
+        let n = kept.len();
+        let mut calls = call_matrix();
+        if options.write_distances {
+            let distances = pairwise_distance_matrix(&calls, n, d);
+            let dist_path = output_dir.join("distances.csv");
+            write_distances(&dist_path, &kept, &distances, options.float_format)
+                .map_err(|e| PcaError::io(&dist_path.to_string_lossy(), e))?;
+        }
+        if options.write_frequencies {
+            let maf_path = output_dir.join("maf.csv");
+            let frequencies: Vec<f64> = allele_frequencies(&calls, n, d)
+                .iter()
+                .map(|freq| freq / ploidy)
+                .collect();
+            write_frequencies(&maf_path, &frequencies, options.float_format)
+                .map_err(|e| PcaError::io(&maf_path.to_string_lossy(), e))?;
+        }
+        if options.write_sfs {
+            let spectrum = site_frequency_spectrum_with(&calls, n, d, ploidy as usize);
+            let sfs_path = output_dir.join("sfs.csv");
+            write_sfs(&sfs_path, &spectrum)
+                .map_err(|e| PcaError::io(&sfs_path.to_string_lossy(), e))?;
+        }
+        // Before any imputation, so missing calls do not count as matches
+        if let Some(threshold) = options.duplicate_threshold {
+            let duplicates = find_duplicates(&calls, n, d, threshold);
+            if !duplicates.is_empty() {
+                eprintln!(
+                    "Warning: {} pair(s) of individuals look like duplicates (distance <= {}); see duplicates.txt",
+                    duplicates.len(),
+                    threshold
+                );
+            }
+            let dup_path = output_dir.join("duplicates.txt");
+            write_duplicates(&dup_path, &kept, &duplicates, options.float_format)
+                .map_err(|e| PcaError::io(&dup_path.to_string_lossy(), e))?;
+        }
+        // Diagnostics and the bootstrap see the same columns PCA will
+        let d = if options.write_diagnostics || options.bootstrap > 0 {
+            apply_missing_policy(&mut calls, n, d, options.missing)?.len()
+        } else {
+            d
+        };
+        if options.write_diagnostics && n >= 2 {
+            let columns = variable_columns(&calls, n, d);
+            let p = columns.len();
+            let mut variable = calls.clone();
+            retain_columns(&mut variable, n, d, &columns);
+            let corr = compute_correlation_matrix(&center_data(&variable, n, p), n, p);
+            let bartlett = bartlett_sphericity(&corr, p, n);
+            let kmo_value = kmo(&corr, p);
+            match (&bartlett, kmo_value) {
+                (Some(test), Some(kmo_value)) => {
+                    println!(
+                        "Bartlett's test of sphericity: chi-square = {:.3}, df = {}, p = {:.3e}",
+                        test.chi_square, test.df, test.p_value
+                    );
+                    println!("Kaiser-Meyer-Olkin measure: {:.3}", kmo_value);
+                }
+                _ => println!(
+                    "Bartlett's test and KMO are undefined: the correlation matrix of the {} variable positions is singular (e.g. more positions than samples)",
+                    p
+                ),
+            }
+            let diag_path = output_dir.join("diagnostics.csv");
+            write_diagnostics(
+                &diag_path,
+                p,
+                bartlett.as_ref(),
+                kmo_value,
+                options.float_format,
+            )
+            .map_err(|e| PcaError::io(&diag_path.to_string_lossy(), e))?;
+        }
+        if options.bootstrap > 0 && n >= 2 {
+            let replicates = bootstrap_eigenvalues(
+                &calls,
+                n,
+                d,
+                options.num_components,
+                options.bootstrap,
+                options.seed,
+            )?;
+            let (means, std_devs) = bootstrap_summary(&replicates);
+            let boot_path = output_dir.join("bootstrap.csv");
+            write_bootstrap(&boot_path, &means, &std_devs, options.float_format)
+                .map_err(|e| PcaError::io(&boot_path.to_string_lossy(), e))?;
+        }
+    }
+
+    timer.lap("qc");
+
+    let result = if options.streaming {
+        // Each individual is loaded twice: once to accumulate, once to project
+        let mut streamed =
+            Progress::new("Streaming individuals", 2 * genomes.len(), options.verbose);
+        let result = run_pca_streaming_with(
+            ref_data,
+            genomes.len(),
+            |i| {
+                streamed.inc(1);
+                Ok(genomes[i].clone())
+            },
+            options,
+        );
+        streamed.finish();
+        // Calling, covariance and projection interleave, so they are timed as one
+        timer.lap("streaming pca");
+        result
+    } else if let Some(dosages) = &dosages {
+        timer.lap("variant call");
+        pca_on_calls(dosages.clone(), kept.len(), d, options, timer)
+    } else {
+        run_pca_timed(ref_data, &genomes, options, timer)
+    };
+    let output = result?;
+    if options.sample_snps.is_some() {
+        // Preview run: record which SNPs the approximate PCA is based on
+        println!(
+            "Sampled {} of {} positions (seed {}); see sampled_snps.txt",
+            output.positions.len(),
+            d,
+            options.seed
+        );
+        let sampled_path = output_dir.join("sampled_snps.txt");
+        write_sampled_snps(&sampled_path, &output.positions)
+            .map_err(|e| PcaError::io(&sampled_path.to_string_lossy(), e))?;
+    } else {
+        println!(
+            "Removed {} invariant{} positions, {} remain",
+            d - output.positions.len(),
+            if options.min_maf > 0.0 {
+                " or rare"
+            } else {
+                ""
+            },
+            output.positions.len()
+        );
+    }
+    let n = output.num_samples;
+    let pca_res = &output.result;
+    let scores = &output.scores;
+
+    let clusters = options.num_clusters.map(|num_clusters| {
+        kmeans(
+            scores,
+            n,
+            pca_res.num_components,
+            num_clusters,
+            options.seed,
+        )
+    });
+
+    // Clusters come from the raw scores; only the written coordinates are rescaled
+    let normalized;
+    let scores = if options.normalize_scores {
+        let mut rescaled = scores.clone();
+        normalize_scores(&mut rescaled, n, pca_res.num_components);
+        normalized = rescaled;
+        &normalized
+    } else {
+        scores
+    };
+
+    let populations: Option<Vec<Option<String>>> = population_labels.map(|labels| {
+        kept.iter()
+            .map(|sample| labels.get(sample).cloned())
+            .collect()
+    });
+
+    // Write results
+    let scores_path = output_dir.join("results.csv");
+    write_scores(
+        &scores_path,
+        &kept,
+        scores,
+        pca_res.num_components,
+        populations.as_deref(),
+        clusters.as_deref(),
+        options.float_format,
+    )
+    .map_err(|e| PcaError::io(&scores_path.to_string_lossy(), e))?;
 
-    // For each individual, call variants
-    for (i, indiv) in individuals_files.iter().enumerate() {
-        let indiv_length = get_file_length(indiv);
-        if indiv_length != d {
+    if let Some(populations) = &populations {
+        let unlabelled = populations.iter().filter(|p| p.is_none()).count();
+        if unlabelled > 0 {
             eprintln!(
-                "Individual {} length {} != reference length {}",
-                i, indiv_length, d
+                "Warning: {} sample(s) have no population in the labels file",
+                unlabelled
             );
-            return;
         }
+        let centroids = population_centroids(scores, n, pca_res.num_components, populations);
+        let centroids_path = output_dir.join("centroids.csv");
+        write_centroids(
+            &centroids_path,
+            &centroids,
+            pca_res.num_components,
+            options.float_format,
+        )
+        .map_err(|e| PcaError::io(&centroids_path.to_string_lossy(), e))?;
+    }
 
-        let indiv_data = match read_file_in_chunks(indiv, indiv_length) {
-            Ok(buf) => buf,
-            Err(e) => {
-                eprintln!("Error reading individual file {}: {}", indiv, e);
-                return;
-            }
+    let evals_path = output_dir.join("eigenvalues.csv");
+    write_eigenvalues(&evals_path, pca_res, options.float_format)
+        .map_err(|e| PcaError::io(&evals_path.to_string_lossy(), e))?;
+
+    let scree_path = output_dir.join("scree.csv");
+    write_scree(&scree_path, pca_res, options.float_format)
+        .map_err(|e| PcaError::io(&scree_path.to_string_lossy(), e))?;
+    println!(
+        "Suggested number of components (scree elbow): {}",
+        pca_res.elbow_component()
+    );
+    let rank = pca_res.numerical_rank();
+    println!(
+        "Numerical rank of the decomposed matrix: {} ({} samples, {} positions)",
+        rank,
+        n,
+        output.positions.len()
+    );
+    if options.num_components > rank {
+        eprintln!(
+            "Warning: {} components requested but the matrix has rank {}; components past {} have zero variance and arbitrary directions",
+            options.num_components, rank, rank
+        );
+    }
+    let tw_p_values = tracy_widom_test(&pca_res.eigenvalues, n, output.positions.len());
+    println!(
+        "{} significant PCs at alpha=0.05 (Tracy-Widom)",
+        significant_components(&tw_p_values, 0.05)
+    );
+
+    if options.save_model {
+        let model_path = output_dir.join("model.json");
+        PCAModel::new(pca_res, &output.means, &output.positions)
+            .save_model(&model_path.to_string_lossy())?;
+    }
+
+    if let Some(grm) = &output.grm {
+        let grm_path = output_dir.join("grm.csv");
+        write_distances(&grm_path, &kept, grm, options.float_format)
+            .map_err(|e| PcaError::io(&grm_path.to_string_lossy(), e))?;
+    }
+
+    if options.write_loadings {
+        let loadings_path = output_dir.join("loadings.csv");
+        write_loadings(
+            &loadings_path,
+            &output.positions,
+            pca_res,
+            options.float_format,
+        )
+        .map_err(|e| PcaError::io(&loadings_path.to_string_lossy(), e))?;
+    }
+
+    if let Some(map_path) = &options.window_map {
+        let mut windows = read_window_map(map_path, options.window_size)?;
+        let mapped: usize = windows.iter().map(|window| window.columns.len()).sum();
+        if mapped < d {
+            return Err(PcaError::LengthMismatch {
+                file: map_path.clone(),
+                expected: d,
+                found: mapped,
+            });
+        }
+        // Positions past a truncated sequence length are not in the matrix
+        for window in windows.iter_mut() {
+            window.columns.retain(|&col| col < d);
+        }
+        windows.retain(|window| !window.columns.is_empty());
+        let calls = call_matrix();
+        let window_options = AnalysisOptions {
+            sample_snps: None,
+            timing: false,
+            emit_grm: false,
+            ..options.clone()
         };
+        let outputs = run_windowed_pca(&calls, n, d, &windows, &window_options)?;
+        println!(
+            "Windowed PCA: {} of {} windows have variable positions; see windows.csv",
+            outputs.len(),
+            windows.len()
+        );
+        let windows_path = output_dir.join("windows.csv");
+        write_windows(&windows_path, &kept, &outputs, options.float_format)
+            .map_err(|e| PcaError::io(&windows_path.to_string_lossy(), e))?;
+        timer.lap("windows");
+    }
 
-        let variants = call_variants(&ref_data, &indiv_data);
-        data_matrix.extend_from_slice(&variants);
+    let samples_path = output_dir.join("samples.txt");
+    write_samples(&samples_path, &kept, &kept_sources)
+        .map_err(|e| PcaError::io(&samples_path.to_string_lossy(), e))?;
+    timer.lap("write");
+    timer.report();
+
+    println!("PCA analysis completed. See results.csv, eigenvalues.csv, scree.csv, samples.txt and qc.txt");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invariant_half_of_columns_is_removed() {
+        // Columns 0, 2 and 4 vary; 1, 3 and 5 do not
+        let mut data = vec![
+            0.0, 1.0, 1.0, 0.0, 0.0, 1.0, //
+            1.0, 1.0, 0.0, 0.0, 1.0, 1.0, //
+            1.0, 1.0, 1.0, 0.0, 0.0, 1.0,
+        ];
+        assert_eq!(variable_columns(&data, 3, 6), vec![0, 2, 4]);
+        let new_d = filter_invariant_columns(&mut data, 3, 6);
+        assert_eq!(new_d, 3);
+        assert_eq!(data, vec![0.0, 1.0, 0.0, 1.0, 0.0, 1.0, 1.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn mismatch_count_equals_called_differences() {
+        let reference = b"ACGTACGTACGTNACG".to_vec();
+        let individuals = vec![
+            b"ACGTACGTACGTNACG".to_vec(),
+            b"TCGAACGTNCGTAACC".to_vec(),
+            b"CATGCATGCATGCATG".to_vec(),
+        ];
+        let report = mismatch_report(&reference, &individuals, 3);
+        for (summary, indiv) in report.iter().zip(&individuals) {
+            let calls = call_variants(&reference, indiv);
+            let differing: Vec<usize> = (0..calls.len())
+                .filter(|&p| !calls[p].is_nan() && calls[p] != 0.0)
+                .collect();
+            assert_eq!(summary.count, differing.len());
+            assert_eq!(summary.first_positions, differing[..differing.len().min(3)]);
+        }
+        assert_eq!(report[0].count, 0);
+        assert_eq!(report[1].count, 3);
     }
 
-    // Perform PCA (n = number of individuals, d = length of genome)
-    let centered = center_data(&data_matrix, n, d);
-    let cov = compute_covariance_matrix(&centered, n, d);
-    let pca_res = eigen_decomposition(&cov, d);
-    let scores = project_data(&centered, n, d, &pca_res);
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn parallel_calling_matches_serial_rows() {
+        let reference = b"ACGTACGTACGTACGTACGT".to_vec();
+        let individuals: Vec<Vec<u8>> = (0..16)
+            .map(|i| {
+                let mut genome = reference.clone();
+                genome[i] = b'N';
+                genome[(i * 7) % 20] = b'T';
+                genome
+            })
+            .collect();
+        let serial: Vec<f64> = individuals
+            .iter()
+            .flat_map(|indiv| call_variants(&reference, indiv))
+            .collect();
+        let parallel = call_matrix(&reference, &individuals, &mut Progress::disabled());
+        assert_eq!(parallel.len(), serial.len());
+        // NaN != NaN, so compare bit patterns
+        let bits = |m: &[f64]| m.iter().map(|x| x.to_bits()).collect::<Vec<_>>();
+        assert_eq!(bits(&parallel), bits(&serial));
+    }
 
-    // Write results
-    {
-        let mut f_scores = match File::create("results.csv") {
-            Ok(file) => file,
-            Err(e) => {
-                eprintln!("Failed to create results.csv: {}", e);
-                return;
-            }
+    #[test]
+    fn missingness_filters_drop_one_snp_and_one_sample() {
+        let nan = f64::NAN;
+        // Sample 2 misses 3 of 5 calls; SNP 4 misses 2 of the 3 remaining samples
+        let data = vec![
+            0.0, 1.0, 0.0, 1.0, nan, //
+            1.0, 0.0, 1.0, 1.0, nan, //
+            nan, nan, nan, 0.0, 1.0, //
+            0.0, 0.0, 1.0, 0.0, 1.0,
+        ];
+        let (matrix, rows, columns) = apply_missingness_filters(&data, 4, 5, 0.5, 0.5);
+        assert_eq!(rows, vec![0, 1, 3]);
+        assert_eq!(columns, vec![0, 1, 2, 3]);
+        assert_eq!(
+            matrix,
+            vec![
+                0.0, 1.0, 0.0, 1.0, //
+                1.0, 0.0, 1.0, 1.0, //
+                0.0, 0.0, 1.0, 0.0,
+            ]
+        );
+    }
+
+    #[test]
+    fn scree_cumulative_column_for_known_eigenvalues() {
+        let pca_res = PCAResult {
+            eigenvalues: vec![4.0, 2.0, 1.0, 1.0],
+            eigenvectors: vec![0.0; 16],
+            num_components: 4,
+            dimension: 4,
         };
-        for row in 0..n {
-            for comp in 0..d {
-                let val = scores[row * d + comp];
-                if comp < (d - 1) {
-                    write!(f_scores, "{:.6},", val).unwrap();
-                } else {
-                    write!(f_scores, "{:.6}\n", val).unwrap();
-                }
-            }
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("scree.csv");
+        write_scree(&path, &pca_res, FloatFormat::default()).unwrap();
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines[0], "component,eigenvalue,proportion,cumulative");
+        let cumulative: Vec<&str> = lines[1..]
+            .iter()
+            .map(|line| line.split(',').nth(3).unwrap())
+            .collect();
+        assert_eq!(cumulative, ["0.500000", "0.750000", "0.875000", "1.000000"]);
+        assert_eq!(pca_res.elbow_component(), 2);
+    }
+
+    #[test]
+    fn pairwise_distances_count_differing_positions() {
+        let nan = f64::NAN;
+        let data = vec![
+            0.0, 0.0, 0.0, 0.0, 0.0, //
+            1.0, 1.0, 0.0, 0.0, 0.0, //
+            1.0, 0.0, 1.0, 1.0, nan,
+        ];
+        // 0-1 differ at 2 positions, 0-2 at 3, 1-2 at 3 (position 4 is skipped)
+        let expected = vec![
+            0.0, 2.0, 3.0, //
+            2.0, 0.0, 3.0, //
+            3.0, 3.0, 0.0,
+        ];
+        assert_eq!(pairwise_distance_matrix(&data, 3, 5), expected);
+    }
+
+    #[test]
+    fn identical_resamples_have_zero_variance() {
+        // Every column is the same, so every resample reproduces the data
+        let column = [0.0, 1.0, 2.0, 0.5, 1.5];
+        let (n, d) = (5, 6);
+        let data: Vec<f64> = column.iter().flat_map(|&x| [x; 6]).collect();
+        let replicates = bootstrap_eigenvalues(&data, n, d, 2, 8, 56).unwrap();
+        assert_eq!(replicates.len(), 8);
+        assert!(replicates.iter().all(|rep| rep == &replicates[0]));
+        let (means, std_devs) = bootstrap_summary(&replicates);
+        assert_eq!(means, replicates[0]);
+        assert_eq!(std_devs, vec![0.0, 0.0]);
+    }
+
+    #[test]
+    fn maf_filter_removes_rare_column() {
+        // Column 1 has one carrier in 10 (MAF 0.1); columns 0 and 2 have MAF 0.5 and 0.3
+        let n = 10;
+        let data: Vec<f64> = (0..n)
+            .flat_map(|s| {
+                [
+                    (s % 2) as f64,
+                    if s == 4 { 1.0 } else { 0.0 },
+                    if s < 7 { 1.0 } else { 0.0 },
+                ]
+            })
+            .collect();
+        let (matrix, new_d) = filter_by_maf(&data, n, 3, 0.2);
+        assert_eq!(new_d, 2);
+        assert_eq!(maf_columns(&data, n, 3, 0.2), vec![0, 2]);
+        let expected: Vec<f64> = data.chunks(3).flat_map(|row| [row[0], row[2]]).collect();
+        assert_eq!(matrix, expected);
+        assert_eq!(filter_by_maf(&data, n, 3, 0.05).1, 3);
+    }
+
+    #[test]
+    fn perfectly_correlated_columns_collapse_to_one() {
+        // Column 2 equals column 0; column 3 is its complement (r = -1)
+        let data = vec![
+            0.0, 1.0, 0.0, 1.0, //
+            1.0, 1.0, 1.0, 0.0, //
+            1.0, 0.0, 1.0, 0.0, //
+            0.0, 0.0, 0.0, 1.0, //
+            1.0, 0.0, 1.0, 0.0,
+        ];
+        let (matrix, columns) = ld_prune(&data, 5, 4, 4, 1, 0.9);
+        assert_eq!(columns, vec![0, 1]);
+        let expected: Vec<f64> = data.chunks(4).flat_map(|row| [row[0], row[1]]).collect();
+        assert_eq!(matrix, expected);
+    }
+
+    #[test]
+    fn timing_is_a_numeric_no_op_and_labels_every_stage() {
+        let dir = tempfile::tempdir().unwrap();
+        let reference = Reference::from_sequence("ref", b"ACGTACGTACGTACGTACGT".to_vec());
+        let files: Vec<String> = [
+            "ACGAACGTACCTACGTACGT",
+            "TCGTACGTACGAACGTACGA",
+            "ACGTACCTACGTTCGTACGT",
+        ]
+        .iter()
+        .enumerate()
+        .map(|(i, genome)| {
+            let path = dir.path().join(format!("ind{}.txt", i));
+            std::fs::write(&path, genome).unwrap();
+            path.to_string_lossy().into_owned()
+        })
+        .collect();
+
+        let plain = dir.path().join("plain");
+        let timed = dir.path().join("timed");
+        let options = PcaConfig::builder().components(2);
+        analyze_with_reference(&reference, &files, &plain, &options.clone().build()).unwrap();
+        let mut timer = StageTimer::new(true);
+        analyze_timed(
+            &reference,
+            &files,
+            &timed,
+            &options.timing(true).build(),
+            &mut timer,
+        )
+        .unwrap();
+
+        for name in ["results.csv", "eigenvalues.csv"] {
+            assert_eq!(
+                std::fs::read(plain.join(name)).unwrap(),
+                std::fs::read(timed.join(name)).unwrap()
+            );
+        }
+        let labels: Vec<&str> = timer.stages().iter().map(|(label, _)| *label).collect();
+        for stage in [
+            "read",
+            "variant call",
+            "center",
+            "covariance",
+            "eigen",
+            "project",
+            "write",
+        ] {
+            assert!(
+                labels.contains(&stage),
+                "{} missing from {:?}",
+                stage,
+                labels
+            );
         }
     }
 
-    {
-        let mut f_evals = match File::create("eigenvalues.csv") {
-            Ok(file) => file,
-            Err(e) => {
-                eprintln!("Failed to create eigenvalues.csv: {}", e);
-                return;
+    #[test]
+    fn sampling_all_snps_keeps_every_column() {
+        let columns = vec![2, 3, 5, 8, 13, 21];
+        assert_eq!(reservoir_sample(&columns, 6, 81), columns);
+        assert_eq!(reservoir_sample(&columns, 10, 81), columns);
+        let some = reservoir_sample(&columns, 3, 81);
+        assert_eq!(some.len(), 3);
+        assert!(some.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(some.iter().all(|col| columns.contains(col)));
+        assert_eq!(reservoir_sample(&columns, 3, 81), some);
+
+        let reference = b"ACGTACGTACGTACGTACGT".to_vec();
+        let individuals: Vec<Vec<u8>> = (0..5)
+            .map(|i| {
+                let mut genome = reference.clone();
+                genome[i] = b'T';
+                genome[(i * 3 + 7) % 20] = b'G';
+                genome
+            })
+            .collect();
+        let full = run_pca(&reference, &individuals, 2).unwrap();
+        let d = full.positions.len();
+        let config = PcaConfig::builder()
+            .components(2)
+            .sample_snps(Some(d))
+            .build();
+        let sampled = run_pca_with_config(&reference, &individuals, &config).unwrap();
+        assert_eq!(sampled.positions, full.positions);
+        assert_eq!(sampled.result.eigenvalues, full.result.eigenvalues);
+    }
+
+    #[test]
+    fn mds_recovers_points_up_to_rotation() {
+        let points: [[f64; 2]; 6] = [
+            [0.0, 0.0],
+            [3.0, 0.0],
+            [0.0, 4.0],
+            [1.0, 1.0],
+            [-2.0, 1.5],
+            [2.5, -1.0],
+        ];
+        let n = points.len();
+        let mut distances = vec![0.0; n * n];
+        for i in 0..n {
+            for j in 0..n {
+                let (dx, dy) = (points[i][0] - points[j][0], points[i][1] - points[j][1]);
+                distances[i * n + j] = (dx * dx + dy * dy).sqrt();
+            }
+        }
+        let coords = classical_mds(&distances, n, 2).unwrap();
+        assert_eq!(coords.len(), n * 2);
+
+        // Equal Gram matrices of the centered point sets mean the two agree up
+        // to a rotation or reflection
+        let mean = [
+            points.iter().map(|p| p[0]).sum::<f64>() / n as f64,
+            points.iter().map(|p| p[1]).sum::<f64>() / n as f64,
+        ];
+        for i in 0..n {
+            for j in 0..n {
+                let original: f64 = (0..2)
+                    .map(|c| (points[i][c] - mean[c]) * (points[j][c] - mean[c]))
+                    .sum();
+                let recovered: f64 = (0..2).map(|c| coords[i * 2 + c] * coords[j * 2 + c]).sum();
+                assert!((original - recovered).abs() < 1e-9, "({}, {})", i, j);
             }
-        };
-        for (i, &val) in pca_res.eigenvalues.iter().enumerate() {
-            writeln!(f_evals, "{},{}", i + 1, val).unwrap();
         }
+
+        let mut bad = distances.clone();
+        bad[1] = f64::INFINITY;
+        assert!(matches!(
+            classical_mds(&bad, n, 2),
+            Err(PcaError::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn builder_defaults_and_overrides() {
+        let defaults = PcaConfig::builder().build();
+        assert_eq!(defaults.num_components, 10);
+        assert_eq!(defaults.min_maf, 0.0);
+        assert_eq!(defaults.scaling, Scaling::Center);
+        assert_eq!(defaults.matrix, MatrixKind::Covariance);
+        assert_eq!(defaults.missing, MissingPolicy::MeanImpute);
+        assert_eq!(defaults.length_policy, LengthPolicy::Strict);
+        assert_eq!(defaults.soft_mask, SoftMask::Normalize);
+        assert_eq!(defaults.seed, 0);
+        assert!(!defaults.streaming && !defaults.verbose);
+        assert!(defaults.labels_file.is_none() && defaults.max_memory.is_none());
+
+        let config = PcaConfig::builder()
+            .components(3)
+            .maf(0.05)
+            .matrix(MatrixKind::Correlation)
+            .missing(MissingPolicy::DropSnp)
+            .seed(7)
+            .labels_file(Some("labels.txt".to_string()))
+            .build();
+        assert_eq!(config.num_components, 3);
+        assert_eq!(config.min_maf, 0.05);
+        assert_eq!(config.matrix, MatrixKind::Correlation);
+        assert_eq!(config.missing, MissingPolicy::DropSnp);
+        assert_eq!(config.seed, 7);
+        assert_eq!(config.labels_file.as_deref(), Some("labels.txt"));
+        // Fields that were not set keep their defaults
+        assert_eq!(config.scaling, defaults.scaling);
+        assert_eq!(config.length_policy, defaults.length_policy);
+        assert_eq!(config.float_format, defaults.float_format);
+        assert!(!config.streaming);
     }
 
-    println!("PCA analysis completed. See results.csv and eigenvalues.csv");
+    #[test]
+    fn memory_budget_picks_the_algorithm() {
+        // 10 samples x 1000 SNPs: primal needs ~16 MB (d x d), dual ~160 kB (n x n)
+        let (n, d) = (10, 1000);
+        let primal = estimate_memory(n, d, PcaAlgorithm::Primal);
+        let dual = estimate_memory(n, d, PcaAlgorithm::Dual);
+        assert_eq!(primal, 8 * (2 * 10 * 1000 + 2 * 1000 * 1000));
+        assert_eq!(dual, 8 * (2 * 10 * 1000 + 2 * 10 * 10));
+
+        let cov = MatrixKind::Covariance;
+        assert_eq!(
+            choose_algorithm(n, d, cov, None).unwrap(),
+            PcaAlgorithm::Primal
+        );
+        // A budget only dual fits forces dual
+        assert_eq!(
+            choose_algorithm(n, d, cov, Some(dual)).unwrap(),
+            PcaAlgorithm::Dual
+        );
+        // A budget both fit allows either; the cheaper one is taken
+        assert_eq!(
+            choose_algorithm(n, d, cov, Some(u64::MAX)).unwrap(),
+            PcaAlgorithm::Dual
+        );
+        assert_eq!(
+            choose_algorithm(d, n, cov, Some(u64::MAX)).unwrap(),
+            PcaAlgorithm::Primal
+        );
+        // Correlation PCA has no dual path
+        let corr = MatrixKind::Correlation;
+        assert_eq!(
+            choose_algorithm(n, d, corr, Some(primal)).unwrap(),
+            PcaAlgorithm::Primal
+        );
+        assert!(matches!(
+            choose_algorithm(n, d, corr, Some(dual)),
+            Err(PcaError::InvalidInput(_))
+        ));
+        assert!(matches!(
+            choose_algorithm(n, d, cov, Some(dual - 1)),
+            Err(PcaError::InvalidInput(_))
+        ));
+    }
 }
@@ -0,0 +1,249 @@
+/*!
+ * @file checkpoint.rs
+ * @brief On-disk checkpoint of the individuals read so far, so a crashed run
+ *        can resume without re-reading them.
+ *
+ * Author: Namir Garib
+ * Created: January 2025
+ */
+
+use crate::analysis::Batch;
+use crate::error::PcaError;
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+const META_FILE: &str = "meta.txt";
+const SAMPLES_FILE: &str = "samples.txt";
+const SEQUENCES_FILE: &str = "sequences.bin";
+const FILES_FILE: &str = "files.txt";
+
+/**
+ * @struct Checkpoint
+ * @brief A checkpoint directory bound to one reference.
+ *
+ * The directory holds:
+ *   meta.txt       reference length and fingerprint; a resume against a
+ *                  different reference is refused
+ *   sequences.bin  the parsed sequence of every stored sample, back to back
+ *                  (one byte per position; its variant calls follow directly)
+ *   samples.txt    `label<TAB>source` per stored sample, in row order
+ *   files.txt      input files whose samples are all stored
+ * Each sample is synced to disk as it is added. Samples of a file that was
+ * not finished when the run stopped are discarded on resume, and that file is
+ * read again.
+ */
+#[derive(Debug)]
+pub struct Checkpoint {
+    dir: PathBuf,
+    length: usize,
+    samples: Vec<(String, String)>,
+    files: Vec<String>,
+}
+
+/// FNV-1a hash of the reference, so a resume notices a different reference.
+fn fingerprint(sequence: &[u8]) -> u64 {
+    sequence.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+fn lines(path: &Path) -> Result<Vec<String>, PcaError> {
+    match std::fs::read_to_string(path) {
+        Ok(text) => Ok(text.lines().map(str::to_string).collect()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(PcaError::io(&path.to_string_lossy(), e)),
+    }
+}
+
+fn append_line(path: &Path, line: &str) -> Result<(), PcaError> {
+    let name = path.to_string_lossy();
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| PcaError::io(&name, e))?;
+    writeln!(file, "{}", line).map_err(|e| PcaError::io(&name, e))?;
+    file.sync_data().map_err(|e| PcaError::io(&name, e))
+}
+
+impl Checkpoint {
+    /**
+     * @brief Open (resume) or start (fresh) a checkpoint in `dir`.
+     *
+     * @param dir       Checkpoint directory, created if missing.
+     * @param reference Reference sequence the stored samples are compared with.
+     * @param resume    Keep what an earlier run stored; otherwise any existing
+     *                  checkpoint in `dir` is discarded.
+     * @return The checkpoint, or InvalidInput when resuming against a
+     *         reference other than the one the checkpoint was made with.
+     */
+    pub fn open(dir: &Path, reference: &[u8], resume: bool) -> Result<Self, PcaError> {
+        let dir_name = dir.to_string_lossy();
+        std::fs::create_dir_all(dir).map_err(|e| PcaError::io(&dir_name, e))?;
+        let meta = format!("{}\t{:016x}", reference.len(), fingerprint(reference));
+        let meta_path = dir.join(META_FILE);
+        let mut checkpoint = Checkpoint {
+            dir: dir.to_path_buf(),
+            length: reference.len(),
+            samples: Vec::new(),
+            files: Vec::new(),
+        };
+
+        let existing = lines(&meta_path)?;
+        if resume && !existing.is_empty() {
+            if existing[0] != meta {
+                return Err(PcaError::InvalidInput(format!(
+                    "Checkpoint in {} was made with a different reference; remove it or drop --resume.",
+                    dir_name
+                )));
+            }
+            checkpoint.load_index()?;
+            return Ok(checkpoint);
+        }
+
+        for name in [SAMPLES_FILE, SEQUENCES_FILE, FILES_FILE] {
+            let path = dir.join(name);
+            File::create(&path).map_err(|e| PcaError::io(&path.to_string_lossy(), e))?;
+        }
+        std::fs::write(&meta_path, format!("{}\n", meta))
+            .map_err(|e| PcaError::io(&meta_path.to_string_lossy(), e))?;
+        Ok(checkpoint)
+    }
+
+    /// Read samples.txt and files.txt, dropping samples of unfinished files.
+    fn load_index(&mut self) -> Result<(), PcaError> {
+        self.files = lines(&self.dir.join(FILES_FILE))?;
+        let finished: HashSet<&str> = self.files.iter().map(String::as_str).collect();
+        let mut samples = Vec::new();
+        for line in lines(&self.dir.join(SAMPLES_FILE))? {
+            let (label, source) = line.split_once('\t').ok_or_else(|| {
+                PcaError::Parse(format!(
+                    "{}: malformed checkpoint line '{}'",
+                    self.dir.join(SAMPLES_FILE).to_string_lossy(),
+                    line
+                ))
+            })?;
+            samples.push((label.to_string(), source.to_string()));
+        }
+
+        // Rows are stored file by file, so unfinished samples form the tail
+        let complete = samples
+            .iter()
+            .take_while(|(_, source)| finished.contains(source.as_str()))
+            .count();
+        let sequences_path = self.dir.join(SEQUENCES_FILE);
+        let stored = std::fs::metadata(&sequences_path)
+            .map_err(|e| PcaError::io(&sequences_path.to_string_lossy(), e))?
+            .len() as usize;
+        if stored < complete * self.length {
+            return Err(PcaError::InvalidInput(format!(
+                "Checkpoint in {} is damaged: {} samples listed but only {} stored.",
+                self.dir.to_string_lossy(),
+                complete,
+                stored / self.length.max(1)
+            )));
+        }
+        samples.truncate(complete);
+        self.samples = samples;
+        self.rewrite_tail()
+    }
+
+    /// Cut samples.txt and sequences.bin back to the finished samples.
+    fn rewrite_tail(&self) -> Result<(), PcaError> {
+        let samples_path = self.dir.join(SAMPLES_FILE);
+        let text: String = self
+            .samples
+            .iter()
+            .map(|(label, source)| format!("{}\t{}\n", label, source))
+            .collect();
+        std::fs::write(&samples_path, text)
+            .map_err(|e| PcaError::io(&samples_path.to_string_lossy(), e))?;
+        let sequences_path = self.dir.join(SEQUENCES_FILE);
+        let name = sequences_path.to_string_lossy();
+        OpenOptions::new()
+            .write(true)
+            .open(&sequences_path)
+            .and_then(|file| file.set_len((self.samples.len() * self.length) as u64))
+            .map_err(|e| PcaError::io(&name, e))
+    }
+
+    /// Input files whose samples are all stored.
+    pub fn finished_files(&self) -> HashSet<String> {
+        self.files.iter().cloned().collect()
+    }
+
+    /// Number of samples stored.
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// True if no sample is stored yet.
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /**
+     * @brief Store one sample's sequence (the reference's length) and sync it.
+     */
+    pub fn add_sample(&mut self, label: &str, source: &str, genome: &[u8]) -> Result<(), PcaError> {
+        if genome.len() != self.length {
+            return Err(PcaError::LengthMismatch {
+                file: source.to_string(),
+                expected: self.length,
+                found: genome.len(),
+            });
+        }
+        let sequences_path = self.dir.join(SEQUENCES_FILE);
+        let name = sequences_path.to_string_lossy();
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(&sequences_path)
+            .map_err(|e| PcaError::io(&name, e))?;
+        file.write_all(genome).map_err(|e| PcaError::io(&name, e))?;
+        file.sync_data().map_err(|e| PcaError::io(&name, e))?;
+        append_line(
+            &self.dir.join(SAMPLES_FILE),
+            &format!("{}\t{}", label, source),
+        )?;
+        self.samples.push((label.to_string(), source.to_string()));
+        Ok(())
+    }
+
+    /**
+     * @brief Record that every sample of `source` has been stored.
+     */
+    pub fn finish_file(&mut self, source: &str) -> Result<(), PcaError> {
+        append_line(&self.dir.join(FILES_FILE), source)?;
+        self.files.push(source.to_string());
+        Ok(())
+    }
+
+    /**
+     * @brief Every stored sample, in the order it was added.
+     *
+     * @return A Batch of the reference's length, or a PcaError if
+     *         sequences.bin cannot be read.
+     */
+    pub fn batch(&self) -> Result<Batch, PcaError> {
+        let sequences_path = self.dir.join(SEQUENCES_FILE);
+        let name = sequences_path.to_string_lossy();
+        let mut raw = Vec::with_capacity(self.samples.len() * self.length);
+        File::open(&sequences_path)
+            .and_then(|mut file| file.read_to_end(&mut raw))
+            .map_err(|e| PcaError::io(&name, e))?;
+        let genomes = raw
+            .chunks_exact(self.length.max(1))
+            .take(self.samples.len())
+            .map(<[u8]>::to_vec)
+            .collect();
+        let (labels, sources) = self.samples.iter().cloned().unzip();
+        Ok(Batch {
+            labels,
+            sources,
+            genomes,
+            length: self.length,
+        })
+    }
+}
@@ -0,0 +1,143 @@
+/*!
+ * @file cluster.rs
+ * @brief K-means clustering of PCA scores for population assignment.
+ *
+ * Author: Namir Garib
+ * Created: January 2025
+ */
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/**
+ * @brief Assign each sample to one of num_clusters groups with k-means.
+ *
+ * @param scores       Projected scores, row-major: n x k_components.
+ * @param n            Number of samples.
+ * @param k_components Number of score columns per sample.
+ * @param num_clusters Number of clusters (clamped to 1..=n).
+ * @param seed         Seed for k-means++ initialization; equal seeds give equal labels.
+ * @return One cluster label (0..num_clusters) per sample.
+ *
+ * Centers are seeded with k-means++ and refined by Lloyd iterations until no
+ * assignment changes (at most 300 rounds). A cluster that loses all its
+ * members keeps its previous center.
+ */
+pub fn kmeans(
+    scores: &[f64],
+    n: usize,
+    k_components: usize,
+    num_clusters: usize,
+    seed: u64,
+) -> Vec<usize> {
+    if n == 0 {
+        return Vec::new();
+    }
+    let num_clusters = num_clusters.clamp(1, n);
+    let dim = k_components;
+    let point = |i: usize| &scores[i * dim..(i + 1) * dim];
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    // k-means++: first center uniformly, then proportional to squared distance
+    let mut centers: Vec<f64> = Vec::with_capacity(num_clusters * dim);
+    centers.extend_from_slice(point(rng.gen_range(0..n)));
+    let mut nearest: Vec<f64> = (0..n)
+        .map(|i| squared_distance(point(i), &centers[..dim]))
+        .collect();
+    for _ in 1..num_clusters {
+        let total: f64 = nearest.iter().sum();
+        let chosen = if total > 0.0 {
+            let mut target = rng.gen_range(0.0..total);
+            let mut chosen = n - 1;
+            for (i, &dist) in nearest.iter().enumerate() {
+                if target < dist {
+                    chosen = i;
+                    break;
+                }
+                target -= dist;
+            }
+            chosen
+        } else {
+            // Every sample already sits on a center
+            rng.gen_range(0..n)
+        };
+        let start = centers.len();
+        centers.extend_from_slice(point(chosen));
+        for (i, dist) in nearest.iter_mut().enumerate() {
+            *dist = dist.min(squared_distance(point(i), &centers[start..start + dim]));
+        }
+    }
+
+    // Lloyd iterations
+    let max_iter = 300;
+    let mut labels = vec![usize::MAX; n];
+    for _ in 0..max_iter {
+        let mut changed = false;
+        for (i, label) in labels.iter_mut().enumerate() {
+            let best = (0..num_clusters)
+                .min_by(|&a, &b| {
+                    let da = squared_distance(point(i), &centers[a * dim..(a + 1) * dim]);
+                    let db = squared_distance(point(i), &centers[b * dim..(b + 1) * dim]);
+                    da.total_cmp(&db)
+                })
+                .unwrap_or(0);
+            if *label != best {
+                *label = best;
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+
+        let mut sums = vec![0.0; num_clusters * dim];
+        let mut counts = vec![0usize; num_clusters];
+        for (i, &label) in labels.iter().enumerate() {
+            counts[label] += 1;
+            for (s, x) in sums[label * dim..(label + 1) * dim]
+                .iter_mut()
+                .zip(point(i).iter())
+            {
+                *s += x;
+            }
+        }
+        for c in 0..num_clusters {
+            if counts[c] == 0 {
+                continue;
+            }
+            for j in 0..dim {
+                centers[c * dim + j] = sums[c * dim + j] / counts[c] as f64;
+            }
+        }
+    }
+
+    labels
+}
+
+/**
+ * @brief Squared Euclidean distance between two equal-length points.
+ */
+fn squared_distance(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b.iter()).map(|(x, y)| (x - y) * (x - y)).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn separated_clusters_are_recovered() {
+        // Samples 0..5 near (0, 0), samples 5..10 near (10, 10)
+        let mut scores = Vec::new();
+        for s in 0..10 {
+            let base = if s < 5 { 0.0 } else { 10.0 };
+            let jitter = (s % 5) as f64 * 0.1;
+            scores.extend([base + jitter, base - jitter]);
+        }
+        let labels = kmeans(&scores, 10, 2, 2, 37);
+        assert!(labels[..5].iter().all(|&l| l == labels[0]));
+        assert!(labels[5..].iter().all(|&l| l == labels[5]));
+        assert_ne!(labels[0], labels[5]);
+        assert_eq!(kmeans(&scores, 10, 2, 2, 37), labels);
+    }
+}
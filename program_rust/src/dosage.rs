@@ -0,0 +1,78 @@
+/*!
+ * @file dosage.rs
+ * @brief Raw binary dosage matrices (little-endian f64), for inputs whose calls are precomputed.
+ *
+ * Author: Namir Garib
+ * Created: January 2025
+ */
+
+use crate::error::PcaError;
+use crate::utils::{get_file_length, read_file_in_chunks, write_atomically};
+use std::io::Write;
+use std::path::Path;
+
+/**
+ * @brief Read an n x d row-major matrix of little-endian f64 values.
+ *
+ * @param path Path to the binary file.
+ * @param n    Number of rows (samples).
+ * @param d    Number of columns (SNPs).
+ * @return The matrix, or a PcaError if the file size is not exactly n*d*8 bytes.
+ *
+ * The file has no header, so n and d must be known; NaN marks a missing call.
+ */
+pub fn read_dosage_matrix(path: &str, n: usize, d: usize) -> Result<Vec<f64>, PcaError> {
+    let expected = n
+        .checked_mul(d)
+        .and_then(|cells| cells.checked_mul(8))
+        .ok_or_else(|| PcaError::InvalidInput(format!("{} x {} matrix is too large", n, d)))?;
+    let length = get_file_length(path)?;
+    if length != expected {
+        return Err(PcaError::Parse(format!(
+            "{}: expected {} bytes for a {} x {} f64 matrix, found {}",
+            path, expected, n, d, length
+        )));
+    }
+
+    let raw = read_file_in_chunks(path, length)?;
+    Ok(raw
+        .chunks_exact(8)
+        .map(|bytes| f64::from_le_bytes(bytes.try_into().unwrap()))
+        .collect())
+}
+
+/**
+ * @brief Write a matrix as little-endian f64 values, readable by read_dosage_matrix.
+ */
+pub fn write_dosage_matrix(path: &str, matrix: &[f64]) -> Result<(), PcaError> {
+    write_atomically(Path::new(path), |writer| {
+        for value in matrix {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        Ok(())
+    })
+    .map_err(|e| PcaError::io(path, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn written_matrix_reads_back() {
+        let matrix = vec![0.0, 1.0, 2.0, f64::NAN, 0.25, -1.5];
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dosage.bin");
+        let path = path.to_str().unwrap();
+        write_dosage_matrix(path, &matrix).unwrap();
+        assert_eq!(get_file_length(path).unwrap(), 6 * 8);
+
+        let read = read_dosage_matrix(path, 2, 3).unwrap();
+        let bits = |m: &[f64]| m.iter().map(|x| x.to_bits()).collect::<Vec<_>>();
+        assert_eq!(bits(&read), bits(&matrix));
+        assert!(matches!(
+            read_dosage_matrix(path, 3, 3),
+            Err(PcaError::Parse(_))
+        ));
+    }
+}
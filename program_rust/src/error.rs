@@ -0,0 +1,87 @@
+/*!
+ * @file error.rs
+ * @brief Crate-wide error type.
+ *
+ * Author: Namir Garib
+ * Created: January 2025
+ */
+
+use std::io;
+use thiserror::Error;
+
+/**
+ * @enum PcaError
+ * @brief Every fallible operation in the crate reports one of these.
+ */
+#[derive(Debug, Error)]
+pub enum PcaError {
+    /// An input file does not exist.
+    #[error("File not found: {0}")]
+    FileNotFound(String),
+
+    /// Opening, reading or writing a file failed.
+    #[error("I/O error on {path}: {source}")]
+    Io {
+        path: String,
+        #[source]
+        source: io::Error,
+    },
+
+    /// A file that must contain data is empty.
+    #[error("File is empty: {0}")]
+    EmptyFile(String),
+
+    /// A genome's length differs from the reference.
+    #[error("{file}: length {found} != reference length {expected}")]
+    LengthMismatch {
+        file: String,
+        expected: usize,
+        found: usize,
+    },
+
+    /// Several genomes' lengths differ from the reference (pre-flight check).
+    #[error(
+        "{} file(s) differ from reference length {expected}: {}",
+        .files.len(),
+        describe_mismatches(.files)
+    )]
+    LengthMismatches {
+        expected: usize,
+        files: Vec<(String, usize)>,
+    },
+
+    /// An input file is malformed.
+    #[error("Parse error: {0}")]
+    Parse(String),
+
+    /// The inputs were read but cannot be analysed (e.g. nothing varies).
+    #[error("{0}")]
+    InvalidInput(String),
+}
+
+/**
+ * @brief "path (length N), ..." listing for LengthMismatches.
+ */
+fn describe_mismatches(files: &[(String, usize)]) -> String {
+    files
+        .iter()
+        .map(|(file, found)| format!("{} (length {})", file, found))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl PcaError {
+    /**
+     * @brief Wrap an io::Error with the path it concerns; NotFound becomes FileNotFound.
+     */
+    pub fn io(path: &str, source: io::Error) -> Self {
+        if source.kind() == io::ErrorKind::NotFound {
+            PcaError::FileNotFound(path.to_string())
+        } else {
+            PcaError::Io {
+                path: path.to_string(),
+                source,
+            }
+        }
+    }
+}
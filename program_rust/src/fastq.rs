@@ -0,0 +1,109 @@
+/*!
+ * @file fastq.rs
+ * @brief Minimal FASTQ reader with optional quality-based base masking.
+ *
+ * Author: Namir Garib
+ * Created: January 2025
+ */
+
+use crate::error::PcaError;
+use crate::utils::read_maybe_gzipped;
+
+/**
+ * @brief Read the 4-line records of a FASTQ file.
+ *
+ * @param path     Path to a (optionally gzipped) FASTQ file.
+ * @param min_qual If set, bases whose Phred quality (ASCII - 33) is below this
+ *                 value are replaced by `N`, which variant calling treats as missing.
+ * @return (read id, uppercased sequence) pairs in file order, or a PcaError.
+ *
+ * Each record is `@id`, sequence, `+`, quality on one line each; wrapped
+ * sequence lines are not supported. Blank lines and CRLF endings are ignored.
+ */
+pub fn read_fastq(path: &str, min_qual: Option<u8>) -> Result<Vec<(String, Vec<u8>)>, PcaError> {
+    let raw = read_maybe_gzipped(path)?;
+    let text = String::from_utf8_lossy(&raw);
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim_end_matches('\r')))
+        .filter(|(_, line)| !line.is_empty());
+
+    let mut records = Vec::new();
+    while let Some((line_no, header)) = lines.next() {
+        let id = header
+            .strip_prefix('@')
+            .and_then(|h| h.split_whitespace().next())
+            .ok_or_else(|| {
+                PcaError::Parse(format!("{}:{}: expected '@' record header", path, line_no))
+            })?;
+        let truncated = || PcaError::Parse(format!("{}: record {} is truncated", path, id));
+        let (_, sequence) = lines.next().ok_or_else(truncated)?;
+        let (sep_no, separator) = lines.next().ok_or_else(truncated)?;
+        let (_, quality) = lines.next().ok_or_else(truncated)?;
+        if !separator.starts_with('+') {
+            return Err(PcaError::Parse(format!(
+                "{}:{}: expected '+' separator",
+                path, sep_no
+            )));
+        }
+        if quality.len() != sequence.len() {
+            return Err(PcaError::Parse(format!(
+                "{}: record {} has {} bases but {} quality scores",
+                path,
+                id,
+                sequence.len(),
+                quality.len()
+            )));
+        }
+
+        let bases = sequence
+            .bytes()
+            .zip(quality.bytes())
+            .map(|(base, qual)| match min_qual {
+                Some(min) if qual.saturating_sub(33) < min => b'N',
+                _ => base.to_ascii_uppercase(),
+            })
+            .collect();
+        records.push((id.to_string(), bases));
+    }
+
+    Ok(records)
+}
+
+/**
+ * @brief All records of a FASTQ file concatenated into one sequence
+ *        (the FASTQ counterpart of read_fasta).
+ */
+pub fn read_fastq_sequence(path: &str, min_qual: Option<u8>) -> Result<Vec<u8>, PcaError> {
+    Ok(read_fastq(path, min_qual)?
+        .into_iter()
+        .flat_map(|(_, sequence)| sequence)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn low_quality_base_is_masked() {
+        // '#' is Phred 2, 'I' is Phred 40
+        let fastq = "@read1 lane1\nacGTA\n+\nII#II\n\n@read2\r\nTTGC\r\n+read2\r\nIIII\r\n";
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tiny.fq");
+        std::fs::write(&path, fastq).unwrap();
+        let path = path.to_str().unwrap();
+
+        let masked = read_fastq(path, Some(20)).unwrap();
+        assert_eq!(
+            masked,
+            vec![
+                ("read1".to_string(), b"ACNTA".to_vec()),
+                ("read2".to_string(), b"TTGC".to_vec()),
+            ]
+        );
+        assert_eq!(read_fastq(path, None).unwrap()[0].1, b"ACGTA");
+        assert_eq!(read_fastq_sequence(path, Some(20)).unwrap(), b"ACNTATTGC");
+    }
+}
@@ -0,0 +1,26 @@
+/*!
+ * @file lib.rs
+ * @brief Library crate for PCA-based population stratification (Rust version)
+ *
+ * Author: Namir Garib
+ * Created: January 2025
+ */
+
+pub mod analysis;
+pub mod checkpoint;
+pub mod cluster;
+pub mod dosage;
+pub mod error;
+pub mod fastq;
+pub mod model;
+pub mod pca;
+pub mod plink;
+pub mod progress;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod reference;
+pub mod stats;
+pub mod utils;
+pub mod variant_calling;
+pub mod vcf;
+pub mod window;
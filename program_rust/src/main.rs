@@ -6,34 +6,423 @@
  * Created: January 2025
  */
 
-mod analysis;
-mod pca;
-mod utils;
-mod variant_calling;
-
-use std::env;
+use clap::Parser;
+use pca_population_strat::analysis::{
+    self, LengthPolicy, MatrixKind, MissingPolicy, PcaConfig, Scaling, SoftMask,
+};
+use pca_population_strat::error::PcaError;
+use pca_population_strat::utils::{collect_genome_files, is_url, FloatFormat, InvalidBasePolicy};
+use std::path::{Path, PathBuf};
 use std::process;
 
+/// Matrix that is eigendecomposed.
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum MatrixArg {
+    Covariance,
+    Correlation,
+}
+
+/// What to do with input bytes other than A, C, G, T and N.
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum InvalidBasesArg {
+    Error,
+    Skip,
+    Missing,
+}
+
+/// Treatment of lowercase (soft-masked) reference bases.
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum SoftMaskArg {
+    Normalize,
+    Exclude,
+}
+
+/// Handling of missing calls (`N` bases in an individual).
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum MissingArg {
+    Error,
+    MeanImpute,
+    DropSnp,
+}
+
+/// PCA-based population stratification from a reference and individual genomes.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Cli {
+    /// Reference genome (raw sequence or FASTA, optionally gzipped)
+    #[arg(short, long)]
+    reference: String,
+
+    /// Individual genomes: paths, glob patterns (e.g. "data/*.fa") or a directory of FASTA/FASTQ files
+    #[arg(short, long, num_args = 1.., required = true)]
+    individuals: Vec<String>,
+
+    /// Directory that receives results.csv and eigenvalues.csv (created if missing)
+    #[arg(short, long, default_value = ".")]
+    output_dir: PathBuf,
+
+    /// Number of principal components to compute and write
+    #[arg(short = 'k', long, visible_alias = "num-components", default_value_t = 10,
+          value_parser = clap::value_parser!(u64).range(1..))]
+    components: u64,
+
+    /// Z-score standardize SNP columns instead of only centering them
+    #[arg(long)]
+    standardize: bool,
+
+    /// Advanced/debugging: skip mean-centering and run PCA on the raw calls
+    /// (second-moment matrix); scores are projections of the raw data
+    #[arg(long, conflicts_with = "standardize")]
+    no_center: bool,
+
+    /// Eigendecompose the covariance or the correlation matrix
+    #[arg(long, value_enum, default_value = "covariance")]
+    matrix: MatrixArg,
+
+    /// Accumulate covariance one individual at a time (lower memory)
+    #[arg(long)]
+    streaming: bool,
+
+    /// Treat each individuals file as a multi-FASTA with one sample per record
+    #[arg(long)]
+    multi_fasta: bool,
+
+    /// Mask FASTQ bases with Phred quality below this value as missing
+    #[arg(long)]
+    min_qual: Option<u8>,
+
+    /// Bytes other than A/C/G/T/N (e.g. a binary file given by mistake): fail,
+    /// drop them, or treat them as missing calls
+    #[arg(long, value_enum, default_value = "error")]
+    invalid_bases: InvalidBasesArg,
+
+    /// Lowercase (soft-masked) reference bases: uppercase and analyse them,
+    /// or exclude those positions
+    #[arg(long, value_enum, default_value = "normalize")]
+    soft_mask: SoftMaskArg,
+
+    /// Missing calls (N; other bytes too with --invalid-bases missing): fail,
+    /// fill with the position's mean, or drop positions that have any
+    #[arg(long, value_enum, default_value = "mean-impute")]
+    missing: MissingArg,
+
+    /// Also write loadings.csv with each SNP position's weight on every component
+    #[arg(long)]
+    loadings: bool,
+
+    /// Also write distances.csv with the pairwise count of differing positions
+    #[arg(long)]
+    distances: bool,
+
+    /// Also write grm.csv: the n x n sample covariance (genetic relationship matrix)
+    #[arg(long)]
+    emit_grm: bool,
+
+    /// Also write maf.csv with each position's alternate and minor allele frequency
+    #[arg(long)]
+    frequencies: bool,
+
+    /// Also write sfs.csv: how many positions have each count of variant carriers
+    #[arg(long)]
+    sfs: bool,
+
+    /// Flag pairs of individuals differing at no more than this fraction of
+    /// positions (default 0.001) as likely duplicates in duplicates.txt
+    #[arg(long, value_name = "MAX_DIST", num_args = 0..=1, default_missing_value = "0.001")]
+    check_duplicates: Option<f64>,
+
+    /// Drop SNPs with a minor allele frequency below this (e.g. 0.05) before PCA
+    #[arg(long, default_value_t = 0.0)]
+    maf: f64,
+
+    /// Also write diagnostics.csv with Bartlett's sphericity test and the KMO measure
+    #[arg(long)]
+    diagnostics: bool,
+
+    /// Bootstrap SNPs this many times and write eigenvalue mean/stddev to bootstrap.csv
+    #[arg(long, default_value_t = 0)]
+    bootstrap: usize,
+
+    /// Quick preview: run PCA on M randomly chosen variable SNPs (seeded by
+    /// --seed) and list them in sampled_snps.txt
+    #[arg(long, value_name = "M", value_parser = clap::value_parser!(u64).range(1..))]
+    sample_snps: Option<u64>,
+
+    /// Rescale each PC column in results.csv to [-1, 1] (max |score| = 1) for
+    /// plotting; eigenvalues are unchanged
+    #[arg(long)]
+    normalize_scores: bool,
+
+    /// Report progress of reading, variant calling and covariance on stderr
+    #[arg(short, long)]
+    verbose: bool,
+
+    /// Print how long each pipeline stage (read, variant call, center,
+    /// covariance, eigen, project, write) took
+    #[arg(long)]
+    timing: bool,
+
+    /// Assign samples to this many populations by k-means on the PC scores
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    clusters: Option<u64>,
+
+    /// Seed for randomized steps such as k-means initialization
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+
+    /// Also write model.json so new samples can be projected without refitting
+    #[arg(long)]
+    save_model: bool,
+
+    /// Sample-to-population table (`sample population` per line); adds a
+    /// population column to results.csv and writes centroids.csv
+    #[arg(long)]
+    labels: Option<String>,
+
+    /// Only analyse the sample IDs listed in this file (one per line; matched
+    /// against file names without extension, or multi-FASTA record ids)
+    #[arg(long, value_name = "FILE")]
+    include: Option<String>,
+
+    /// Leave out the sample IDs listed in this file
+    #[arg(long, value_name = "FILE")]
+    exclude: Option<String>,
+
+    /// Store each individual's sequence in DIR as it is read, so an
+    /// interrupted run can continue with --resume
+    #[arg(long, value_name = "DIR")]
+    checkpoint: Option<String>,
+
+    /// Continue from --checkpoint DIR, reading only the files it has not finished
+    #[arg(long, requires = "checkpoint")]
+    resume: bool,
+
+    /// Individuals are phased haplotypes, two files per sample named
+    /// <sample>.hap1.<ext> and <sample>.hap2.<ext>; calls 0/1/2 dosages per sample
+    #[arg(long)]
+    haplotype_pairs: bool,
+
+    /// Decimal places of every number in the output files
+    #[arg(long, value_name = "N", default_value_t = 6)]
+    precision: usize,
+
+    /// Write numbers in scientific notation (e.g. 1.234560e-7), with --precision
+    /// digits after the point; keeps very small eigenvalues readable
+    #[arg(long)]
+    scientific: bool,
+
+    /// Memory budget in bytes for the PCA stage; the primal (d x d) or dual
+    /// (n x n) algorithm is picked to fit, failing if neither does
+    #[arg(long, value_name = "BYTES")]
+    max_memory: Option<u64>,
+
+    /// Accept individuals up to N bases longer than the reference, trimming the
+    /// extra bases with a warning (line breaks are never counted)
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    length_slack: usize,
+
+    /// Also run PCA separately per window and write windows.csv; FILE has one
+    /// "chrom pos" line per reference position
+    #[arg(long, value_name = "FILE")]
+    window_map: Option<String>,
+
+    /// Split each chromosome of --window-map into windows of this many base pairs
+    #[arg(long, value_name = "BP", requires = "window_map")]
+    window_size: Option<u64>,
+
+    /// Worker threads for parallel stages (rayon builds); 0 uses all cores
+    #[arg(long, default_value_t = 0)]
+    threads: usize,
+
+    /// Only check that every input exists, parses and matches the reference
+    /// length; print a summary and exit without running PCA or writing files
+    #[arg(long)]
+    validate: bool,
+}
+
+/**
+ * @brief Expand directories and glob patterns in the individuals list;
+ *        plain paths pass through.
+ */
+fn expand_individuals(patterns: &[String]) -> Result<Vec<String>, PcaError> {
+    let mut files = Vec::new();
+    for pattern in patterns {
+        if Path::new(pattern).is_dir() {
+            files.extend(collect_genome_files(pattern)?);
+            continue;
+        }
+        // A URL's query string is not a glob
+        if is_url(pattern) || !pattern.contains(['*', '?', '[']) {
+            files.push(pattern.clone());
+            continue;
+        }
+        let entries = glob::glob(pattern)
+            .map_err(|e| PcaError::InvalidInput(format!("Invalid pattern {}: {}", pattern, e)))?;
+        let mut matched: Vec<String> = entries
+            .filter_map(Result::ok)
+            .map(|p| p.to_string_lossy().into_owned())
+            .collect();
+        if matched.is_empty() {
+            return Err(PcaError::InvalidInput(format!(
+                "Pattern {} matched no files",
+                pattern
+            )));
+        }
+        matched.sort();
+        files.extend(matched);
+    }
+    Ok(files)
+}
+
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 4 {
-        eprintln!(
-            "Usage: {} <ref_genome> <num_individuals> <indiv1> [indiv2 ...]",
-            args[0]
-        );
+    let cli = Cli::parse();
+
+    #[cfg(feature = "rayon")]
+    if let Err(e) = pca_population_strat::utils::configure_threads(cli.threads) {
+        eprintln!("{}", e);
         process::exit(1);
     }
+    #[cfg(not(feature = "rayon"))]
+    if cli.threads > 0 {
+        eprintln!("Warning: --threads has no effect without the `rayon` feature");
+    }
+
+    let individuals_files = match expand_individuals(&cli.individuals) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+
+    let invalid_bases = match cli.invalid_bases {
+        InvalidBasesArg::Error => InvalidBasePolicy::Error,
+        InvalidBasesArg::Skip => InvalidBasePolicy::Skip,
+        InvalidBasesArg::Missing => InvalidBasePolicy::Missing,
+    };
 
-    let ref_file = &args[1];
-    let num_individuals: usize = args[2].parse().unwrap_or(0);
-    if num_individuals < 1 || (args.len() - 3) < num_individuals {
-        eprintln!("Invalid number of individuals or not enough file paths.");
+    if cli.validate {
+        let checks = analysis::validate_inputs(
+            &cli.reference,
+            &individuals_files,
+            cli.multi_fasta,
+            invalid_bases,
+            cli.length_slack,
+        );
+        println!("file\tformat\tlength\tstatus");
+        for check in &checks {
+            let length = check.length.map_or("-".to_string(), |len| len.to_string());
+            let status = check.error.as_deref().unwrap_or("ok");
+            println!("{}\t{}\t{}\t{}", check.file, check.format, length, status);
+        }
+        let failed = checks.iter().filter(|check| !check.is_ok()).count();
+        if failed > 0 {
+            eprintln!(
+                "Validation failed: {} of {} input(s) have errors.",
+                failed,
+                checks.len()
+            );
+            process::exit(1);
+        }
+        println!("All {} input(s) are valid.", checks.len());
+        return;
+    }
+
+    let scaling = if cli.standardize {
+        Scaling::Standardize
+    } else if cli.no_center {
+        Scaling::Raw
+    } else {
+        Scaling::Center
+    };
+
+    let options = PcaConfig::builder()
+        .length_policy(LengthPolicy::Strict)
+        .length_slack(cli.length_slack)
+        .scaling(scaling)
+        .matrix(match cli.matrix {
+            MatrixArg::Covariance => MatrixKind::Covariance,
+            MatrixArg::Correlation => MatrixKind::Correlation,
+        })
+        .components(cli.components as usize)
+        .streaming(cli.streaming)
+        .multi_fasta(cli.multi_fasta)
+        .haplotype_pairs(cli.haplotype_pairs)
+        .min_qual(cli.min_qual)
+        .invalid_bases(invalid_bases)
+        .soft_mask(match cli.soft_mask {
+            SoftMaskArg::Normalize => SoftMask::Normalize,
+            SoftMaskArg::Exclude => SoftMask::Exclude,
+        })
+        .missing(match cli.missing {
+            MissingArg::Error => MissingPolicy::Error,
+            MissingArg::MeanImpute => MissingPolicy::MeanImpute,
+            MissingArg::DropSnp => MissingPolicy::DropSnp,
+        })
+        .float_format(FloatFormat {
+            precision: cli.precision,
+            scientific: cli.scientific,
+        })
+        .write_loadings(cli.loadings)
+        .write_distances(cli.distances)
+        .emit_grm(cli.emit_grm)
+        .write_frequencies(cli.frequencies)
+        .write_sfs(cli.sfs)
+        .duplicate_threshold(cli.check_duplicates)
+        .maf(cli.maf)
+        .write_diagnostics(cli.diagnostics)
+        .bootstrap(cli.bootstrap)
+        .sample_snps(cli.sample_snps.map(|m| m as usize))
+        .normalize_scores(cli.normalize_scores)
+        .verbose(cli.verbose)
+        .timing(cli.timing)
+        .checkpoint_dir(cli.checkpoint)
+        .resume(cli.resume)
+        .num_clusters(cli.clusters.map(|c| c as usize))
+        .seed(cli.seed)
+        .max_memory(cli.max_memory)
+        .save_model(cli.save_model)
+        .labels_file(cli.labels)
+        .include_file(cli.include)
+        .exclude_file(cli.exclude)
+        .window_map(cli.window_map)
+        .window_size(cli.window_size)
+        .build();
+    let outcome = analysis::perform_full_analysis(
+        &cli.reference,
+        &individuals_files,
+        &cli.output_dir,
+        &options,
+    );
+    if let Err(e) = outcome {
+        eprintln!("Error: {}", e);
         process::exit(1);
     }
 
-    let individuals_files = &args[3..(3 + num_individuals)];
+    println!(
+        "Analysis complete. Check results.csv and eigenvalues.csv in {}.",
+        cli.output_dir.display()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    analysis::perform_full_analysis(ref_file, individuals_files);
+    #[test]
+    fn threads_flag_is_parsed() {
+        let args = ["pca", "-r", "ref.fa", "-i", "a.fa", "b.fa"];
+        assert_eq!(Cli::try_parse_from(args).unwrap().threads, 0);
+        let cli = Cli::try_parse_from(args.iter().chain(&["--threads", "3"])).unwrap();
+        assert_eq!(cli.threads, 3);
+        assert!(Cli::try_parse_from(args.iter().chain(&["--threads", "x"])).is_err());
+    }
 
-    println!("Analysis complete. Check results.csv and eigenvalues.csv.");
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn threads_flag_sizes_the_global_pool() {
+        // The only test in this binary that touches rayon, so the pool is not built yet
+        pca_population_strat::utils::configure_threads(3).unwrap();
+        assert_eq!(rayon::current_num_threads(), 3);
+    }
 }
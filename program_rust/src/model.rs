@@ -0,0 +1,185 @@
+/*!
+ * @file model.rs
+ * @brief A fitted PCA model that can be saved, reloaded and used to project new samples.
+ *
+ * Author: Namir Garib
+ * Created: January 2025
+ */
+
+use crate::error::PcaError;
+use crate::pca::PCAResult;
+use crate::utils::write_atomically;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+/**
+ * @struct PCAModel
+ * @brief Everything needed to place a new sample on fitted components.
+ */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PCAModel {
+    /// Training mean of each fitted column.
+    pub means: Vec<f64>,
+    /// Eigenvectors stored row-wise (component * dimension + col).
+    pub eigenvectors: Vec<f64>,
+    /// Eigenvalues in descending order.
+    pub eigenvalues: Vec<f64>,
+    pub num_components: usize,
+    pub dimension: usize,
+    /// Genome position of each fitted column, to select them from a raw call vector.
+    pub positions: Vec<usize>,
+}
+
+impl PCAModel {
+    /**
+     * @brief Capture a fit: its components, training means and column positions.
+     */
+    pub fn new(result: &PCAResult, means: &[f64], positions: &[usize]) -> Self {
+        PCAModel {
+            means: means.to_vec(),
+            eigenvectors: result.eigenvectors.clone(),
+            eigenvalues: result.eigenvalues.clone(),
+            num_components: result.num_components,
+            dimension: result.dimension,
+            positions: positions.to_vec(),
+        }
+    }
+
+    /**
+     * @brief Scores of a sample given as one value per fitted column.
+     *
+     * @param sample Length `dimension`; centered with the training means.
+     * @return num_components scores, as project_new_sample would give, or
+     *         InvalidInput if the sample's length is not `dimension`.
+     */
+    pub fn project(&self, sample: &[f64]) -> Result<Vec<f64>, PcaError> {
+        let d = self.dimension;
+        if sample.len() != d {
+            return Err(PcaError::InvalidInput(format!(
+                "Sample has {} values but the model was fitted on {} columns.",
+                sample.len(),
+                d
+            )));
+        }
+        Ok((0..self.num_components)
+            .map(|comp| {
+                sample
+                    .iter()
+                    .zip(self.means.iter())
+                    .zip(self.eigenvectors[comp * d..(comp + 1) * d].iter())
+                    .map(|((x, m), v)| (x - m) * v)
+                    .sum()
+            })
+            .collect())
+    }
+
+    /**
+     * @brief Scores of a sample given as a full-genome call vector (see call_variants).
+     *
+     * Only the fitted `positions` are read; InvalidInput if the vector is too
+     * short to hold them all (e.g. it comes from a shorter genome).
+     */
+    pub fn project_genome_calls(&self, calls: &[f64]) -> Result<Vec<f64>, PcaError> {
+        let needed = self.positions.iter().max().map_or(0, |&pos| pos + 1);
+        if calls.len() < needed {
+            return Err(PcaError::InvalidInput(format!(
+                "Call vector has {} positions but the model reads position {}.",
+                calls.len(),
+                needed - 1
+            )));
+        }
+        let sample: Vec<f64> = self.positions.iter().map(|&pos| calls[pos]).collect();
+        self.project(&sample)
+    }
+
+    /**
+     * @brief Write the model as JSON.
+     */
+    pub fn save_model(&self, path: &str) -> Result<(), PcaError> {
+        write_atomically(Path::new(path), |writer| {
+            serde_json::to_writer(writer, self).map_err(std::io::Error::from)
+        })
+        .map_err(|e| PcaError::io(path, e))
+    }
+
+    /**
+     * @brief Read a model written by save_model.
+     */
+    pub fn load_model(path: &str) -> Result<PCAModel, PcaError> {
+        let file = File::open(path).map_err(|e| PcaError::io(path, e))?;
+        let model: PCAModel = serde_json::from_reader(BufReader::new(file))
+            .map_err(|e| PcaError::Parse(format!("{}: {}", path, e)))?;
+        if model.means.len() != model.dimension
+            || model.positions.len() != model.dimension
+            || model.eigenvectors.len() != model.num_components * model.dimension
+        {
+            return Err(PcaError::Parse(format!(
+                "{}: model dimensions are inconsistent",
+                path
+            )));
+        }
+        Ok(model)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pca::{center_data, compute_covariance_matrix, eigen_decomposition, project_data};
+
+    /// A model fitted on 4 samples of 3 columns, read from genome positions 1, 3 and 4.
+    fn fitted() -> (PCAModel, Vec<f64>) {
+        let data = [
+            0.0, 1.0, 1.0, //
+            1.0, 0.0, 1.0, //
+            1.0, 1.0, 0.0, //
+            0.0, 0.0, 1.0,
+        ];
+        let centered = center_data(&data, 4, 3);
+        let means: Vec<f64> = (0..3)
+            .map(|col| (0..4).map(|row| data[row * 3 + col]).sum::<f64>() / 4.0)
+            .collect();
+        let mut result = eigen_decomposition(&compute_covariance_matrix(&centered, 4, 3), 3);
+        result.truncate(2);
+        let scores = project_data(&centered, 4, 3, &result);
+        (PCAModel::new(&result, &means, &[1, 3, 4]), scores)
+    }
+
+    #[test]
+    fn reloaded_model_projects_identically() {
+        let (model, scores) = fitted();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("model.json");
+        let path = path.to_str().unwrap();
+        model.save_model(path).unwrap();
+        let loaded = PCAModel::load_model(path).unwrap();
+        assert_eq!(loaded, model);
+
+        let sample = [1.0, 0.0, 1.0];
+        assert_eq!(
+            loaded.project(&sample).unwrap(),
+            model.project(&sample).unwrap()
+        );
+        assert_eq!(model.project(&sample).unwrap(), scores[2..4].to_vec());
+        let calls = [9.0, 1.0, 9.0, 0.0, 1.0, 9.0];
+        assert_eq!(
+            loaded.project_genome_calls(&calls).unwrap(),
+            model.project(&sample).unwrap()
+        );
+    }
+
+    #[test]
+    fn wrong_lengths_are_errors() {
+        let (model, _) = fitted();
+        assert!(matches!(
+            model.project(&[1.0, 0.0]),
+            Err(PcaError::InvalidInput(_))
+        ));
+        assert!(matches!(
+            model.project_genome_calls(&[0.0, 1.0, 0.0, 1.0]),
+            Err(PcaError::InvalidInput(_))
+        ));
+    }
+}
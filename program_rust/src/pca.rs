@@ -6,8 +6,15 @@
  * Created: January 2025
  */
 
+use crate::error::PcaError;
+use crate::progress::Progress;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use std::f64;
 
+/// Relative gap below which two loadings count as tied in PCAResult::stabilize_signs.
+pub const SIGN_PIVOT_TOLERANCE: f64 = 1e-6;
+
 /**
  * @struct PCAResult
  * @brief Container for PCA results (eigenvalues, eigenvectors).
@@ -19,19 +26,209 @@ pub struct PCAResult {
     pub dimension: usize,
 }
 
+impl PCAResult {
+    /**
+     * @brief Fraction of the total variance captured by each component.
+     *
+     * @return Each eigenvalue divided by the sum of all stored eigenvalues
+     *         (all zeros if the total is not positive).
+     */
+    pub fn explained_variance_ratio(&self) -> Vec<f64> {
+        let total: f64 = self.eigenvalues.iter().sum();
+        if total <= 0.0 {
+            return vec![0.0; self.eigenvalues.len()];
+        }
+        self.eigenvalues.iter().map(|&val| val / total).collect()
+    }
+
+    /**
+     * @brief The loading matrix transposed to d x num_components (row-major).
+     *
+     * `eigenvectors` stores one component per row (num_components x d, entry
+     * `comp * d + col`), which is what project_data walks. Most consumers,
+     * e.g. numpy via `np.asarray(v).reshape(d, k)`, expect one SNP per row and
+     * one component per column instead: entry `col * num_components + comp`.
+     */
+    pub fn eigenvectors_as_columns(&self) -> Vec<f64> {
+        transpose(
+            &self.eigenvectors[..self.num_components * self.dimension],
+            self.num_components,
+            self.dimension,
+        )
+    }
+
+    /**
+     * @brief Keep only the first k eigenvectors.
+     *
+     * Eigenvalues are left untouched so explained_variance_ratio() still
+     * reports fractions of the total variance.
+     */
+    pub fn truncate(&mut self, k: usize) {
+        let k = k.min(self.num_components);
+        self.eigenvectors.truncate(k * self.dimension);
+        self.num_components = k;
+    }
+
+    /**
+     * @brief Flip each eigenvector so its largest-magnitude loading is positive.
+     *
+     * Eigenvector signs are arbitrary, so without this the scores of the same
+     * data can change sign between solvers or runs. Ties go to the first entry,
+     * and loadings within SIGN_PIVOT_TOLERANCE (relative) of the largest count
+     * as tied: identical or complementary SNP columns give loadings of equal
+     * magnitude, and solvers that round differently must still pick the same one.
+     */
+    pub fn stabilize_signs(&mut self) {
+        let d = self.dimension;
+        if d == 0 {
+            return;
+        }
+        for vector in self.eigenvectors.chunks_exact_mut(d) {
+            let largest = vector.iter().fold(0.0f64, |acc, val| acc.max(val.abs()));
+            let threshold = largest * (1.0 - SIGN_PIVOT_TOLERANCE);
+            let pivot = vector
+                .iter()
+                .copied()
+                .find(|val| val.abs() >= threshold)
+                .unwrap_or(0.0);
+            if pivot < 0.0 {
+                for val in vector.iter_mut() {
+                    *val = -*val;
+                }
+            }
+        }
+    }
+
+    /**
+     * @brief Running sum of explained_variance_ratio().
+     */
+    pub fn cumulative_variance(&self) -> Vec<f64> {
+        let mut running = 0.0;
+        self.explained_variance_ratio()
+            .iter()
+            .map(|&ratio| {
+                running += ratio;
+                running
+            })
+            .collect()
+    }
+
+    /**
+     * @brief Suggested number of components from the scree curve's elbow.
+     *
+     * @return The 1-based component farthest from the straight line joining
+     *         the first and last eigenvalue (0 if there are no eigenvalues).
+     *
+     * Both axes are rescaled to [0, 1] first, so the answer does not depend on
+     * the eigenvalue units. A flat or two-point spectrum yields 1.
+     */
+    pub fn elbow_component(&self) -> usize {
+        let m = self.eigenvalues.len();
+        if m < 3 {
+            return m.min(1);
+        }
+        let first = self.eigenvalues[0];
+        let last = self.eigenvalues[m - 1];
+        if first - last <= f64::EPSILON {
+            return 1;
+        }
+
+        // After rescaling the line runs from (0, 1) to (1, 0): distance ~ |x + y - 1|
+        let mut best = 0;
+        let mut best_distance = 0.0;
+        for (i, &val) in self.eigenvalues.iter().enumerate() {
+            let x = i as f64 / (m - 1) as f64;
+            let y = (val - last) / (first - last);
+            let distance = (1.0 - x - y).abs();
+            if distance > best_distance {
+                best = i;
+                best_distance = distance;
+            }
+        }
+        best + 1
+    }
+
+    /**
+     * @brief Tolerance below which an eigenvalue counts as zero: 1e-10 times
+     *        the largest eigenvalue magnitude.
+     *
+     * Round-off and the iterative solvers' stopping rules leave "zero"
+     * eigenvalues of a rank-deficient matrix around 1e-12 of the largest
+     * rather than exactly 0; 1e-10 sits safely above that.
+     */
+    pub fn rank_tolerance(&self) -> f64 {
+        let largest = self
+            .eigenvalues
+            .iter()
+            .fold(0.0_f64, |acc, val| acc.max(val.abs()));
+        largest * 1e-10
+    }
+
+    /**
+     * @brief Numerical rank: how many eigenvalues exceed rank_tolerance().
+     *
+     * A covariance of n samples has rank at most n - 1 (and at most d), so
+     * with n > d or few samples, components past the rank are pure noise.
+     */
+    pub fn numerical_rank(&self) -> usize {
+        self.numerical_rank_with(self.rank_tolerance())
+    }
+
+    /**
+     * @brief Number of eigenvalues strictly greater than `tolerance`.
+     */
+    pub fn numerical_rank_with(&self, tolerance: f64) -> usize {
+        self.eigenvalues
+            .iter()
+            .filter(|&&val| val > tolerance)
+            .count()
+    }
+}
+
 /**
- * @brief Center data column-wise (subtract mean from each column).
+ * @brief Replace missing (NaN) entries with their column mean, in place.
  *
  * @param data   Data in row-major format: n x d
  * @param n      Number of samples
  * @param d      Dimension (number of SNP positions)
- * @return A new Vec<f64> containing the centered data.
+ *
+ * The mean is taken over the non-missing entries of each column (smartpca-style
+ * imputation). A column with no observed values is filled with 0. Must run
+ * before centering, otherwise NaN spreads through the covariance.
  */
-pub fn center_data(data: &Vec<f64>, n: usize, d: usize) -> Vec<f64> {
-    let mut centered = vec![0.0; n * d];
-    let mut means = vec![0.0; d];
+pub fn impute_column_means(data: &mut [f64], n: usize, d: usize) {
+    for col in 0..d {
+        let mut sum = 0.0;
+        let mut count = 0usize;
+        for row in 0..n {
+            let val = data[row * d + col];
+            if !val.is_nan() {
+                sum += val;
+                count += 1;
+            }
+        }
+        if count == n {
+            continue;
+        }
+        let mean = if count > 0 { sum / (count as f64) } else { 0.0 };
+        for row in 0..n {
+            if data[row * d + col].is_nan() {
+                data[row * d + col] = mean;
+            }
+        }
+    }
+}
 
-    // Compute column means
+/**
+ * @brief Mean of each column.
+ *
+ * @param data   Data in row-major format: n x d
+ * @param n      Number of samples
+ * @param d      Dimension (number of SNP positions)
+ * @return A Vec<f64> of length d.
+ */
+pub fn column_means(data: &[f64], n: usize, d: usize) -> Vec<f64> {
+    let mut means = vec![0.0; d];
     for col in 0..d {
         let mut sum = 0.0;
         for row in 0..n {
@@ -39,6 +236,20 @@ pub fn center_data(data: &Vec<f64>, n: usize, d: usize) -> Vec<f64> {
         }
         means[col] = sum / (n as f64);
     }
+    means
+}
+
+/**
+ * @brief Center data column-wise (subtract mean from each column).
+ *
+ * @param data   Data in row-major format: n x d
+ * @param n      Number of samples
+ * @param d      Dimension (number of SNP positions)
+ * @return A new Vec<f64> containing the centered data.
+ */
+pub fn center_data(data: &[f64], n: usize, d: usize) -> Vec<f64> {
+    let mut centered = vec![0.0; n * d];
+    let means = column_means(data, n, d);
 
     // Subtract means
     for row in 0..n {
@@ -50,6 +261,107 @@ pub fn center_data(data: &Vec<f64>, n: usize, d: usize) -> Vec<f64> {
     centered
 }
 
+/**
+ * @brief Weighted mean of each column, sum_k w_k x_k / sum_k w_k.
+ *
+ * @param data    Data in row-major format: n x d
+ * @param n       Number of samples
+ * @param d       Dimension (number of SNP positions)
+ * @param weights One non-negative weight per sample, with a positive sum.
+ * @return A Vec<f64> of length d; all-ones weights give exactly column_means.
+ */
+pub fn column_means_weighted(data: &[f64], n: usize, d: usize, weights: &[f64]) -> Vec<f64> {
+    assert_eq!(weights.len(), n, "one weight per sample");
+    let total: f64 = weights.iter().sum();
+    let mut means = vec![0.0; d];
+    for col in 0..d {
+        let mut sum = 0.0;
+        for row in 0..n {
+            sum += weights[row] * data[row * d + col];
+        }
+        means[col] = sum / total;
+    }
+    means
+}
+
+/**
+ * @brief Center data column-wise on the weighted column means.
+ *
+ * @param data    Data in row-major format: n x d
+ * @param n       Number of samples
+ * @param d       Dimension (number of SNP positions)
+ * @param weights One weight per sample (see column_means_weighted).
+ * @return (centered data, the means that were subtracted).
+ */
+pub fn center_data_weighted(
+    data: &[f64],
+    n: usize,
+    d: usize,
+    weights: &[f64],
+) -> (Vec<f64>, Vec<f64>) {
+    let means = column_means_weighted(data, n, d, weights);
+    let mut centered = data[..n * d].to_vec();
+    for row in centered.chunks_mut(d.max(1)) {
+        for (val, mean) in row.iter_mut().zip(means.iter()) {
+            *val -= mean;
+        }
+    }
+    (centered, means)
+}
+
+/**
+ * @brief Center data column-wise without allocating a second n x d buffer.
+ *
+ * @param data   Data in row-major format: n x d (overwritten with the centered values).
+ * @param n      Number of samples
+ * @param d      Dimension (number of SNP positions)
+ * @return The column means that were subtracted, e.g. for project_new_sample.
+ *
+ * Gives the same values as center_data.
+ */
+pub fn center_data_in_place(data: &mut [f64], n: usize, d: usize) -> Vec<f64> {
+    let means = column_means(data, n, d);
+    for row in data.chunks_mut(d.max(1)).take(n) {
+        for (val, mean) in row.iter_mut().zip(means.iter()) {
+            *val -= mean;
+        }
+    }
+    means
+}
+
+/**
+ * @brief Standardize data column-wise (z-score: center, then divide by stddev).
+ *
+ * @param data   Data in row-major format: n x d
+ * @param n      Number of samples
+ * @param d      Dimension (number of SNP positions)
+ * @return A new Vec<f64> containing the standardized data.
+ *
+ * Uses the sample standard deviation (n - 1). Zero-variance columns are left
+ * at zero instead of producing NaN.
+ */
+pub fn standardize_data(data: &[f64], n: usize, d: usize) -> Vec<f64> {
+    let mut standardized = center_data(data, n, d);
+    let denom = if n > 1 { (n - 1) as f64 } else { 1.0 };
+
+    for col in 0..d {
+        let mut sum_sq = 0.0;
+        for row in 0..n {
+            let val = standardized[row * d + col];
+            sum_sq += val * val;
+        }
+        let std_dev = (sum_sq / denom).sqrt();
+        if std_dev <= f64::EPSILON {
+            continue;
+        }
+        for row in 0..n {
+            standardized[row * d + col] /= std_dev;
+        }
+    }
+
+    standardized
+}
+
 /**
  * @brief Compute covariance matrix (d x d).
  *
@@ -57,84 +369,1913 @@ pub fn center_data(data: &Vec<f64>, n: usize, d: usize) -> Vec<f64> {
  * @param n             Number of samples.
  * @param d             Dimension.
  * @return Vec<f64>     A new vector storing the covariance matrix in row-major order.
+ *
+ * Only the upper triangle (j >= i) is summed; each value is mirrored into the
+ * lower triangle. Floating-point products commute, so the result is identical
+ * to summing both triangles. Requires n >= 2 (the divisor is n - 1).
  */
-pub fn compute_covariance_matrix(centered_data: &Vec<f64>, n: usize, d: usize) -> Vec<f64> {
+pub fn compute_covariance_matrix(centered_data: &[f64], n: usize, d: usize) -> Vec<f64> {
+    debug_assert!(n > 1, "covariance needs at least 2 samples");
     let mut cov = vec![0.0; d * d];
     for i in 0..d {
-        for j in 0..d {
+        for j in i..d {
             let mut sum = 0.0;
             for k in 0..n {
                 sum += centered_data[k * d + i] * centered_data[k * d + j];
             }
-            cov[i * d + j] = sum / ((n - 1) as f64);
+            let val = sum / ((n - 1) as f64);
+            cov[i * d + j] = val;
+            cov[j * d + i] = val;
         }
     }
     cov
 }
 
 /**
- * @brief Naive eigen decomposition for demonstration.
- *        In real usage, use a numeric library for large d.
+ * @brief Covariance matrix (d x d) straight from uncentered data in one pass.
+ *
+ * @param data Data in row-major format: n x d (not centered).
+ * @param n    Number of samples.
+ * @param d    Dimension.
+ * @return Vec<f64> The covariance matrix in row-major order, equal to
+ *         compute_covariance_matrix(&center_data(data, n, d), n, d) up to
+ *         rounding.
+ *
+ * Accumulates the column sums S_i and cross-products S_ij row by row and
+ * returns (S_ij - S_i S_j / n) / (n - 1), so neither the means nor a centered
+ * copy of the data are needed first. The price is cancellation: when a
+ * column's mean is large next to its spread, S_ij and S_i S_j / n agree in
+ * their leading digits and the difference loses that many. For 0/1 calls the
+ * error stays near machine epsilon times n; for data with large offsets, or
+ * where accuracy matters more than the saved pass, center first.
+ * Requires n >= 2.
  */
-pub fn eigen_decomposition(cov_matrix: &Vec<f64>, d: usize) -> PCAResult {
-    let mut eigenvectors = cov_matrix.clone();
-    let mut eigenvalues = vec![0.0; d];
+pub fn covariance_from_raw(data: &[f64], n: usize, d: usize) -> Vec<f64> {
+    debug_assert!(n > 1, "covariance needs at least 2 samples");
+    let mut sums = vec![0.0; d];
+    let mut products = vec![0.0; d * d];
+    for row in data.chunks(d.max(1)).take(n) {
+        for i in 0..d {
+            let xi = row[i];
+            sums[i] += xi;
+            // Upper triangle only; mirrored below
+            for j in i..d {
+                products[i * d + j] += xi * row[j];
+            }
+        }
+    }
 
-    // Pretend diagonal are eigenvalues
+    let nf = n as f64;
     for i in 0..d {
-        eigenvalues[i] = eigenvectors[i * d + i];
+        for j in i..d {
+            let val = (products[i * d + j] - sums[i] * sums[j] / nf) / (nf - 1.0);
+            products[i * d + j] = val;
+            products[j * d + i] = val;
+        }
     }
+    products
+}
 
-    // Sort in descending order (bubble sort demonstration)
-    for i in 0..(d - 1) {
-        for j in 0..(d - i - 1) {
-            if eigenvalues[j] < eigenvalues[j + 1] {
-                eigenvalues.swap(j, j + 1);
-                // swap the entire row in eigenvectors
-                for col in 0..d {
-                    let idx1 = j * d + col;
-                    let idx2 = (j + 1) * d + col;
-                    eigenvectors.swap(idx1, idx2);
-                }
+/**
+ * @brief Weighted covariance matrix (d x d): sum_k w_k x_ki x_kj / (sum_k w_k - 1).
+ *
+ * @param centered_data Data centered with center_data_weighted (n x d).
+ * @param n             Number of samples.
+ * @param d             Dimension.
+ * @param weights       One weight per sample, read as frequency weights
+ *                      (a weight of 2 counts a sample twice).
+ * @return Vec<f64>     The covariance matrix in row-major order; all-ones
+ *                      weights give exactly compute_covariance_matrix.
+ *
+ * Requires sum_k w_k > 1.
+ */
+pub fn compute_covariance_matrix_weighted(
+    centered_data: &[f64],
+    n: usize,
+    d: usize,
+    weights: &[f64],
+) -> Vec<f64> {
+    assert_eq!(weights.len(), n, "one weight per sample");
+    let denom = weights.iter().sum::<f64>() - 1.0;
+    debug_assert!(
+        denom > 0.0,
+        "weighted covariance needs a total weight above 1"
+    );
+    let mut cov = vec![0.0; d * d];
+    for i in 0..d {
+        for j in i..d {
+            let mut sum = 0.0;
+            for k in 0..n {
+                sum += weights[k] * centered_data[k * d + i] * centered_data[k * d + j];
             }
+            let val = sum / denom;
+            cov[i * d + j] = val;
+            cov[j * d + i] = val;
         }
     }
+    cov
+}
 
-    PCAResult {
-        eigenvalues,
-        eigenvectors,
-        num_components: d,
-        dimension: d,
+/**
+ * @brief Compute covariance matrix (d x d) tile by tile.
+ *
+ * @param centered_data The centered data (n x d).
+ * @param n             Number of samples.
+ * @param d             Dimension.
+ * @param block         Tile edge length (clamped to at least 1).
+ * @return Vec<f64>     The covariance matrix in row-major order, bitwise
+ *                      identical to compute_covariance_matrix.
+ *
+ * Assembles the tiles of for_each_covariance_tile, mirroring each one into the
+ * lower triangle. Reads the row-major data directly, so unlike the
+ * column-major path no transposed copy of the data is made.
+ */
+pub fn compute_covariance_matrix_blocked(
+    centered_data: &[f64],
+    n: usize,
+    d: usize,
+    block: usize,
+) -> Vec<f64> {
+    compute_covariance_matrix_blocked_reporting(
+        centered_data,
+        n,
+        d,
+        block,
+        &mut Progress::disabled(),
+    )
+}
+
+/// Tile edge the pipeline uses with compute_covariance_matrix_blocked: two
+/// 64-wide runs of a row fit comfortably in L1.
+pub const COVARIANCE_BLOCK: usize = 64;
+
+/**
+ * @brief compute_covariance_matrix_blocked, ticking `progress` once per
+ *        covariance row as each band of `block` rows is completed.
+ */
+pub fn compute_covariance_matrix_blocked_reporting(
+    centered_data: &[f64],
+    n: usize,
+    d: usize,
+    block: usize,
+    progress: &mut Progress,
+) -> Vec<f64> {
+    let mut cov = vec![0.0; d * d];
+    for_each_covariance_tile(centered_data, n, d, block, |rows, cols, tile| {
+        let width = cols.len();
+        for (ti, i) in rows.clone().enumerate() {
+            for (tj, j) in cols.clone().enumerate() {
+                cov[i * d + j] = tile[ti * width + tj];
+                cov[j * d + i] = tile[ti * width + tj];
+            }
+        }
+        if cols.end == d {
+            progress.inc(rows.len());
+        }
+    });
+    progress.finish();
+    cov
+}
+
+/**
+ * @brief Compute the covariance matrix in block x block tiles and hand each to `visit`.
+ *
+ * @param centered_data The centered data (n x d).
+ * @param n             Number of samples.
+ * @param d             Dimension.
+ * @param block         Tile edge length (clamped to at least 1).
+ * @param visit         Called as visit(rows, cols, tile) for every tile on or
+ *                      above the diagonal, where tile holds
+ *                      cov[rows] x [cols] row-major.
+ *
+ * Only one tile is held at a time, so the full d x d matrix never has to exist
+ * (e.g. `visit` may write tiles to disk). Each tile reads two contiguous
+ * runs of every data row, which keeps them in cache for large d. Every entry
+ * is summed over samples in order, as compute_covariance_matrix does.
+ */
+pub fn for_each_covariance_tile<F>(
+    centered_data: &[f64],
+    n: usize,
+    d: usize,
+    block: usize,
+    mut visit: F,
+) where
+    F: FnMut(std::ops::Range<usize>, std::ops::Range<usize>, &[f64]),
+{
+    debug_assert!(n > 1, "covariance needs at least 2 samples");
+    let block = block.max(1);
+    let mut tile = Vec::with_capacity(block * block);
+    for row_start in (0..d).step_by(block) {
+        let rows = row_start..(row_start + block).min(d);
+        for col_start in (row_start..d).step_by(block) {
+            let cols = col_start..(col_start + block).min(d);
+            let width = cols.len();
+            tile.clear();
+            tile.resize(rows.len() * width, 0.0);
+            for k in 0..n {
+                let sample = &centered_data[k * d..(k + 1) * d];
+                for (ti, &x) in sample[rows.clone()].iter().enumerate() {
+                    let out = &mut tile[ti * width..(ti + 1) * width];
+                    for (acc, &y) in out.iter_mut().zip(&sample[cols.clone()]) {
+                        *acc += x * y;
+                    }
+                }
+            }
+            for val in tile.iter_mut() {
+                *val /= (n - 1) as f64;
+            }
+            visit(rows.clone(), cols, &tile);
+        }
     }
 }
 
 /**
- * @brief Project data onto the principal components.
+ * @brief Compute the correlation matrix (d x d) of centered data.
  *
  * @param centered_data The centered data (n x d).
  * @param n             Number of samples.
  * @param d             Dimension.
- * @param pca_result    Contains eigenvectors and eigenvalues.
- * @return Vec<f64>     The projected data (n x num_components).
+ * @return Vec<f64>     The correlation matrix in row-major order.
+ *
+ * Zero-variance columns get an all-zero row and column (including the
+ * diagonal) instead of NaN.
+ */
+pub fn compute_correlation_matrix(centered_data: &[f64], n: usize, d: usize) -> Vec<f64> {
+    let mut cov = compute_covariance_matrix(centered_data, n, d);
+    covariance_to_correlation(&mut cov, d);
+    cov
+}
+
+/**
+ * @brief Rescale a covariance matrix (d x d) into a correlation matrix, in place.
+ *
+ * Entry (i, j) is divided by sd_i * sd_j; rows and columns of zero-variance
+ * columns are set to 0.
+ */
+pub fn covariance_to_correlation(cov: &mut [f64], d: usize) {
+    let std_devs: Vec<f64> = (0..d).map(|i| cov[i * d + i].sqrt()).collect();
+    for i in 0..d {
+        for j in 0..d {
+            let denom = std_devs[i] * std_devs[j];
+            cov[i * d + j] = if denom > f64::EPSILON {
+                cov[i * d + j] / denom
+            } else {
+                0.0
+            };
+        }
+    }
+}
+
+/**
+ * @brief Transpose a row-major n x d matrix into row-major d x n (i.e. column-major n x d).
+ *
+ * @param data Data in row-major format: n x d
+ * @param n    Number of rows
+ * @param d    Number of columns
+ * @return A new Vec<f64> in which each original column is contiguous.
+ */
+pub fn transpose(data: &[f64], n: usize, d: usize) -> Vec<f64> {
+    let mut transposed = vec![0.0; n * d];
+    for row in 0..n {
+        for col in 0..d {
+            transposed[col * n + row] = data[row * d + col];
+        }
+    }
+    transposed
+}
+
+/**
+ * @brief Compute covariance matrix (d x d) from column-major centered data.
+ *
+ * @param centered_columns The centered data stored column-major (d x n, see transpose).
+ * @param n                Number of samples.
+ * @param d                Dimension.
+ * @return Vec<f64>        The covariance matrix in row-major order.
+ *
+ * The inner accumulation walks two contiguous columns instead of striding by d,
+ * which is far friendlier to the cache for large d. Products are summed in the
+ * same order as compute_covariance_matrix, so the result is bitwise identical.
  */
-pub fn project_data(
-    centered_data: &Vec<f64>,
+pub fn compute_covariance_matrix_column_major(
+    centered_columns: &[f64],
     n: usize,
     d: usize,
-    pca_result: &PCAResult,
 ) -> Vec<f64> {
-    let dims_to_use = pca_result.num_components;
-    let mut projections = vec![0.0; n * dims_to_use];
+    compute_covariance_matrix_column_major_reporting(
+        centered_columns,
+        n,
+        d,
+        &mut Progress::disabled(),
+    )
+}
 
-    for row in 0..n {
-        for comp in 0..dims_to_use {
+/**
+ * @brief compute_covariance_matrix_column_major, ticking `progress` once per row.
+ */
+pub fn compute_covariance_matrix_column_major_reporting(
+    centered_columns: &[f64],
+    n: usize,
+    d: usize,
+    progress: &mut Progress,
+) -> Vec<f64> {
+    debug_assert!(n > 1, "covariance needs at least 2 samples");
+    let mut cov = vec![0.0; d * d];
+    for i in 0..d {
+        let col_i = &centered_columns[i * n..(i + 1) * n];
+        for j in i..d {
+            let col_j = &centered_columns[j * n..(j + 1) * n];
             let mut sum = 0.0;
-            for col in 0..d {
-                sum += centered_data[row * d + col] * pca_result.eigenvectors[comp * d + col];
+            for k in 0..n {
+                sum += col_i[k] * col_j[k];
             }
-            projections[row * dims_to_use + comp] = sum;
+            let val = sum / ((n - 1) as f64);
+            cov[i * d + j] = val;
+            cov[j * d + i] = val;
         }
+        progress.inc(1);
     }
+    progress.finish();
+    cov
+}
 
-    projections
+/**
+ * @brief Compute covariance matrix (d x d) with rows spread over rayon threads.
+ *
+ * @param centered_data The centered data (n x d).
+ * @param n             Number of samples.
+ * @param d             Dimension.
+ * @return Vec<f64>     The covariance matrix in row-major order.
+ *
+ * Each thread fills the upper triangle (j >= i) of its own rows; the lower
+ * triangle is mirrored afterwards. Every entry is summed in the same order as
+ * compute_covariance_matrix, so the result is bitwise identical.
+ */
+#[cfg(feature = "rayon")]
+pub fn compute_covariance_matrix_parallel(centered_data: &[f64], n: usize, d: usize) -> Vec<f64> {
+    debug_assert!(n > 1, "covariance needs at least 2 samples");
+    use rayon::prelude::*;
+
+    let mut cov = vec![0.0; d * d];
+    cov.par_chunks_mut(d.max(1))
+        .enumerate()
+        .for_each(|(i, row)| {
+            for j in i..d {
+                let mut sum = 0.0;
+                for k in 0..n {
+                    sum += centered_data[k * d + i] * centered_data[k * d + j];
+                }
+                row[j] = sum / ((n - 1) as f64);
+            }
+        });
+
+    for i in 0..d {
+        for j in 0..i {
+            cov[i * d + j] = cov[j * d + i];
+        }
+    }
+    cov
+}
+
+/**
+ * @brief Compute covariance matrix (d x d) as X^T X / (n - 1) with one nalgebra GEMM.
+ *
+ * @param centered_data The centered data (n x d).
+ * @param n             Number of samples.
+ * @param d             Dimension.
+ * @return Vec<f64>     The covariance matrix in row-major order.
+ *
+ * Agrees with compute_covariance_matrix to rounding; the blocked multiply sums
+ * in a different order, so results are close but not bitwise identical.
+ */
+#[cfg(feature = "nalgebra")]
+pub fn compute_covariance_matrix_nalgebra(centered_data: &[f64], n: usize, d: usize) -> Vec<f64> {
+    debug_assert!(n > 1, "covariance needs at least 2 samples");
+    let x = nalgebra::DMatrix::from_row_slice(n, d, centered_data);
+    let cov = x.tr_mul(&x) / ((n - 1) as f64);
+
+    // nalgebra is column-major; read back the upper triangle and mirror it
+    let mut out = vec![0.0; d * d];
+    for i in 0..d {
+        for j in i..d {
+            out[i * d + j] = cov[(i, j)];
+            out[j * d + i] = cov[(i, j)];
+        }
+    }
+    out
+}
+
+/**
+ * @struct MeanAccumulator
+ * @brief One-pass, numerically stable per-column means (Welford's update).
+ *
+ * Each sample moves the running mean by (x - mean) / count, so the large
+ * partial sums of the naive method, and their cancellation error, never appear.
+ */
+pub struct MeanAccumulator {
+    count: usize,
+    means: Vec<f64>,
+}
+
+impl MeanAccumulator {
+    pub fn new(d: usize) -> Self {
+        MeanAccumulator {
+            count: 0,
+            means: vec![0.0; d],
+        }
+    }
+
+    /**
+     * @brief Fold one sample (length d) into the running means.
+     */
+    pub fn add_sample(&mut self, sample: &[f64]) {
+        self.count += 1;
+        let n = self.count as f64;
+        for (mean, x) in self.means.iter_mut().zip(sample.iter()) {
+            *mean += (x - *mean) / n;
+        }
+    }
+
+    /// Number of samples folded in so far.
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Running column means.
+    pub fn means(&self) -> &[f64] {
+        &self.means
+    }
+}
+
+/**
+ * @struct CovarianceAccumulator
+ * @brief One-sample-at-a-time column means and co-moments (d x d).
+ *
+ * Uses the online (Welford-style) co-moment update, so the n x d data matrix
+ * never has to be held in memory; only the d means and d x d co-moments are.
+ */
+pub struct CovarianceAccumulator {
+    dimension: usize,
+    means: MeanAccumulator,
+    comoments: Vec<f64>,
+    // Scratch rows reused across samples, so adding one does not allocate
+    delta: Vec<f64>,
+    residual: Vec<f64>,
+    sample: Vec<f64>,
+}
+
+impl CovarianceAccumulator {
+    pub fn new(d: usize) -> Self {
+        CovarianceAccumulator {
+            dimension: d,
+            means: MeanAccumulator::new(d),
+            comoments: vec![0.0; d * d],
+            delta: vec![0.0; d],
+            residual: vec![0.0; d],
+            sample: Vec::with_capacity(d),
+        }
+    }
+
+    /**
+     * @brief Fold one sample (length d) into the running means and co-moments.
+     */
+    pub fn add_sample(&mut self, sample: &[f64]) {
+        let d = self.dimension;
+
+        // delta against the old mean, then the mean update
+        for ((delta, x), m) in self.delta.iter_mut().zip(sample).zip(self.means.means()) {
+            *delta = x - m;
+        }
+        self.means.add_sample(sample);
+
+        // C_ij += (x_i - old_mean_i) * (x_j - new_mean_j), upper triangle only
+        for ((residual, x), m) in self.residual.iter_mut().zip(sample).zip(self.means.means()) {
+            *residual = x - m;
+        }
+        for (i, &dx) in self.delta.iter().enumerate() {
+            if dx == 0.0 {
+                continue;
+            }
+            let row = &mut self.comoments[i * d..(i + 1) * d];
+            for (c, r) in row[i..].iter_mut().zip(self.residual[i..].iter()) {
+                *c += dx * r;
+            }
+        }
+    }
+
+    /**
+     * @brief add_sample for a sample produced lazily, e.g. by call_variants_iter.
+     *
+     * The values are collected into a buffer kept between calls; the iterator
+     * must yield exactly d values.
+     */
+    pub fn add_sample_iter<I: IntoIterator<Item = f64>>(&mut self, sample: I) {
+        let mut buffer = std::mem::take(&mut self.sample);
+        buffer.clear();
+        buffer.extend(sample);
+        debug_assert_eq!(buffer.len(), self.dimension);
+        self.add_sample(&buffer);
+        self.sample = buffer;
+    }
+
+    /// Number of samples folded in so far.
+    pub fn count(&self) -> usize {
+        self.means.count()
+    }
+
+    /// Running column means.
+    pub fn means(&self) -> &[f64] {
+        self.means.means()
+    }
+
+    /**
+     * @brief Sample covariance matrix (d x d, row-major) of everything added so far.
+     */
+    pub fn covariance(&self) -> Vec<f64> {
+        let d = self.dimension;
+        let count = self.count();
+        let denom = if count > 1 { (count - 1) as f64 } else { 1.0 };
+        let mut cov = vec![0.0; d * d];
+        for i in 0..d {
+            for j in i..d {
+                let val = self.comoments[i * d + j] / denom;
+                cov[i * d + j] = val;
+                cov[j * d + i] = val;
+            }
+        }
+        cov
+    }
+}
+
+/**
+ * @brief Indices that sort eigenvalues in descending order.
+ *
+ * Equal eigenvalues keep the solver's original order (ties are broken by
+ * index), so the eigenvector assigned to each component is the same on every
+ * run, bit for bit.
+ */
+fn descending_order(eigenvalues: &[f64]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..eigenvalues.len()).collect();
+    order.sort_by(|&a, &b| {
+        eigenvalues[b]
+            .total_cmp(&eigenvalues[a])
+            .then_with(|| a.cmp(&b))
+    });
+    order
+}
+
+/**
+ * @brief Eigen decomposition of a symmetric matrix using cyclic Jacobi rotations.
+ *
+ * @param cov_matrix The symmetric matrix (d x d), row-major.
+ * @param d          Dimension.
+ * @return PCAResult with eigenvalues in descending order and the matching
+ *         eigenvectors stored row-wise (component * d + col).
+ *
+ * Jacobi is O(d^3) per sweep; for large d prefer top_k_components.
+ * d = 0 gives an empty result and d = 1 the single pair (a, [1]).
+ * Eigenvector signs are fixed by PCAResult::stabilize_signs, as in every solver.
+ */
+pub fn eigen_decomposition(cov_matrix: &[f64], d: usize) -> PCAResult {
+    if d < 2 {
+        return PCAResult {
+            eigenvalues: cov_matrix[..d].to_vec(),
+            eigenvectors: vec![1.0; d],
+            num_components: d,
+            dimension: d,
+        };
+    }
+
+    let mut a = cov_matrix.to_vec();
+    // Rows of `eigenvectors` accumulate the rotations (V^T), starting from identity
+    let mut eigenvectors = vec![0.0; d * d];
+    for i in 0..d {
+        eigenvectors[i * d + i] = 1.0;
+    }
+
+    let max_sweeps = 100;
+    for _ in 0..max_sweeps {
+        let mut off = 0.0;
+        let mut total = 0.0;
+        for i in 0..d {
+            for j in 0..d {
+                let sq = a[i * d + j] * a[i * d + j];
+                total += sq;
+                if i != j {
+                    off += sq;
+                }
+            }
+        }
+        if off <= 1e-24 * total || off == 0.0 {
+            break;
+        }
+
+        for p in 0..d {
+            for q in (p + 1)..d {
+                let apq = a[p * d + q];
+                if apq.abs() < f64::MIN_POSITIVE {
+                    continue;
+                }
+                let theta = (a[q * d + q] - a[p * d + p]) / (2.0 * apq);
+                let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+                let c = 1.0 / (t * t + 1.0).sqrt();
+                let s = t * c;
+
+                // A <- A J (columns p, q)
+                for k in 0..d {
+                    let akp = a[k * d + p];
+                    let akq = a[k * d + q];
+                    a[k * d + p] = c * akp - s * akq;
+                    a[k * d + q] = s * akp + c * akq;
+                }
+                // A <- J^T A (rows p, q)
+                for k in 0..d {
+                    let apk = a[p * d + k];
+                    let aqk = a[q * d + k];
+                    a[p * d + k] = c * apk - s * aqk;
+                    a[q * d + k] = s * apk + c * aqk;
+                }
+                // V <- V J, stored transposed
+                for k in 0..d {
+                    let vpk = eigenvectors[p * d + k];
+                    let vqk = eigenvectors[q * d + k];
+                    eigenvectors[p * d + k] = c * vpk - s * vqk;
+                    eigenvectors[q * d + k] = s * vpk + c * vqk;
+                }
+            }
+        }
+    }
+
+    let diag: Vec<f64> = (0..d).map(|i| a[i * d + i]).collect();
+    let order = descending_order(&diag);
+    let eigenvalues = order.iter().map(|&idx| diag[idx]).collect();
+    let sorted_vectors = order
+        .iter()
+        .flat_map(|&idx| eigenvectors[idx * d..(idx + 1) * d].iter().copied())
+        .collect();
+
+    let mut result = PCAResult {
+        eigenvalues,
+        eigenvectors: sorted_vectors,
+        num_components: d,
+        dimension: d,
+    };
+    result.stabilize_signs();
+    result
+}
+
+/**
+ * @brief Eigen decomposition of a symmetric matrix by Householder
+ *        tridiagonalization followed by the implicit-shift QL algorithm.
+ *
+ * @param cov_matrix The symmetric matrix (d x d), row-major.
+ * @param d          Dimension.
+ * @return PCAResult laid out exactly like eigen_decomposition (descending
+ *         eigenvalues, eigenvectors stored row-wise), or InvalidInput if the
+ *         matrix holds a NaN or infinite entry.
+ *
+ * O(d^3) with a much smaller constant than Jacobi's repeated sweeps, so it is
+ * the better choice once d reaches the hundreds (e.g. dual_pca's n x n matrix).
+ * Follows the tred2/tql2 routines of EISPACK, including their limit of 30 QL
+ * iterations per eigenvalue; should an eigenvalue not converge within it, the
+ * matrix is solved by eigen_decomposition instead.
+ */
+pub fn eigen_decomposition_ql(cov_matrix: &[f64], d: usize) -> Result<PCAResult, PcaError> {
+    const MAX_ITERATIONS: usize = 30;
+    if cov_matrix[..d * d].iter().any(|x| !x.is_finite()) {
+        return Err(PcaError::InvalidInput(
+            "Cannot decompose a matrix with NaN or infinite entries.".to_string(),
+        ));
+    }
+    if d < 2 {
+        return Ok(eigen_decomposition(cov_matrix, d));
+    }
+
+    // v ends up holding the eigenvectors as columns; diag / off hold the
+    // tridiagonal form and then the eigenvalues
+    let mut v = cov_matrix.to_vec();
+    let mut diag = vec![0.0; d];
+    let mut off = vec![0.0; d];
+
+    // Householder reduction to tridiagonal form
+    diag.copy_from_slice(&v[(d - 1) * d..]);
+    for i in (1..d).rev() {
+        let scale: f64 = diag[..i].iter().map(|x| x.abs()).sum();
+        let mut h = 0.0;
+        if scale == 0.0 {
+            off[i] = diag[i - 1];
+            for j in 0..i {
+                diag[j] = v[(i - 1) * d + j];
+                v[i * d + j] = 0.0;
+                v[j * d + i] = 0.0;
+            }
+        } else {
+            for x in diag[..i].iter_mut() {
+                *x /= scale;
+                h += *x * *x;
+            }
+            let f = diag[i - 1];
+            let g = if f > 0.0 { -h.sqrt() } else { h.sqrt() };
+            off[i] = scale * g;
+            h -= f * g;
+            diag[i - 1] = f - g;
+            off[..i].fill(0.0);
+
+            for j in 0..i {
+                let f = diag[j];
+                v[j * d + i] = f;
+                let mut g = off[j] + v[j * d + j] * f;
+                for k in (j + 1)..i {
+                    g += v[k * d + j] * diag[k];
+                    off[k] += v[k * d + j] * f;
+                }
+                off[j] = g;
+            }
+            let mut f = 0.0;
+            for j in 0..i {
+                off[j] /= h;
+                f += off[j] * diag[j];
+            }
+            let hh = f / (h + h);
+            for j in 0..i {
+                off[j] -= hh * diag[j];
+            }
+            for j in 0..i {
+                let f = diag[j];
+                let g = off[j];
+                for k in j..i {
+                    v[k * d + j] -= f * off[k] + g * diag[k];
+                }
+                diag[j] = v[(i - 1) * d + j];
+                v[i * d + j] = 0.0;
+            }
+        }
+        diag[i] = h;
+    }
+
+    // Accumulate the transformations
+    for i in 0..(d - 1) {
+        v[(d - 1) * d + i] = v[i * d + i];
+        v[i * d + i] = 1.0;
+        let h = diag[i + 1];
+        if h != 0.0 {
+            for k in 0..=i {
+                diag[k] = v[k * d + i + 1] / h;
+            }
+            for j in 0..=i {
+                let mut g = 0.0;
+                for k in 0..=i {
+                    g += v[k * d + i + 1] * v[k * d + j];
+                }
+                for k in 0..=i {
+                    v[k * d + j] -= g * diag[k];
+                }
+            }
+        }
+        for k in 0..=i {
+            v[k * d + i + 1] = 0.0;
+        }
+    }
+    for j in 0..d {
+        diag[j] = v[(d - 1) * d + j];
+        v[(d - 1) * d + j] = 0.0;
+    }
+    v[d * d - 1] = 1.0;
+    off[0] = 0.0;
+
+    // Implicit-shift QL on the tridiagonal matrix
+    for i in 1..d {
+        off[i - 1] = off[i];
+    }
+    off[d - 1] = 0.0;
+
+    let mut shift = 0.0;
+    let mut tst1: f64 = 0.0;
+    let eps = f64::EPSILON;
+    for l in 0..d {
+        tst1 = tst1.max(diag[l].abs() + off[l].abs());
+        // off[d - 1] is zero, so this always stops by m = d - 1
+        let mut m = l;
+        while off[m].abs() > eps * tst1 {
+            m += 1;
+        }
+
+        if m > l {
+            let mut iterations = 0;
+            loop {
+                iterations += 1;
+                if iterations > MAX_ITERATIONS {
+                    return Ok(eigen_decomposition(cov_matrix, d));
+                }
+                let g = diag[l];
+                let mut p = (diag[l + 1] - g) / (2.0 * off[l]);
+                let mut r = p.hypot(1.0);
+                if p < 0.0 {
+                    r = -r;
+                }
+                diag[l] = off[l] / (p + r);
+                diag[l + 1] = off[l] * (p + r);
+                let dl1 = diag[l + 1];
+                let mut h = g - diag[l];
+                for x in diag[(l + 2)..].iter_mut() {
+                    *x -= h;
+                }
+                shift += h;
+
+                p = diag[m];
+                let mut c = 1.0;
+                let mut c2 = c;
+                let mut c3 = c;
+                let el1 = off[l + 1];
+                let mut s = 0.0;
+                let mut s2 = 0.0;
+                for i in (l..m).rev() {
+                    c3 = c2;
+                    c2 = c;
+                    s2 = s;
+                    let g = c * off[i];
+                    h = c * p;
+                    r = p.hypot(off[i]);
+                    off[i + 1] = s * r;
+                    s = off[i] / r;
+                    c = p / r;
+                    p = c * diag[i] - s * g;
+                    diag[i + 1] = h + s * (c * g + s * diag[i]);
+                    for k in 0..d {
+                        let h = v[k * d + i + 1];
+                        v[k * d + i + 1] = s * v[k * d + i] + c * h;
+                        v[k * d + i] = c * v[k * d + i] - s * h;
+                    }
+                }
+                p = -s * s2 * c3 * el1 * off[l] / dl1;
+                off[l] = s * p;
+                diag[l] = c * p;
+                if off[l].abs() <= eps * tst1 {
+                    break;
+                }
+            }
+        }
+        diag[l] += shift;
+        off[l] = 0.0;
+    }
+
+    let order = descending_order(&diag);
+
+    let mut eigenvalues = Vec::with_capacity(d);
+    let mut eigenvectors = Vec::with_capacity(d * d);
+    for &idx in &order {
+        eigenvalues.push(diag[idx]);
+        eigenvectors.extend((0..d).map(|row| v[row * d + idx]));
+    }
+
+    let mut result = PCAResult {
+        eigenvalues,
+        eigenvectors,
+        num_components: d,
+        dimension: d,
+    };
+    result.stabilize_signs();
+    Ok(result)
+}
+
+/**
+ * @brief Eigen decomposition of a symmetric matrix with nalgebra's symmetric solver.
+ *
+ * @param cov_matrix The symmetric matrix (d x d), row-major.
+ * @param d          Dimension.
+ * @return PCAResult laid out exactly like eigen_decomposition (descending
+ *         eigenvalues, eigenvectors stored row-wise).
+ */
+#[cfg(feature = "nalgebra")]
+pub fn eigen_decomposition_nalgebra(cov_matrix: &[f64], d: usize) -> PCAResult {
+    let matrix = nalgebra::DMatrix::from_row_slice(d, d, cov_matrix);
+    let eigen = nalgebra::SymmetricEigen::new(matrix);
+
+    let order = descending_order(eigen.eigenvalues.as_slice());
+
+    let mut eigenvalues = Vec::with_capacity(d);
+    let mut eigenvectors = Vec::with_capacity(d * d);
+    for &idx in &order {
+        eigenvalues.push(eigen.eigenvalues[idx]);
+        eigenvectors.extend(eigen.eigenvectors.column(idx).iter());
+    }
+
+    let mut result = PCAResult {
+        eigenvalues,
+        eigenvectors,
+        num_components: d,
+        dimension: d,
+    };
+    result.stabilize_signs();
+    result
+}
+
+/**
+ * @brief Project data onto the principal components.
+ *
+ * @param centered_data The centered data (n x d).
+ * @param n             Number of samples.
+ * @param d             Dimension.
+ * @param pca_result    Contains eigenvectors and eigenvalues.
+ * @return Vec<f64>     The projected data (n x num_components).
+ */
+pub fn project_data(centered_data: &[f64], n: usize, d: usize, pca_result: &PCAResult) -> Vec<f64> {
+    let dims_to_use = pca_result.num_components;
+    let mut projections = vec![0.0; n * dims_to_use];
+
+    for row in 0..n {
+        for comp in 0..dims_to_use {
+            let mut sum = 0.0;
+            for col in 0..d {
+                sum += centered_data[row * d + col] * pca_result.eigenvectors[comp * d + col];
+            }
+            projections[row * dims_to_use + comp] = sum;
+        }
+    }
+
+    projections
+}
+
+/**
+ * @brief Project a sample that was not part of the fit onto existing components.
+ *
+ * @param new_variant_vec The sample's variant calls, one per fitted column
+ *                        (length pca_result.dimension).
+ * @param means           Column means from the training data (same length).
+ * @param pca_result      The fitted components.
+ * @return Vec<f64>       The sample's num_components scores.
+ *
+ * The sample is centered with the training means, not its own, so its scores
+ * are on the same axes as the training samples'. For a fit on standardized
+ * columns, divide by the training standard deviations before calling this.
+ */
+pub fn project_new_sample(
+    new_variant_vec: &[f64],
+    means: &[f64],
+    pca_result: &PCAResult,
+) -> Vec<f64> {
+    let d = pca_result.dimension;
+    let centered: Vec<f64> = new_variant_vec
+        .iter()
+        .zip(means.iter())
+        .map(|(x, m)| x - m)
+        .collect();
+    project_data(&centered, 1, d, pca_result)
+}
+
+/**
+ * @brief Project data and scale each component to unit variance (whitening).
+ *
+ * @param centered_data The centered data (n x d).
+ * @param n             Number of samples.
+ * @param d             Dimension.
+ * @param pca_result    Contains eigenvectors and eigenvalues.
+ * @return Vec<f64>     The whitened projections (n x num_components).
+ *
+ * Each score is divided by sqrt(eigenvalue). Components whose eigenvalue is
+ * numerically zero carry no variance to normalize and are set to 0.
+ */
+pub fn project_data_whitened(
+    centered_data: &[f64],
+    n: usize,
+    d: usize,
+    pca_result: &PCAResult,
+) -> Vec<f64> {
+    let k = pca_result.num_components;
+    let mut projections = project_data(centered_data, n, d, pca_result);
+
+    for comp in 0..k {
+        let lambda = pca_result.eigenvalues[comp];
+        let scale = if lambda > 1e-12 {
+            1.0 / lambda.sqrt()
+        } else {
+            0.0
+        };
+        for row in 0..n {
+            projections[row * k + comp] *= scale;
+        }
+    }
+
+    projections
+}
+
+/**
+ * @brief Rescale each score column so its largest absolute value is 1, in place.
+ *
+ * @param scores Row-major scores (n x k), as returned by project_data.
+ * @param n      Number of samples.
+ * @param k      Number of components.
+ *
+ * Columns land in [-1, 1] with relative positions and signs unchanged; an
+ * all-zero column is left as is. Meant for plotting only: eigenvalues and the
+ * variance carried by each component are not reflected in the result.
+ */
+pub fn normalize_scores(scores: &mut [f64], n: usize, k: usize) {
+    for comp in 0..k {
+        let max_abs = (0..n)
+            .map(|row| scores[row * k + comp].abs())
+            .fold(0.0, f64::max);
+        if max_abs == 0.0 {
+            continue;
+        }
+        for row in 0..n {
+            scores[row * k + comp] /= max_abs;
+        }
+    }
+}
+
+/**
+ * @brief Apply the covariance operator implicitly: out = X^T (X v) / (n - 1).
+ *
+ * @param centered  The centered data (n x d).
+ * @param v         Input vector of length d.
+ * @param xv        Scratch buffer of length n.
+ * @param out       Output vector of length d.
+ */
+fn covariance_times_vector(
+    centered: &[f64],
+    n: usize,
+    d: usize,
+    v: &[f64],
+    xv: &mut [f64],
+    out: &mut [f64],
+) {
+    for row in 0..n {
+        let mut sum = 0.0;
+        for col in 0..d {
+            sum += centered[row * d + col] * v[col];
+        }
+        xv[row] = sum;
+    }
+
+    out.iter_mut().for_each(|x| *x = 0.0);
+    for row in 0..n {
+        let scale = xv[row] / ((n - 1) as f64);
+        for col in 0..d {
+            out[col] += centered[row * d + col] * scale;
+        }
+    }
+}
+
+/**
+ * @brief Top-k principal components by power iteration with deflation.
+ *
+ * @param centered  The centered data (n x d).
+ * @param n         Number of samples.
+ * @param d         Dimension.
+ * @param k         Number of components to extract (clamped to d).
+ * @return PCAResult with k eigenvalues and eigenvectors (k x d).
+ *
+ * The d x d covariance matrix is never built; each iteration costs two
+ * matrix-vector products with X, so memory stays O(n * d).
+ */
+pub fn top_k_components(centered: &[f64], n: usize, d: usize, k: usize) -> PCAResult {
+    let k = k.min(d);
+    let max_iter = 1000;
+    let tol = 1e-12;
+
+    let mut eigenvalues = Vec::with_capacity(k);
+    let mut eigenvectors: Vec<f64> = Vec::with_capacity(k * d);
+    let mut xv = vec![0.0; n];
+    let mut w = vec![0.0; d];
+
+    for comp in 0..k {
+        // Deterministic, non-degenerate starting vector
+        let mut v: Vec<f64> = (0..d)
+            .map(|i| 1.0 + ((i + comp) as f64 * 0.618_033_988_75).fract())
+            .collect();
+        orthogonalize(&mut v, &eigenvectors, d);
+        normalize(&mut v);
+
+        let mut lambda = 0.0;
+        for _ in 0..max_iter {
+            covariance_times_vector(centered, n, d, &v, &mut xv, &mut w);
+            // Deflation: keep the iterate orthogonal to components already found
+            orthogonalize(&mut w, &eigenvectors, d);
+
+            let new_lambda: f64 = v.iter().zip(w.iter()).map(|(a, b)| a * b).sum();
+            let norm = normalize(&mut w);
+            if norm == 0.0 {
+                lambda = 0.0;
+                break;
+            }
+
+            let diff: f64 = v.iter().zip(w.iter()).map(|(a, b)| (a - b).abs()).sum();
+            std::mem::swap(&mut v, &mut w);
+            let converged =
+                (new_lambda - lambda).abs() <= tol * new_lambda.abs().max(1.0) && diff <= 1e-9;
+            lambda = new_lambda;
+            if converged {
+                break;
+            }
+        }
+
+        eigenvalues.push(lambda);
+        eigenvectors.extend_from_slice(&v);
+    }
+
+    let mut result = PCAResult {
+        eigenvalues,
+        eigenvectors,
+        num_components: k,
+        dimension: d,
+    };
+    result.stabilize_signs();
+    result
+}
+
+/**
+ * @brief Dual (Gram-matrix) PCA for the n << d case.
+ *
+ * @param centered  The centered data (n x d).
+ * @param n         Number of samples.
+ * @param d         Dimension.
+ * @param k         Number of components to keep (clamped to min(n, d)).
+ * @return PCAResult with k eigenvalues and feature-space eigenvectors (k x d),
+ *         or InvalidInput if the data hold a NaN or infinite value.
+ *
+ * Builds the n x n matrix G = X X^T / (n - 1), whose non-zero eigenvalues equal
+ * those of the covariance matrix, then recovers each loading vector as
+ * v = X^T u / sqrt(lambda * (n - 1)). Cost is O(n^2 * d) instead of O(n * d^2).
+ */
+pub fn dual_pca(centered: &[f64], n: usize, d: usize, k: usize) -> Result<PCAResult, PcaError> {
+    dual_pca_from_gram(centered, &gram_matrix(centered, n, d), n, d, k)
+}
+
+/**
+ * @brief Sample-by-sample covariance G = X X^T / (n - 1) of centered data.
+ *
+ * @param centered The centered data (n x d).
+ * @param n        Number of samples.
+ * @param d        Dimension.
+ * @return The symmetric n x n matrix, row-major. With centered (or
+ *         standardized) genotypes this is the genetic relationship matrix.
+ */
+pub fn gram_matrix(centered: &[f64], n: usize, d: usize) -> Vec<f64> {
+    let scale = (n - 1) as f64;
+    let mut gram = vec![0.0; n * n];
+    for i in 0..n {
+        for j in i..n {
+            let mut sum = 0.0;
+            for col in 0..d {
+                sum += centered[i * d + col] * centered[j * d + col];
+            }
+            gram[i * n + j] = sum / scale;
+            gram[j * n + i] = sum / scale;
+        }
+    }
+    gram
+}
+
+/**
+ * @brief dual_pca with the n x n Gram matrix already computed (see gram_matrix).
+ */
+pub fn dual_pca_from_gram(
+    centered: &[f64],
+    gram: &[f64],
+    n: usize,
+    d: usize,
+    k: usize,
+) -> Result<PCAResult, PcaError> {
+    let k = k.min(n).min(d);
+    let scale = (n - 1) as f64;
+
+    let sample_space = eigen_decomposition_ql(gram, n)?;
+
+    let mut eigenvalues = Vec::with_capacity(k);
+    let mut eigenvectors = vec![0.0; k * d];
+    for comp in 0..k {
+        let lambda = sample_space.eigenvalues[comp];
+        eigenvalues.push(lambda);
+        if lambda <= 1e-12 {
+            // Null direction: no meaningful loading vector can be recovered
+            continue;
+        }
+        let u = &sample_space.eigenvectors[comp * n..(comp + 1) * n];
+        let norm = (lambda * scale).sqrt();
+        for row in 0..n {
+            let weight = u[row] / norm;
+            for col in 0..d {
+                eigenvectors[comp * d + col] += centered[row * d + col] * weight;
+            }
+        }
+    }
+
+    let mut result = PCAResult {
+        eigenvalues,
+        eigenvectors,
+        num_components: k,
+        dimension: d,
+    };
+    result.stabilize_signs();
+    Ok(result)
+}
+
+/**
+ * @brief Approximate top-k components by randomized SVD (Halko, Martinsson & Tropp).
+ *
+ * @param centered     The centered data (n x d).
+ * @param n            Number of samples.
+ * @param d            Dimension.
+ * @param k            Number of components to keep (clamped to min(n, d)).
+ * @param oversampling Extra random directions beyond k; 5-10 is usually enough.
+ * @param n_iter       Power iterations; more sharpen a slowly decaying spectrum.
+ * @param seed         Seed for the random test matrix; equal seeds give equal output.
+ * @return PCAResult with k eigenvalues and eigenvectors (k x d).
+ *
+ * X is multiplied by a random d x l matrix (l = k + oversampling) to find an
+ * orthonormal basis Q of its range, refined by power iterations. The small
+ * l x l problem B B^T, with B = Q^T X, is then solved exactly and its
+ * eigenvectors lifted back to loadings as in dual_pca. Cost is O(n * d * l)
+ * per pass over X.
+ */
+pub fn randomized_svd(
+    centered: &[f64],
+    n: usize,
+    d: usize,
+    k: usize,
+    oversampling: usize,
+    n_iter: usize,
+    seed: u64,
+) -> PCAResult {
+    let k = k.min(n).min(d);
+    let l = (k + oversampling).min(n).min(d);
+    let scale = (n - 1) as f64;
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    // Q^T (l x n): each row is X times a random direction, then orthonormalized
+    let mut basis = vec![0.0; l * n];
+    let mut direction = vec![0.0; d];
+    for j in 0..l {
+        direction
+            .iter_mut()
+            .for_each(|x| *x = rng.gen_range(-1.0..1.0));
+        multiply(centered, n, d, &direction, &mut basis[j * n..(j + 1) * n]);
+    }
+    orthonormalize_rows(&mut basis, n);
+
+    // B^T rows (l x d): X^T q_j, re-orthonormalized while power iterating
+    let mut projected = vec![0.0; l * d];
+    for _ in 0..n_iter {
+        for j in 0..l {
+            multiply_transposed(
+                centered,
+                n,
+                d,
+                &basis[j * n..(j + 1) * n],
+                &mut projected[j * d..(j + 1) * d],
+            );
+        }
+        orthonormalize_rows(&mut projected, d);
+        for j in 0..l {
+            multiply(
+                centered,
+                n,
+                d,
+                &projected[j * d..(j + 1) * d],
+                &mut basis[j * n..(j + 1) * n],
+            );
+        }
+        orthonormalize_rows(&mut basis, n);
+    }
+    for j in 0..l {
+        multiply_transposed(
+            centered,
+            n,
+            d,
+            &basis[j * n..(j + 1) * n],
+            &mut projected[j * d..(j + 1) * d],
+        );
+    }
+
+    // Small problem: B B^T / (n - 1) shares its eigenvalues with the covariance
+    let mut small = vec![0.0; l * l];
+    for i in 0..l {
+        for j in i..l {
+            let b_i = &projected[i * d..(i + 1) * d];
+            let b_j = &projected[j * d..(j + 1) * d];
+            let sum: f64 = b_i.iter().zip(b_j.iter()).map(|(a, b)| a * b).sum();
+            small[i * l + j] = sum / scale;
+            small[j * l + i] = sum / scale;
+        }
+    }
+    let reduced = eigen_decomposition(&small, l);
+
+    let mut eigenvalues = Vec::with_capacity(k);
+    let mut eigenvectors = vec![0.0; k * d];
+    for comp in 0..k {
+        let lambda = reduced.eigenvalues[comp];
+        eigenvalues.push(lambda);
+        if lambda <= 1e-12 {
+            continue;
+        }
+        let u = &reduced.eigenvectors[comp * l..(comp + 1) * l];
+        let norm = (lambda * scale).sqrt();
+        for j in 0..l {
+            let weight = u[j] / norm;
+            for col in 0..d {
+                eigenvectors[comp * d + col] += projected[j * d + col] * weight;
+            }
+        }
+    }
+
+    let mut result = PCAResult {
+        eigenvalues,
+        eigenvectors,
+        num_components: k,
+        dimension: d,
+    };
+    result.stabilize_signs();
+    result
+}
+
+/**
+ * @brief out = X v for X (n x d) and v of length d; out has length n.
+ */
+fn multiply(x: &[f64], n: usize, d: usize, v: &[f64], out: &mut [f64]) {
+    for row in 0..n {
+        out[row] = x[row * d..(row + 1) * d]
+            .iter()
+            .zip(v.iter())
+            .map(|(a, b)| a * b)
+            .sum();
+    }
+}
+
+/**
+ * @brief out = X^T v for X (n x d) and v of length n; out has length d.
+ */
+fn multiply_transposed(x: &[f64], n: usize, d: usize, v: &[f64], out: &mut [f64]) {
+    out.iter_mut().for_each(|o| *o = 0.0);
+    for row in 0..n {
+        for (o, a) in out.iter_mut().zip(x[row * d..(row + 1) * d].iter()) {
+            *o += a * v[row];
+        }
+    }
+}
+
+/**
+ * @brief Modified Gram-Schmidt over the rows (length len) of m, in place.
+ *        Rows that are linearly dependent on earlier ones end up zero.
+ */
+fn orthonormalize_rows(m: &mut [f64], len: usize) {
+    for j in 0..m.len() / len {
+        let (done, rest) = m.split_at_mut(j * len);
+        let row = &mut rest[..len];
+        orthogonalize(row, done, len);
+        if normalize(row) <= 1e-12 {
+            row.iter_mut().for_each(|x| *x = 0.0);
+        }
+    }
+}
+
+/**
+ * @brief Remove from v its projection on each row of `basis` (rows of length d).
+ */
+fn orthogonalize(v: &mut [f64], basis: &[f64], d: usize) {
+    for pc in basis.chunks(d) {
+        let dot: f64 = v.iter().zip(pc.iter()).map(|(a, b)| a * b).sum();
+        for (x, p) in v.iter_mut().zip(pc.iter()) {
+            *x -= dot * p;
+        }
+    }
+}
+
+/**
+ * @brief Scale v to unit length in place; returns the original norm.
+ */
+fn normalize(v: &mut [f64]) -> f64 {
+    let norm = v.iter().map(|x| x * x).sum::<f64>().sqrt();
+    if norm > 0.0 {
+        v.iter_mut().for_each(|x| *x /= norm);
+    }
+    norm
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// n x d matrix of uniform values in [0, 1), reproducible from `seed`.
+    fn random_matrix(n: usize, d: usize, seed: u64) -> Vec<f64> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        (0..n * d).map(|_| rng.gen::<f64>()).collect()
+    }
+
+    fn assert_close(a: &[f64], b: &[f64], tol: f64) {
+        assert_eq!(a.len(), b.len());
+        for (i, (x, y)) in a.iter().zip(b).enumerate() {
+            assert!((x - y).abs() <= tol, "entry {}: {} vs {}", i, x, y);
+        }
+    }
+
+    #[test]
+    fn ql_matches_jacobi_on_10x10() {
+        let m = random_matrix(10, 10, 7);
+        let mut sym = vec![0.0; 100];
+        for i in 0..10 {
+            for j in 0..10 {
+                sym[i * 10 + j] = m[i * 10 + j] + m[j * 10 + i];
+            }
+        }
+        let jacobi = eigen_decomposition(&sym, 10);
+        let ql = eigen_decomposition_ql(&sym, 10).unwrap();
+        assert_close(&ql.eigenvalues, &jacobi.eigenvalues, 1e-9);
+        assert_close(&ql.eigenvectors, &jacobi.eigenvectors, 1e-7);
+    }
+
+    #[test]
+    fn ql_rejects_non_finite_input() {
+        let mut sym = vec![1.0, 0.5, 0.5, 1.0];
+        sym[1] = f64::NAN;
+        assert!(matches!(
+            eigen_decomposition_ql(&sym, 2),
+            Err(PcaError::InvalidInput(_))
+        ));
+        sym[1] = f64::INFINITY;
+        assert!(eigen_decomposition_ql(&sym, 2).is_err());
+    }
+
+    #[test]
+    fn in_place_centering_matches_copy() {
+        let data = random_matrix(6, 9, 3);
+        let copied = center_data(&data, 6, 9);
+        let mut in_place = data.clone();
+        let means = center_data_in_place(&mut in_place, 6, 9);
+        assert_eq!(in_place, copied);
+        assert_eq!(means, column_means(&data, 6, 9));
+    }
+
+    #[test]
+    fn blocked_reporting_matches_naive() {
+        let centered = center_data(&random_matrix(7, 130, 5), 7, 130);
+        let mut progress = Progress::disabled();
+        let blocked = compute_covariance_matrix_blocked_reporting(
+            &centered,
+            7,
+            130,
+            COVARIANCE_BLOCK,
+            &mut progress,
+        );
+        assert_eq!(blocked, compute_covariance_matrix(&centered, 7, 130));
+    }
+
+    /// The exact solution: Jacobi on the full covariance matrix.
+    fn exact(centered: &[f64], n: usize, d: usize) -> PCAResult {
+        eigen_decomposition(&compute_covariance_matrix(centered, n, d), d)
+    }
+
+    #[test]
+    fn power_iteration_matches_jacobi_top_2() {
+        let centered = center_data(&random_matrix(8, 6, 11), 8, 6);
+        let full = exact(&centered, 8, 6);
+        let top = top_k_components(&centered, 8, 6, 2);
+        assert_eq!(top.num_components, 2);
+        assert_close(&top.eigenvalues, &full.eigenvalues[..2], 1e-9);
+        assert_close(&top.eigenvectors, &full.eigenvectors[..2 * 6], 1e-5);
+    }
+
+    #[test]
+    fn dual_scores_match_primal_on_4x10() {
+        let centered = center_data(&random_matrix(4, 10, 13), 4, 10);
+        let mut primal = exact(&centered, 4, 10);
+        primal.truncate(3);
+        let dual = dual_pca(&centered, 4, 10, 3).unwrap();
+        assert_close(&dual.eigenvalues, &primal.eigenvalues[..3], 1e-9);
+        assert_close(
+            &project_data(&centered, 4, 10, &dual),
+            &project_data(&centered, 4, 10, &primal),
+            1e-8,
+        );
+    }
+
+    #[test]
+    fn explained_variance_ratios_sum_to_one() {
+        let centered = center_data(&random_matrix(9, 5, 17), 9, 5);
+        let mut result = exact(&centered, 9, 5);
+        result.truncate(2);
+        let ratios = result.explained_variance_ratio();
+        assert_eq!(ratios.len(), 5);
+        assert!((ratios.iter().sum::<f64>() - 1.0).abs() < 1e-12);
+        let cumulative = result.cumulative_variance();
+        assert!((cumulative[4] - 1.0).abs() < 1e-12);
+        assert!(cumulative.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+
+    #[test]
+    #[cfg(feature = "rayon")]
+    fn parallel_covariance_is_bitwise_serial() {
+        let centered = center_data(&random_matrix(12, 40, 19), 12, 40);
+        assert_eq!(
+            compute_covariance_matrix_parallel(&centered, 12, 40),
+            compute_covariance_matrix(&centered, 12, 40)
+        );
+    }
+
+    #[test]
+    fn standardized_zero_variance_columns_stay_finite() {
+        let data = [
+            1.0, 0.0, 5.0, //
+            1.0, 1.0, 5.0, //
+            1.0, 0.0, 5.0,
+        ];
+        let standardized = standardize_data(&data, 3, 3);
+        assert!(standardized.iter().all(|x| x.is_finite()));
+        for row in 0..3 {
+            assert_eq!(standardized[row * 3], 0.0);
+            assert_eq!(standardized[row * 3 + 2], 0.0);
+        }
+        let variance: f64 = (0..3)
+            .map(|row| standardized[row * 3 + 1].powi(2))
+            .sum::<f64>()
+            / 2.0;
+        assert!((variance - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn nan_is_imputed_with_mean_of_the_rest() {
+        let mut data = vec![
+            1.0,
+            0.0, //
+            f64::NAN,
+            1.0, //
+            0.0,
+            1.0, //
+            1.0,
+            1.0,
+        ];
+        impute_column_means(&mut data, 4, 2);
+        assert_eq!(data[2], 2.0 / 3.0);
+        assert_eq!(data, vec![1.0, 0.0, 2.0 / 3.0, 1.0, 0.0, 1.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn covariance_matches_naive_full_loop() {
+        let (n, d) = (5, 8);
+        let centered = center_data(&random_matrix(n, d, 25), n, d);
+        let cov = compute_covariance_matrix(&centered, n, d);
+        for i in 0..d {
+            for j in 0..d {
+                let mut sum = 0.0;
+                for s in 0..n {
+                    sum += centered[s * d + i] * centered[s * d + j];
+                }
+                assert_eq!(cov[i * d + j], sum / (n - 1) as f64, "entry ({}, {})", i, j);
+            }
+        }
+    }
+
+    #[test]
+    fn whitened_score_columns_have_unit_variance() {
+        let (n, d) = (12, 4);
+        let centered = center_data(&random_matrix(n, d, 26), n, d);
+        let result = eigen_decomposition(&compute_covariance_matrix(&centered, n, d), d);
+        let k = result.num_components;
+        let white = project_data_whitened(&centered, n, d, &result);
+        for c in 0..k {
+            let var = (0..n).map(|s| white[s * k + c].powi(2)).sum::<f64>() / (n - 1) as f64;
+            assert!(
+                (var - 1.0).abs() < 1e-9,
+                "component {}: variance {}",
+                c,
+                var
+            );
+        }
+    }
+
+    #[test]
+    fn randomized_svd_matches_exact_top_2() {
+        let (n, d) = (20, 15);
+        // Two strong directions plus small noise, so the top 2 are well separated
+        let noise = random_matrix(n, d, 29);
+        let mut data = vec![0.0; n * d];
+        for s in 0..n {
+            let (a, b) = ((s % 2) as f64 * 4.0, (s % 3) as f64 * 2.0);
+            for c in 0..d {
+                let u = if c < 8 { a } else { 0.0 };
+                let v = if c % 2 == 0 { b } else { -b };
+                data[s * d + c] = u + v + 0.1 * noise[s * d + c];
+            }
+        }
+        let centered = center_data(&data, n, d);
+        let full = exact(&centered, n, d);
+        let approx = randomized_svd(&centered, n, d, 2, 5, 3, 42);
+        assert_eq!(approx.num_components, 2);
+        assert_close(
+            &approx.eigenvalues[..2],
+            &full.eigenvalues[..2],
+            1e-3 * full.eigenvalues[0],
+        );
+        for c in 0..2 {
+            let dot: f64 = (0..d)
+                .map(|r| approx.eigenvectors[c * d + r] * full.eigenvectors[c * d + r])
+                .sum();
+            assert!(dot.abs() > 0.999, "component {}: |cos| = {}", c, dot.abs());
+        }
+        let again = randomized_svd(&centered, n, d, 2, 5, 3, 42);
+        assert_eq!(again.eigenvectors, approx.eigenvectors);
+    }
+
+    #[test]
+    fn column_major_covariance_is_bitwise_row_major() {
+        let (n, d) = (9, 13);
+        let centered = center_data(&random_matrix(n, d, 34), n, d);
+        let columns = transpose(&centered, n, d);
+        assert_eq!(
+            compute_covariance_matrix_column_major(&columns, n, d),
+            compute_covariance_matrix(&centered, n, d)
+        );
+    }
+
+    #[cfg(feature = "nalgebra")]
+    #[test]
+    fn nalgebra_matches_naive_path() {
+        let (n, d) = (10, 7);
+        let centered = center_data(&random_matrix(n, d, 36), n, d);
+        let naive = compute_covariance_matrix(&centered, n, d);
+        let blas = compute_covariance_matrix_nalgebra(&centered, n, d);
+        assert_close(&blas, &naive, 1e-8);
+        let jacobi = eigen_decomposition(&naive, d);
+        let eig = eigen_decomposition_nalgebra(&naive, d);
+        assert_close(&eig.eigenvalues, &jacobi.eigenvalues, 1e-8);
+    }
+
+    #[test]
+    fn projected_sample_matches_its_fitted_scores() {
+        let (n, d) = (8, 5);
+        let data = random_matrix(n, d, 44);
+        let means = column_means(&data, n, d);
+        let centered = center_data(&data, n, d);
+        let mut result = exact(&centered, n, d);
+        result.truncate(3);
+        let scores = project_data(&centered, n, d, &result);
+        for s in 0..n {
+            let projected = project_new_sample(&data[s * d..(s + 1) * d], &means, &result);
+            assert_close(&projected, &scores[s * 3..(s + 1) * 3], 1e-12);
+        }
+    }
+
+    #[test]
+    fn correlation_diagonal_is_one_except_constant_columns() {
+        let (n, d) = (7, 5);
+        let mut data = random_matrix(n, d, 47);
+        for s in 0..n {
+            data[s * d + 2] = 3.0;
+        }
+        let corr = compute_correlation_matrix(&center_data(&data, n, d), n, d);
+        for i in 0..d {
+            let expected = if i == 2 { 0.0 } else { 1.0 };
+            assert!((corr[i * d + i] - expected).abs() < 1e-12, "diagonal {}", i);
+        }
+        assert!(corr.iter().all(|x| x.is_finite() && x.abs() <= 1.0 + 1e-12));
+    }
+
+    #[test]
+    fn one_pass_means_match_two_pass() {
+        let (n, d) = (50, 6);
+        // A large offset makes naive running sums lose digits
+        let data: Vec<f64> = random_matrix(n, d, 49).iter().map(|x| 1e9 + x).collect();
+        let mut acc = MeanAccumulator::new(d);
+        for row in data.chunks(d) {
+            acc.add_sample(row);
+        }
+        assert_eq!(acc.count(), n);
+        let two_pass: Vec<f64> = (0..d)
+            .map(|c| {
+                let rough = (0..n).map(|s| data[s * d + c]).sum::<f64>() / n as f64;
+                rough + (0..n).map(|s| data[s * d + c] - rough).sum::<f64>() / n as f64
+            })
+            .collect();
+        assert_close(acc.means(), &two_pass, 1e-6);
+        assert_close(acc.means(), &column_means(&data, n, d), 1e-6);
+    }
+
+    #[test]
+    fn eigen_of_empty_and_scalar_matrices() {
+        let empty = eigen_decomposition(&[], 0);
+        assert_eq!(empty.num_components, 0);
+        assert!(empty.eigenvalues.is_empty() && empty.eigenvectors.is_empty());
+
+        let scalar = eigen_decomposition(&[2.5], 1);
+        assert_eq!(scalar.num_components, 1);
+        assert_eq!(scalar.eigenvalues, vec![2.5]);
+        assert_eq!(scalar.eigenvectors, vec![1.0]);
+    }
+
+    #[test]
+    fn repeated_solves_give_identical_signs() {
+        let (n, d) = (9, 6);
+        let centered = center_data(&random_matrix(n, d, 63), n, d);
+        let cov = compute_covariance_matrix(&centered, n, d);
+        let first = eigen_decomposition(&cov, d);
+        let second = eigen_decomposition(&cov, d);
+        assert_eq!(
+            project_data(&centered, n, d, &first),
+            project_data(&centered, n, d, &second)
+        );
+
+        // Every solver leaves the first (near-)largest loading positive
+        let ql = eigen_decomposition_ql(&cov, d).unwrap();
+        let top = top_k_components(&centered, n, d, 3);
+        for result in [&first, &ql, &top] {
+            for vector in result.eigenvectors.chunks(d) {
+                let largest = vector.iter().fold(0.0f64, |acc, x| acc.max(x.abs()));
+                let pivot = vector
+                    .iter()
+                    .find(|x| x.abs() >= largest * (1.0 - SIGN_PIVOT_TOLERANCE))
+                    .unwrap();
+                assert!(*pivot > 0.0);
+            }
+        }
+        assert_close(
+            &project_data(&centered, n, d, &ql),
+            &project_data(&centered, n, d, &first),
+            1e-8,
+        );
+    }
+
+    #[test]
+    fn blocked_covariance_with_block_3_matches_naive() {
+        let (n, d) = (6, 8);
+        let centered = center_data(&random_matrix(n, d, 74), n, d);
+        assert_eq!(
+            compute_covariance_matrix_blocked(&centered, n, d, 3),
+            compute_covariance_matrix(&centered, n, d)
+        );
+
+        let mut tiles = 0;
+        let mut assembled = vec![0.0; d * d];
+        for_each_covariance_tile(&centered, n, d, 3, |rows, cols, tile| {
+            tiles += 1;
+            for (r, i) in rows.clone().enumerate() {
+                for (c, j) in cols.clone().enumerate() {
+                    assembled[i * d + j] = tile[r * cols.len() + c];
+                    assembled[j * d + i] = tile[r * cols.len() + c];
+                }
+            }
+        });
+        // Row blocks 0..3, 3..6 and 6..8: 3 + 2 + 1 tiles on or above the diagonal
+        assert_eq!(tiles, 6);
+        assert_eq!(assembled, compute_covariance_matrix(&centered, n, d));
+    }
+
+    #[test]
+    fn uniform_weights_match_the_unweighted_path() {
+        let (n, d) = (7, 5);
+        let data = random_matrix(n, d, 79);
+        let ones = vec![1.0; n];
+        let (centered, means) = center_data_weighted(&data, n, d, &ones);
+        assert_eq!(means, column_means(&data, n, d));
+        assert_eq!(centered, center_data(&data, n, d));
+        assert_eq!(
+            compute_covariance_matrix_weighted(&centered, n, d, &ones),
+            compute_covariance_matrix(&centered, n, d)
+        );
+    }
+
+    #[test]
+    fn non_uniform_weights_shift_the_mean() {
+        let data = vec![
+            0.0, 1.0, //
+            0.0, 1.0, //
+            1.0, 0.0,
+        ];
+        assert_close(&column_means(&data, 3, 2), &[1.0 / 3.0, 2.0 / 3.0], 1e-15);
+        // Weighting the last sample 4x pulls the means toward it
+        let weights = [1.0, 1.0, 4.0];
+        assert_close(
+            &column_means_weighted(&data, 3, 2, &weights),
+            &[4.0 / 6.0, 2.0 / 6.0],
+            1e-15,
+        );
+
+        // A weight of 2 counts a sample twice
+        let (centered, _) = center_data_weighted(&data, 3, 2, &[1.0, 1.0, 2.0]);
+        let duplicated = [&data[..], &data[4..6]].concat();
+        let expected = compute_covariance_matrix(&center_data(&duplicated, 4, 2), 4, 2);
+        assert_close(
+            &compute_covariance_matrix_weighted(&centered, 3, 2, &[1.0, 1.0, 2.0]),
+            &expected,
+            1e-15,
+        );
+    }
+
+    #[test]
+    fn duplicate_eigenvalues_keep_index_order() {
+        assert_eq!(
+            descending_order(&[2.0, 5.0, 2.0, 5.0, 1.0]),
+            vec![1, 3, 0, 2, 4]
+        );
+
+        // Diagonal, so Jacobi does no rotations and the ties come straight from the diagonal
+        let d = 5;
+        let mut diag = vec![0.0; d * d];
+        for (i, value) in [2.0, 5.0, 2.0, 5.0, 1.0].iter().enumerate() {
+            diag[i * d + i] = *value;
+        }
+        let result = eigen_decomposition(&diag, d);
+        assert_eq!(result.eigenvalues, vec![5.0, 5.0, 2.0, 2.0, 1.0]);
+        let axis = |component: usize| {
+            let vector = &result.eigenvectors[component * d..(component + 1) * d];
+            vector.iter().position(|&x| x == 1.0).unwrap()
+        };
+        assert_eq!((0..d).map(axis).collect::<Vec<_>>(), vec![1, 3, 0, 2, 4]);
+
+        // A dense matrix with a repeated eigenvalue gives bit-identical runs
+        let mut dense = vec![1.0; 16];
+        for i in 0..4 {
+            dense[i * 4 + i] = 3.0;
+        }
+        let first = eigen_decomposition(&dense, 4);
+        let second = eigen_decomposition(&dense, 4);
+        assert_close(&first.eigenvalues[1..], &[2.0, 2.0, 2.0], 1e-12);
+        assert_eq!(first.eigenvalues, second.eigenvalues);
+        assert_eq!(first.eigenvectors, second.eigenvectors);
+    }
+
+    #[test]
+    fn normalized_columns_have_max_abs_one() {
+        let (n, k) = (6, 3);
+        let mut scores: Vec<f64> = random_matrix(n, k, 86)
+            .iter()
+            .map(|x| 7.0 * x - 5.0)
+            .collect();
+        for row in 0..n {
+            scores[row * k + 2] = 0.0;
+        }
+        let original = scores.clone();
+        normalize_scores(&mut scores, n, k);
+        for comp in 0..2 {
+            let max_abs = (0..n)
+                .map(|row| scores[row * k + comp].abs())
+                .fold(0.0, f64::max);
+            assert_eq!(max_abs, 1.0);
+            // A single positive factor per column: ratios and signs are kept
+            let factor = original[comp] / scores[comp];
+            assert!(factor > 0.0);
+            for row in 0..n {
+                let idx = row * k + comp;
+                assert!((scores[idx] * factor - original[idx]).abs() < 1e-12);
+            }
+        }
+        assert!((0..n).all(|row| scores[row * k + 2] == 0.0));
+    }
+
+    #[test]
+    fn co_moment_covariance_matches_centered_path() {
+        let (n, d) = (12, 6);
+        let data = random_matrix(n, d, 88);
+        let reference = compute_covariance_matrix(&center_data(&data, n, d), n, d);
+        assert_close(&covariance_from_raw(&data, n, d), &reference, 1e-14);
+
+        // 0/1 calls, as the pipeline produces, stay near machine precision too
+        let calls: Vec<f64> = data.iter().map(|x| (*x > 0.5) as u8 as f64).collect();
+        assert_close(
+            &covariance_from_raw(&calls, n, d),
+            &compute_covariance_matrix(&center_data(&calls, n, d), n, d),
+            1e-14,
+        );
+    }
+
+    #[test]
+    fn eigenvectors_as_columns_is_the_transpose() {
+        // Two stored components over d = 3; entry comp * 3 + col = 10 comp + col
+        let mut result = PCAResult {
+            eigenvalues: vec![3.0, 2.0, 1.0],
+            eigenvectors: vec![0.0, 1.0, 2.0, 10.0, 11.0, 12.0],
+            num_components: 2,
+            dimension: 3,
+        };
+        let columns = result.eigenvectors_as_columns();
+        assert_eq!(columns, vec![0.0, 10.0, 1.0, 11.0, 2.0, 12.0]);
+        for comp in 0..2 {
+            for col in 0..3 {
+                assert_eq!(columns[col * 2 + comp], result.eigenvectors[comp * 3 + col]);
+            }
+        }
+
+        result.truncate(1);
+        assert_eq!(result.eigenvectors_as_columns(), vec![0.0, 1.0, 2.0]);
+    }
+
+    #[test]
+    fn rank_two_matrix_reports_two_nonzero_eigenvalues() {
+        // 12 samples (n > d) in the span of two fixed 5-SNP patterns
+        let (n, d) = (12, 5);
+        let patterns = [[1.0, 0.0, 2.0, 1.0, 0.0], [0.0, 1.0, 1.0, 0.0, 2.0]];
+        let weights = random_matrix(n, 2, 41);
+        let data: Vec<f64> = weights
+            .chunks(2)
+            .flat_map(|w| (0..d).map(move |col| w[0] * patterns[0][col] + w[1] * patterns[1][col]))
+            .collect();
+        let result = exact(&center_data(&data, n, d), n, d);
+
+        assert_eq!(result.eigenvalues.len(), d);
+        assert_eq!(result.numerical_rank(), 2);
+        assert!(result.eigenvalues[1] > 1e3 * result.rank_tolerance());
+        for &val in &result.eigenvalues[2..] {
+            assert!(val.abs() <= result.rank_tolerance(), "{}", val);
+        }
+        // A tolerance above the second eigenvalue leaves only the first
+        assert_eq!(result.numerical_rank_with(result.eigenvalues[1]), 1);
+    }
 }
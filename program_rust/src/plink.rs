@@ -0,0 +1,150 @@
+/*!
+ * @file plink.rs
+ * @brief Minimal PLINK text (.ped + .map) reader producing an allele-dosage matrix for PCA.
+ *
+ * Author: Namir Garib
+ * Created: January 2025
+ */
+
+use crate::error::PcaError;
+use crate::utils::read_maybe_gzipped;
+
+/**
+ * @brief Read a .ped/.map pair into an n x d dosage matrix.
+ *
+ * @param ped_path Path to the .ped file (FID IID PAT MAT SEX PHENO, then two
+ *                 alleles per SNP).
+ * @param map_path Path to the matching .map file (one line per SNP).
+ * @return (matrix, n, d, sample_ids) where the matrix is row-major with one row
+ *         per sample and one column per SNP, or a PcaError. Sample ids are
+ *         "FID_IID".
+ *
+ * PLINK text has no reference allele, so each SNP is coded as the count of its
+ * minor allele (ties go to the allele seen second), so `A A` / `A C` / `C C`
+ * give 0 / 1 / 2 when C is minor. Any missing allele (`0`) gives NaN.
+ */
+pub fn read_plink(
+    ped_path: &str,
+    map_path: &str,
+) -> Result<(Vec<f64>, usize, usize, Vec<String>), PcaError> {
+    let map_raw = read_maybe_gzipped(map_path)?;
+    let d = String::from_utf8_lossy(&map_raw)
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .count();
+
+    let ped_raw = read_maybe_gzipped(ped_path)?;
+    let ped_text = String::from_utf8_lossy(&ped_raw);
+
+    let mut sample_ids: Vec<String> = Vec::new();
+    // Alleles stored sample-major while parsing: two per SNP
+    let mut alleles: Vec<String> = Vec::new();
+    for (line_no, line) in ped_text.lines().enumerate() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.is_empty() {
+            continue;
+        }
+        if fields.len() != 6 + 2 * d {
+            return Err(PcaError::Parse(format!(
+                "{}:{}: expected {} columns for {} SNPs, found {}",
+                ped_path,
+                line_no + 1,
+                6 + 2 * d,
+                d,
+                fields.len()
+            )));
+        }
+        sample_ids.push(format!("{}_{}", fields[0], fields[1]));
+        alleles.extend(fields[6..].iter().map(|a| a.to_string()));
+    }
+    let n = sample_ids.len();
+
+    let mut matrix = vec![0.0; n * d];
+    for col in 0..d {
+        let genotype = |row: usize| {
+            let base = row * 2 * d + 2 * col;
+            (alleles[base].as_str(), alleles[base + 1].as_str())
+        };
+
+        // Tally the (at most two) non-missing alleles in order of appearance
+        let mut counts: Vec<(&str, usize)> = Vec::new();
+        for row in 0..n {
+            let (a, b) = genotype(row);
+            for allele in [a, b] {
+                if allele == "0" {
+                    continue;
+                }
+                match counts.iter_mut().find(|(seen, _)| *seen == allele) {
+                    Some((_, count)) => *count += 1,
+                    None => counts.push((allele, 1)),
+                }
+            }
+        }
+        if counts.len() > 2 {
+            return Err(PcaError::Parse(format!(
+                "{}: SNP {} has more than two alleles",
+                ped_path,
+                col + 1
+            )));
+        }
+        let minor = match counts.as_slice() {
+            [(_, first), (second, count)] if count <= first => Some(*second),
+            [(first, _), _] => Some(*first),
+            _ => None,
+        };
+
+        for row in 0..n {
+            let (a, b) = genotype(row);
+            matrix[row * d + col] = if a == "0" || b == "0" {
+                f64::NAN
+            } else {
+                [a, b]
+                    .iter()
+                    .filter(|&&allele| Some(allele) == minor)
+                    .count() as f64
+            };
+        }
+    }
+
+    Ok((matrix, n, d, sample_ids))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn three_samples_three_snps() {
+        let ped = "F1 I1 0 0 1 -9 A A C T G G\n\
+            F1 I2 0 0 2 -9 A C T T 0 0\n\
+            \n\
+            F2 I3 0 0 1 -9 C C C T G G\n";
+        let map = "1 rs1 0 100\n1 rs2 0 200\n1 rs3 0 300\n";
+        let dir = tempfile::tempdir().unwrap();
+        let ped_path = dir.path().join("small.ped");
+        let map_path = dir.path().join("small.map");
+        std::fs::write(&ped_path, ped).unwrap();
+        std::fs::write(&map_path, map).unwrap();
+
+        let (matrix, n, d, ids) =
+            read_plink(ped_path.to_str().unwrap(), map_path.to_str().unwrap()).unwrap();
+        assert_eq!((n, d), (3, 3));
+        assert_eq!(ids, ["F1_I1", "F1_I2", "F2_I3"]);
+        // rs1: A and C both seen 3 times, the tie goes to C; rs2: C is minor;
+        // rs3: only G, so every called genotype is 0
+        let expected = [[0.0, 1.0, 0.0], [1.0, 0.0, f64::NAN], [2.0, 1.0, 0.0]];
+        for (row, values) in expected.iter().enumerate() {
+            for (col, &value) in values.iter().enumerate() {
+                let got = matrix[row * d + col];
+                assert!(
+                    got == value || (got.is_nan() && value.is_nan()),
+                    "({}, {}): {} vs {}",
+                    row,
+                    col,
+                    got,
+                    value
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,194 @@
+/*!
+ * @file progress.rs
+ * @brief Optional progress reporting and timing for long-running pipeline stages.
+ *
+ * Author: Namir Garib
+ * Created: January 2025
+ */
+
+use std::io::IsTerminal;
+use std::time::{Duration, Instant};
+
+/**
+ * @struct Progress
+ * @brief Counts completed steps of one stage and reports them on stderr.
+ *
+ * With the `progress` feature and an interactive stderr, an indicatif bar is
+ * drawn. Otherwise a plain line is printed every 10% so logs stay readable.
+ * A disabled Progress does nothing, so callers can tick unconditionally.
+ */
+pub struct Progress {
+    label: String,
+    total: usize,
+    done: usize,
+    enabled: bool,
+    last_decile: usize,
+    #[cfg(feature = "progress")]
+    bar: Option<indicatif::ProgressBar>,
+}
+
+impl Progress {
+    /**
+     * @brief Start reporting a stage of `total` steps.
+     *
+     * @param label   Stage name shown with every update.
+     * @param total   Number of steps expected.
+     * @param enabled When false, every method is a no-op.
+     */
+    pub fn new(label: &str, total: usize, enabled: bool) -> Self {
+        let interactive = enabled && std::io::stderr().is_terminal();
+        #[cfg(not(feature = "progress"))]
+        let _ = interactive;
+
+        Progress {
+            label: label.to_string(),
+            total,
+            done: 0,
+            enabled,
+            last_decile: 0,
+            #[cfg(feature = "progress")]
+            bar: interactive.then(|| {
+                let bar = indicatif::ProgressBar::new(total as u64);
+                bar.set_style(
+                    indicatif::ProgressStyle::with_template("{msg} [{bar:40}] {pos}/{len} ({eta})")
+                        .unwrap_or_else(|_| indicatif::ProgressStyle::default_bar())
+                        .progress_chars("=> "),
+                );
+                bar.set_message(label.to_string());
+                bar
+            }),
+        }
+    }
+
+    /// A Progress that never reports.
+    pub fn disabled() -> Self {
+        Progress::new("", 0, false)
+    }
+
+    /**
+     * @brief Record `steps` more completed steps.
+     */
+    pub fn inc(&mut self, steps: usize) {
+        if !self.enabled {
+            return;
+        }
+        self.done = (self.done + steps).min(self.total);
+
+        #[cfg(feature = "progress")]
+        if let Some(bar) = &self.bar {
+            bar.set_position(self.done as u64);
+            return;
+        }
+
+        let decile = (self.done * 10).checked_div(self.total).unwrap_or(10);
+        if decile > self.last_decile {
+            self.last_decile = decile;
+            eprintln!(
+                "{}: {}/{} ({}%)",
+                self.label,
+                self.done,
+                self.total,
+                decile * 10
+            );
+        }
+    }
+
+    /**
+     * @brief Mark the stage complete.
+     */
+    pub fn finish(&mut self) {
+        if !self.enabled {
+            return;
+        }
+        #[cfg(feature = "progress")]
+        if let Some(bar) = self.bar.take() {
+            bar.finish();
+            return;
+        }
+        let remaining = self.total - self.done;
+        if remaining > 0 {
+            self.inc(remaining);
+        }
+    }
+}
+
+/**
+ * @struct StageTimer
+ * @brief Wall-clock time spent in each named pipeline stage.
+ *
+ * Each lap charges the time since the previous lap (or since creation) to a
+ * stage; laps with the same label add up. A disabled StageTimer records
+ * nothing, so callers can lap unconditionally.
+ */
+pub struct StageTimer {
+    enabled: bool,
+    last: Instant,
+    stages: Vec<(&'static str, Duration)>,
+}
+
+impl StageTimer {
+    /**
+     * @brief Start timing; the first lap is measured from here.
+     *
+     * @param enabled When false, every method is a no-op.
+     */
+    pub fn new(enabled: bool) -> Self {
+        StageTimer {
+            enabled,
+            last: Instant::now(),
+            stages: Vec::new(),
+        }
+    }
+
+    /// A StageTimer that never records.
+    pub fn disabled() -> Self {
+        StageTimer::new(false)
+    }
+
+    /**
+     * @brief Charge the time since the previous lap to `label`.
+     */
+    pub fn lap(&mut self, label: &'static str) {
+        if !self.enabled {
+            return;
+        }
+        let now = Instant::now();
+        let elapsed = now - self.last;
+        self.last = now;
+        match self.stages.iter_mut().find(|(stage, _)| *stage == label) {
+            Some((_, total)) => *total += elapsed,
+            None => self.stages.push((label, elapsed)),
+        }
+    }
+
+    /// Recorded stages in the order they were first lapped.
+    pub fn stages(&self) -> &[(&'static str, Duration)] {
+        &self.stages
+    }
+
+    /**
+     * @brief Print one line per stage with its time and share of the total
+     *        on stderr.
+     */
+    pub fn report(&self) {
+        if !self.enabled {
+            return;
+        }
+        let total: Duration = self.stages.iter().map(|(_, time)| *time).sum();
+        eprintln!("Timing breakdown:");
+        for (label, time) in &self.stages {
+            let share = if total.is_zero() {
+                0.0
+            } else {
+                100.0 * time.as_secs_f64() / total.as_secs_f64()
+            };
+            eprintln!(
+                "  {:<14} {:>10.3} ms  {:>5.1}%",
+                label,
+                time.as_secs_f64() * 1e3,
+                share
+            );
+        }
+        eprintln!("  {:<14} {:>10.3} ms", "total", total.as_secs_f64() * 1e3);
+    }
+}
@@ -0,0 +1,80 @@
+/*!
+ * @file python.rs
+ * @brief Python bindings (PyO3), built with the `python` feature.
+ *
+ * Author: Namir Garib
+ * Created: January 2025
+ */
+
+use crate::analysis;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+/**
+ * @brief Python `run_pca(ref: bytes, individuals: list[bytes], k: int)`.
+ *
+ * @return (scores, eigenvalues) as plain lists that numpy.asarray accepts.
+ *
+ * Scores are n rows of num_components values; every eigenvalue is returned.
+ * A PcaError is raised as ValueError. Sequences are uppercased first, as the
+ * file readers do.
+ */
+#[pyfunction]
+#[pyo3(name = "run_pca")]
+pub fn py_run_pca(
+    reference: &[u8],
+    individuals: Vec<Vec<u8>>,
+    k: usize,
+) -> PyResult<(Vec<Vec<f64>>, Vec<f64>)> {
+    let reference = reference.to_ascii_uppercase();
+    let individuals: Vec<Vec<u8>> = individuals
+        .iter()
+        .map(|sequence| sequence.to_ascii_uppercase())
+        .collect();
+    let output = analysis::run_pca(&reference, &individuals, k)
+        .map_err(|e| PyValueError::new_err(e.to_string()))?;
+
+    let num_components = output.result.num_components;
+    let scores = output
+        .scores
+        .chunks(num_components.max(1))
+        .take(output.num_samples)
+        .map(|row| row[..num_components].to_vec())
+        .collect();
+    Ok((scores, output.result.eigenvalues))
+}
+
+/**
+ * @brief The `pca_population_strat` Python module.
+ */
+#[pymodule]
+fn pca_population_strat(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_function(wrap_pyfunction!(py_run_pca, module)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrapper_round_trips_run_pca() {
+        let reference = b"acgtACGTacgtACGTacgt".to_vec();
+        let individuals: Vec<Vec<u8>> = (0..4)
+            .map(|i| {
+                let mut genome = reference.clone();
+                genome[i * 3] = b'n';
+                genome[i % 2 + 10] = b'T';
+                genome[15 + i] = b'c';
+                genome
+            })
+            .collect();
+        let (scores, eigenvalues) = py_run_pca(&reference, individuals.clone(), 2).unwrap();
+
+        let upper: Vec<Vec<u8>> = individuals.iter().map(|g| g.to_ascii_uppercase()).collect();
+        let direct = analysis::run_pca(&reference.to_ascii_uppercase(), &upper, 2).unwrap();
+        assert_eq!(eigenvalues, direct.result.eigenvalues);
+        assert_eq!(scores.len(), 4);
+        assert_eq!(scores.concat(), direct.scores);
+        assert!(py_run_pca(&reference, vec![reference.clone()], 2).is_err());
+    }
+}
@@ -0,0 +1,198 @@
+/*!
+ * @file reference.rs
+ * @brief A reference genome loaded once and reused to call variants for any
+ *        number of batches of individuals.
+ *
+ * Author: Namir Garib
+ * Created: January 2025
+ */
+
+use crate::error::PcaError;
+use crate::utils::{read_sequence_and_mask, InvalidBasePolicy};
+use crate::variant_calling;
+
+/**
+ * @struct Reference
+ * @brief A parsed reference sequence.
+ *
+ * Reading and parsing a large reference dominates start-up, so callers that
+ * run several cohorts against the same reference load it once and pass it to
+ * analysis::analyze_with_reference (or call_variants directly) per batch.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reference {
+    source: String,
+    sequence: Vec<u8>,
+    /// True where the file had a lowercase (soft-masked) base.
+    soft_mask: Vec<bool>,
+}
+
+impl Reference {
+    /**
+     * @brief Read a reference the same way as individuals (raw, FASTA or FASTQ,
+     *        optionally gzipped or from a URL).
+     *
+     * @param path Path or URL of the reference.
+     * @return The reference, or EmptyFile if it holds no bases; bytes other
+     *         than A, C, G, T and N are a Parse error.
+     */
+    pub fn load(path: &str) -> Result<Self, PcaError> {
+        Self::load_with(path, InvalidBasePolicy::Error)
+    }
+
+    /**
+     * @brief Reference::load with an explicit policy for invalid bytes.
+     */
+    pub fn load_with(path: &str, policy: InvalidBasePolicy) -> Result<Self, PcaError> {
+        let (sequence, soft_mask) = read_sequence_and_mask(path, policy)?;
+        if sequence.is_empty() {
+            return Err(PcaError::EmptyFile(path.to_string()));
+        }
+        Ok(Reference {
+            source: path.to_string(),
+            sequence,
+            soft_mask,
+        })
+    }
+
+    /**
+     * @brief Wrap a sequence that is already in memory; `source` labels it in
+     *        errors. Lowercase bases are uppercased and recorded as soft-masked.
+     */
+    pub fn from_sequence(source: &str, mut sequence: Vec<u8>) -> Self {
+        let soft_mask = sequence.iter().map(u8::is_ascii_lowercase).collect();
+        sequence.make_ascii_uppercase();
+        Reference {
+            source: source.to_string(),
+            sequence,
+            soft_mask,
+        }
+    }
+
+    /// Path or label the reference was created from.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// The reference bases.
+    pub fn sequence(&self) -> &[u8] {
+        &self.sequence
+    }
+
+    /// Number of bases.
+    pub fn len(&self) -> usize {
+        self.sequence.len()
+    }
+
+    /// True if the reference holds no bases.
+    pub fn is_empty(&self) -> bool {
+        self.sequence.is_empty()
+    }
+
+    /// Per-position flag: true where the reference was lowercase (soft-masked).
+    pub fn soft_mask(&self) -> &[bool] {
+        &self.soft_mask
+    }
+
+    /// Number of soft-masked positions.
+    pub fn soft_masked_count(&self) -> usize {
+        self.soft_mask.iter().filter(|&&masked| masked).count()
+    }
+
+    /**
+     * @brief A copy whose soft-masked positions are `N`.
+     *
+     * Every individual's call is missing at an `N`, so those columns carry no
+     * information and drop out of PCA like invariant ones, while positions
+     * keep their genome coordinates.
+     */
+    pub fn without_soft_masked(&self) -> Reference {
+        let sequence = self
+            .sequence
+            .iter()
+            .zip(&self.soft_mask)
+            .map(|(&base, &masked)| if masked { b'N' } else { base })
+            .collect();
+        Reference {
+            source: self.source.clone(),
+            sequence,
+            soft_mask: vec![false; self.soft_mask.len()],
+        }
+    }
+
+    /**
+     * @brief Call variants for one individual against this reference.
+     *
+     * @param individual The individual's sequence; must match the reference length.
+     * @return One value per position, as variant_calling::call_variants, or
+     *         LengthMismatch.
+     */
+    pub fn call_variants(&self, individual: &[u8]) -> Result<Vec<f64>, PcaError> {
+        if individual.len() != self.sequence.len() {
+            return Err(PcaError::LengthMismatch {
+                file: "individual".to_string(),
+                expected: self.sequence.len(),
+                found: individual.len(),
+            });
+        }
+        Ok(variant_calling::call_variants(&self.sequence, individual))
+    }
+
+    /**
+     * @brief Call variants for a batch of individuals.
+     *
+     * @param individuals Sequences of the batch, each the reference's length.
+     * @return The n x d call matrix (row-major), or LengthMismatch naming the
+     *         first offending individual by index.
+     */
+    pub fn call_batch(&self, individuals: &[Vec<u8>]) -> Result<Vec<f64>, PcaError> {
+        let mut calls = Vec::with_capacity(individuals.len() * self.sequence.len());
+        for (i, individual) in individuals.iter().enumerate() {
+            if individual.len() != self.sequence.len() {
+                return Err(PcaError::LengthMismatch {
+                    file: format!("individual {}", i),
+                    expected: self.sequence.len(),
+                    found: individual.len(),
+                });
+            }
+            calls.extend(variant_calling::call_variants(&self.sequence, individual));
+        }
+        Ok(calls)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn one_reference_serves_two_batches() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ref.fa");
+        std::fs::write(&path, ">ref\nACGTacgt\nACGT\n").unwrap();
+        let reference = Reference::load(path.to_str().unwrap()).unwrap();
+        assert_eq!(reference.sequence(), b"ACGTACGTACGT");
+        assert_eq!(reference.soft_masked_count(), 4);
+
+        let first = vec![b"ACGTACGTACGT".to_vec(), b"TCGTACGAACGT".to_vec()];
+        let second = vec![b"ACGAACGTNCGT".to_vec()];
+        for batch in [&first, &second] {
+            let expected: Vec<f64> = batch
+                .iter()
+                .flat_map(|individual| {
+                    variant_calling::call_variants(reference.sequence(), individual)
+                })
+                .collect();
+            let calls = reference.call_batch(batch).unwrap();
+            let bits = |m: &[f64]| m.iter().map(|x| x.to_bits()).collect::<Vec<_>>();
+            assert_eq!(bits(&calls), bits(&expected));
+        }
+        assert_eq!(reference.call_batch(&first).unwrap()[..12], [0.0; 12]);
+
+        let short = vec![first[0].clone(), b"ACGT".to_vec()];
+        assert!(matches!(
+            reference.call_batch(&short),
+            Err(PcaError::LengthMismatch { found: 4, .. })
+        ));
+    }
+}
@@ -0,0 +1,462 @@
+/*!
+ * @file stats.rs
+ * @brief Statistical checks around PCA: Bartlett's test of sphericity and the
+ *        Kaiser-Meyer-Olkin (KMO) measure on a correlation matrix, and the
+ *        Tracy-Widom test and Horn's parallel analysis for the number of
+ *        significant components.
+ *
+ * Author: Namir Garib
+ * Created: January 2025
+ */
+
+use crate::error::PcaError;
+use crate::pca::{center_data, dual_pca};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+
+/**
+ * @struct BartlettTest
+ * @brief Result of Bartlett's test that the correlation matrix is the identity.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BartlettTest {
+    /// -(n - 1 - (2p + 5) / 6) * ln|R|
+    pub chi_square: f64,
+    /// p(p - 1) / 2
+    pub df: f64,
+    /// Upper-tail chi-square probability; small values mean PCA has structure to find.
+    pub p_value: f64,
+}
+
+/**
+ * @brief Bartlett's test of sphericity.
+ *
+ * @param corr Correlation matrix (p x p), row-major.
+ * @param p    Number of variables.
+ * @param n    Number of samples the matrix was computed from.
+ * @return The test, or None when the matrix is singular (e.g. p >= n, the
+ *         usual case for raw SNP data) or p < 2.
+ */
+pub fn bartlett_sphericity(corr: &[f64], p: usize, n: usize) -> Option<BartlettTest> {
+    if p < 2 {
+        return None;
+    }
+    let chol = cholesky(corr, p)?;
+    let ln_det: f64 = (0..p).map(|i| 2.0 * chol[i * p + i].ln()).sum();
+    let pf = p as f64;
+    let chi_square = -((n as f64 - 1.0) - (2.0 * pf + 5.0) / 6.0) * ln_det;
+    let df = pf * (pf - 1.0) / 2.0;
+    Some(BartlettTest {
+        chi_square,
+        df,
+        p_value: chi_square_sf(chi_square, df),
+    })
+}
+
+/**
+ * @brief Kaiser-Meyer-Olkin measure of sampling adequacy.
+ *
+ * @param corr Correlation matrix (p x p), row-major.
+ * @param p    Number of variables.
+ * @return sum r_ij^2 / (sum r_ij^2 + sum a_ij^2) over i != j, where a_ij are the
+ *         partial correlations from the inverse matrix; None if the matrix is
+ *         singular or p < 2.
+ *
+ * Values near 1 suit PCA; below 0.5 is conventionally unacceptable. A matrix
+ * close to the identity has partial correlations as large as the plain ones,
+ * so it scores about 0.5.
+ */
+pub fn kmo(corr: &[f64], p: usize) -> Option<f64> {
+    if p < 2 {
+        return None;
+    }
+    let inverse = inverse_from_cholesky(&cholesky(corr, p)?, p);
+    let mut r_sq = 0.0;
+    let mut a_sq = 0.0;
+    for i in 0..p {
+        for j in 0..p {
+            if i == j {
+                continue;
+            }
+            let partial = -inverse[i * p + j] / (inverse[i * p + i] * inverse[j * p + j]).sqrt();
+            r_sq += corr[i * p + j] * corr[i * p + j];
+            a_sq += partial * partial;
+        }
+    }
+    if r_sq + a_sq == 0.0 {
+        return None;
+    }
+    Some(r_sq / (r_sq + a_sq))
+}
+
+/**
+ * @brief Tracy-Widom test of how many principal components are significant,
+ *        as in EIGENSOFT's twstats (Patterson, Price & Reich 2006).
+ *
+ * @param eigenvalues Eigenvalues in descending order (all of them, not just the
+ *                    kept components).
+ * @param n           Number of samples.
+ * @param d           Number of markers.
+ * @return One p-value per eigenvalue; NaN where no test is possible (beyond the
+ *         min(n - 1, d) non-null eigenvalues, or with fewer than two left).
+ *
+ * Component k is tested against the eigenvalues k.. only, with the effective
+ * number of markers re-estimated from them each time (which absorbs LD). The
+ * TW1 distribution is approximated by a shifted gamma (Chiani 2014), accurate
+ * to about 1e-3 in the tail that matters here.
+ */
+pub fn tracy_widom_test(eigenvalues: &[f64], n: usize, d: usize) -> Vec<f64> {
+    let m = eigenvalues.len().min(n.saturating_sub(1)).min(d);
+    let mut p_values = vec![f64::NAN; eigenvalues.len()];
+    for k in 0..m {
+        let rest = &eigenvalues[k..m];
+        let count = rest.len() as f64;
+        let sum: f64 = rest.iter().sum();
+        let sum_sq: f64 = rest.iter().map(|l| l * l).sum();
+        if count < 2.0 || sum <= 0.0 {
+            break;
+        }
+
+        // Effective number of markers, by matching the first two moments of a
+        // Wishart spectrum; fall back to d if the estimate breaks down
+        let denom = count * sum_sq - sum * sum;
+        let markers = if denom > 0.0 {
+            (count + 1.0) * sum * sum / denom
+        } else {
+            d as f64
+        };
+        if markers <= 1.0 {
+            break;
+        }
+
+        let scaled = count * rest[0] / sum;
+        let root_n = (markers - 1.0).sqrt();
+        let root_m = count.sqrt();
+        let mu = (root_n + root_m).powi(2) / markers;
+        let sigma = (root_n + root_m) / markers * (1.0 / root_n + 1.0 / root_m).cbrt();
+        p_values[k] = tracy_widom_sf((scaled - mu) / sigma);
+    }
+    p_values
+}
+
+/**
+ * @brief Number of leading components with a Tracy-Widom p-value below alpha.
+ */
+pub fn significant_components(p_values: &[f64], alpha: f64) -> usize {
+    p_values.iter().take_while(|&&p| p < alpha).count()
+}
+
+/**
+ * @brief Horn's parallel analysis: how many components beat those of data
+ *        with the same columns but no structure.
+ *
+ * @param data   Data in row-major format: n x d, without missing values.
+ * @param n      Number of samples.
+ * @param d      Dimension.
+ * @param k      Largest number of components to consider.
+ * @param n_iter Number of permuted data sets.
+ * @param seed   Seed for the permutations, so the answer is reproducible.
+ * @return Number of leading components whose eigenvalue exceeds the 95th
+ *         percentile of the same component over the permuted data sets; 0 if
+ *         n < 2 or n_iter is 0. InvalidInput if the data hold a NaN or
+ *         infinite value.
+ *
+ * Each permuted data set shuffles every column independently across samples,
+ * which keeps allele frequencies but destroys the correlation between SNPs.
+ */
+pub fn parallel_analysis(
+    data: &[f64],
+    n: usize,
+    d: usize,
+    k: usize,
+    n_iter: usize,
+    seed: u64,
+) -> Result<usize, PcaError> {
+    const PERCENTILE: f64 = 0.95;
+    if n < 2 || d == 0 || k == 0 || n_iter == 0 {
+        return Ok(0);
+    }
+    let observed = dual_pca(&center_data(data, n, d), n, d, k)?.eigenvalues;
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut permuted = data.to_vec();
+    let mut column = vec![0.0; n];
+    let mut null: Vec<Vec<f64>> = vec![Vec::with_capacity(n_iter); observed.len()];
+    for _ in 0..n_iter {
+        for col in 0..d {
+            for (row, value) in column.iter_mut().enumerate() {
+                *value = data[row * d + col];
+            }
+            column.shuffle(&mut rng);
+            for (row, &value) in column.iter().enumerate() {
+                permuted[row * d + col] = value;
+            }
+        }
+        let eigenvalues = dual_pca(&center_data(&permuted, n, d), n, d, k)?.eigenvalues;
+        for (component, samples) in null.iter_mut().enumerate() {
+            samples.push(eigenvalues.get(component).copied().unwrap_or(0.0));
+        }
+    }
+
+    let rank = ((PERCENTILE * n_iter as f64).ceil() as usize).clamp(1, n_iter) - 1;
+    for samples in null.iter_mut() {
+        samples.sort_by(|a, b| a.total_cmp(b));
+    }
+    Ok(observed
+        .iter()
+        .zip(&null)
+        .take_while(|(&eigenvalue, samples)| eigenvalue > samples[rank])
+        .count())
+}
+
+/**
+ * @brief Upper tail of the Tracy-Widom (beta = 1) distribution, via the
+ *        shifted gamma approximation TW1 ~ Gamma(46.446, 0.186054) - 9.84801.
+ */
+fn tracy_widom_sf(x: f64) -> f64 {
+    const SHAPE: f64 = 46.446;
+    const SCALE: f64 = 0.186054;
+    const SHIFT: f64 = 9.84801;
+    if x + SHIFT <= 0.0 {
+        return 1.0;
+    }
+    1.0 - regularized_gamma_p(SHAPE, (x + SHIFT) / SCALE)
+}
+
+/**
+ * @brief Upper-tail probability P(X > x) of a chi-square distribution.
+ */
+pub fn chi_square_sf(x: f64, df: f64) -> f64 {
+    if x <= 0.0 {
+        return 1.0;
+    }
+    1.0 - regularized_gamma_p(df / 2.0, x / 2.0)
+}
+
+/**
+ * @brief Regularized lower incomplete gamma P(a, x): a series below a + 1,
+ *        a continued fraction above (Numerical Recipes' gammp).
+ */
+fn regularized_gamma_p(a: f64, x: f64) -> f64 {
+    const MAX_ITER: usize = 500;
+    const EPS: f64 = 1e-14;
+    let log_prefix = a * x.ln() - x - ln_gamma(a);
+
+    if x < a + 1.0 {
+        let mut term = 1.0 / a;
+        let mut sum = term;
+        let mut denom = a;
+        for _ in 0..MAX_ITER {
+            denom += 1.0;
+            term *= x / denom;
+            sum += term;
+            if term.abs() < sum.abs() * EPS {
+                break;
+            }
+        }
+        return (sum * log_prefix.exp()).min(1.0);
+    }
+
+    // Lentz's method for the continued fraction of Q(a, x)
+    let tiny = 1e-300;
+    let mut b = x + 1.0 - a;
+    let mut c = 1.0 / tiny;
+    let mut d = 1.0 / b;
+    let mut h = d;
+    for i in 1..=MAX_ITER {
+        let an = -(i as f64) * (i as f64 - a);
+        b += 2.0;
+        d = an * d + b;
+        if d.abs() < tiny {
+            d = tiny;
+        }
+        c = b + an / c;
+        if c.abs() < tiny {
+            c = tiny;
+        }
+        d = 1.0 / d;
+        let delta = d * c;
+        h *= delta;
+        if (delta - 1.0).abs() < EPS {
+            break;
+        }
+    }
+    (1.0 - log_prefix.exp() * h).max(0.0)
+}
+
+/**
+ * @brief ln Gamma(x) for x > 0 (Lanczos approximation, g = 7).
+ */
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+    if x < 0.5 {
+        // Reflection formula
+        let pi = std::f64::consts::PI;
+        return (pi / (pi * x).sin()).ln() - ln_gamma(1.0 - x);
+    }
+    let x = x - 1.0;
+    let mut sum = COEFFS[0];
+    for (i, &c) in COEFFS.iter().enumerate().skip(1) {
+        sum += c / (x + i as f64);
+    }
+    let t = x + 7.5;
+    0.5 * (2.0 * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + sum.ln()
+}
+
+/**
+ * @brief Lower Cholesky factor L (p x p, row-major) with L L^T = m, or None if
+ *        m is not numerically positive definite.
+ */
+fn cholesky(m: &[f64], p: usize) -> Option<Vec<f64>> {
+    let mut l = vec![0.0; p * p];
+    for i in 0..p {
+        for j in 0..=i {
+            let mut sum = m[i * p + j];
+            for k in 0..j {
+                sum -= l[i * p + k] * l[j * p + k];
+            }
+            if i == j {
+                if sum <= 1e-12 * m[i * p + i].abs().max(1.0) {
+                    return None;
+                }
+                l[i * p + i] = sum.sqrt();
+            } else {
+                l[i * p + j] = sum / l[j * p + j];
+            }
+        }
+    }
+    Some(l)
+}
+
+/**
+ * @brief Inverse of L L^T given its Cholesky factor L.
+ */
+fn inverse_from_cholesky(l: &[f64], p: usize) -> Vec<f64> {
+    let mut inverse = vec![0.0; p * p];
+    let mut column = vec![0.0; p];
+    for col in 0..p {
+        // Solve L y = e_col, then L^T x = y
+        for i in 0..p {
+            let mut sum = if i == col { 1.0 } else { 0.0 };
+            for k in 0..i {
+                sum -= l[i * p + k] * column[k];
+            }
+            column[i] = sum / l[i * p + i];
+        }
+        for i in (0..p).rev() {
+            let mut sum = column[i];
+            for k in (i + 1)..p {
+                sum -= l[k * p + i] * column[k];
+            }
+            column[i] = sum / l[i * p + i];
+        }
+        for row in 0..p {
+            inverse[row * p + col] = column[row];
+        }
+    }
+    inverse
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn near_identity_correlation_has_low_kmo() {
+        let p = 5;
+        let mut corr = vec![0.0; p * p];
+        for i in 0..p {
+            for j in 0..p {
+                corr[i * p + j] = if i == j {
+                    1.0
+                } else {
+                    0.02 * ((i + j) % 3) as f64 - 0.02
+                };
+            }
+        }
+        let value = kmo(&corr, p).unwrap();
+        assert!(value < 0.6, "KMO {}", value);
+        let bartlett = bartlett_sphericity(&corr, p, 100).unwrap();
+        assert!(bartlett.p_value > 0.05, "Bartlett p {}", bartlett.p_value);
+
+        // Strongly equicorrelated variables are the opposite case
+        let strong: Vec<f64> = (0..p * p)
+            .map(|idx| if idx % (p + 1) == 0 { 1.0 } else { 0.8 })
+            .collect();
+        assert!(kmo(&strong, p).unwrap() > 0.8);
+        assert!(bartlett_sphericity(&strong, p, 100).unwrap().p_value < 1e-6);
+    }
+
+    /// Genotypes (0, 1 or 2) of n samples at d markers, split evenly over
+    /// `populations` groups whose allele frequencies differ per marker.
+    fn simulate_genotypes(n: usize, d: usize, populations: usize, seed: u64) -> Vec<f64> {
+        use rand::Rng;
+
+        let mut rng = StdRng::seed_from_u64(seed);
+        let frequencies: Vec<Vec<f64>> = (0..populations)
+            .map(|_| (0..d).map(|_| rng.gen_range(0.05..0.95)).collect())
+            .collect();
+        let mut data = vec![0.0; n * d];
+        for s in 0..n {
+            let freq = &frequencies[s * populations / n];
+            for c in 0..d {
+                data[s * d + c] = (0..2).filter(|_| rng.gen::<f64>() < freq[c]).count() as f64;
+            }
+        }
+        data
+    }
+
+    /// All min(n, d) eigenvalues of the data's covariance, descending.
+    fn eigenvalues(data: &[f64], n: usize, d: usize) -> Vec<f64> {
+        dual_pca(&center_data(data, n, d), n, d, n)
+            .unwrap()
+            .eigenvalues
+    }
+
+    #[test]
+    fn tracy_widom_finds_the_structure_of_three_populations() {
+        let (n, d) = (60, 400);
+        let structured = eigenvalues(&simulate_genotypes(n, d, 3, 73), n, d);
+        let p_values = tracy_widom_test(&structured, n, d);
+        assert_eq!(p_values.len(), structured.len());
+        assert!(
+            p_values[0] < 1e-4 && p_values[1] < 1e-4,
+            "{:?}",
+            &p_values[..3]
+        );
+        assert_eq!(significant_components(&p_values, 0.05), 2);
+
+        let null = eigenvalues(&simulate_genotypes(n, d, 1, 73), n, d);
+        assert_eq!(
+            significant_components(&tracy_widom_test(&null, n, d), 0.05),
+            0
+        );
+    }
+
+    #[test]
+    fn parallel_analysis_separates_structure_from_noise() {
+        let (n, d) = (60, 400);
+        let structured = simulate_genotypes(n, d, 3, 77);
+        assert_eq!(parallel_analysis(&structured, n, d, 10, 20, 1).unwrap(), 2);
+        let noise = simulate_genotypes(n, d, 1, 77);
+        assert_eq!(parallel_analysis(&noise, n, d, 10, 20, 1).unwrap(), 0);
+
+        let mut bad = noise.clone();
+        bad[5] = f64::NAN;
+        assert!(matches!(
+            parallel_analysis(&bad, n, d, 10, 20, 1),
+            Err(PcaError::InvalidInput(_))
+        ));
+    }
+}
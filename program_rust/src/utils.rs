@@ -6,49 +6,61 @@
  * Created: January 2025
  */
 
+use crate::error::PcaError;
 use std::fs::File;
-use std::io::{Read, Seek, SeekFrom};
+use std::io::{self, BufWriter, Read};
 use std::path::Path;
 
 /**
  * @brief Get the file length in bytes.
  *
  * @param path The path to the file
- * @return The file length in bytes, or 0 if an error occurs.
- */
-pub fn get_file_length(path: &str) -> usize {
-    let file_path = Path::new(path);
-    match File::open(file_path) {
-        Ok(mut file) => {
-            if let Ok(metadata) = file.metadata() {
-                return metadata.len() as usize;
-            }
-        }
-        Err(e) => {
-            eprintln!("get_file_length: Failed to open file {}: {}", path, e);
-            return 0;
-        }
-    }
-    0
+ * @return The file length in bytes, or a PcaError if the file cannot be opened.
+ */
+pub fn get_file_length(path: &str) -> Result<usize, PcaError> {
+    let file = File::open(Path::new(path)).map_err(|e| PcaError::io(path, e))?;
+    let metadata = file.metadata().map_err(|e| PcaError::io(path, e))?;
+    Ok(metadata.len() as usize)
 }
 
+/// Chunk size used by read_file_in_chunks (1MB).
+pub const DEFAULT_CHUNK_SIZE: usize = 1024 * 1024;
+
 /**
  * @brief Read the entire file into a Vec<u8> in chunks.
  *
  * @param path   The file path.
  * @param length Number of bytes to read (assumes we know the file size).
- * @return A Result<Vec<u8>, String> containing the file data or an error message.
+ * @return A Result<Vec<u8>, PcaError> containing the file data.
  *
  * For extremely large files, consider memory mapping or streaming approach.
  */
-pub fn read_file_in_chunks(path: &str, length: usize) -> Result<Vec<u8>, String> {
-    let mut file = match File::open(path) {
-        Ok(f) => f,
-        Err(e) => return Err(format!("Failed to open file {}: {}", path, e)),
-    };
+pub fn read_file_in_chunks(path: &str, length: usize) -> Result<Vec<u8>, PcaError> {
+    read_file_in_chunks_with(path, length, DEFAULT_CHUNK_SIZE)
+}
+
+/**
+ * @brief read_file_in_chunks with an explicit chunk size in bytes.
+ *
+ * Larger chunks mean fewer read calls; smaller ones suit memory-constrained
+ * systems. A zero chunk size is an InvalidInput error.
+ */
+pub fn read_file_in_chunks_with(
+    path: &str,
+    length: usize,
+    chunk_size: usize,
+) -> Result<Vec<u8>, PcaError> {
+    if chunk_size == 0 {
+        return Err(PcaError::InvalidInput(
+            "chunk size must be at least 1 byte".to_string(),
+        ));
+    }
+    let mut file = File::open(path).map_err(|e| PcaError::io(path, e))?;
+    if length == 0 {
+        return Ok(Vec::new());
+    }
 
     let mut buffer = vec![0u8; length];
-    let chunk_size = 1024 * 1024; // 1MB
     let mut total_read = 0;
 
     while total_read < length {
@@ -56,13 +68,941 @@ pub fn read_file_in_chunks(path: &str, length: usize) -> Result<Vec<u8>, String>
         match file.read(&mut buffer[total_read..total_read + to_read]) {
             Ok(n) => {
                 if n == 0 {
-                    return Err("Unexpected EOF".to_string());
+                    return Err(PcaError::io(
+                        path,
+                        io::Error::new(io::ErrorKind::UnexpectedEof, "Unexpected EOF"),
+                    ));
                 }
                 total_read += n;
             }
-            Err(e) => return Err(format!("Error reading file: {}", e)),
+            Err(e) => return Err(PcaError::io(path, e)),
         }
     }
 
     Ok(buffer)
 }
+
+/**
+ * @brief Write a file so that readers see either the old file or the complete new one.
+ *
+ * @param path  Final location of the file.
+ * @param write Produces the contents through a buffered writer.
+ * @return Ok(()) once the data is synced and renamed to `path`, or the first
+ *         io::Error.
+ *
+ * The data goes to a hidden temporary file in the same directory, which is
+ * renamed over `path` only after `write` succeeds; on any error the temporary
+ * file is removed and `path` is left untouched.
+ */
+pub fn write_atomically<F>(path: &Path, write: F) -> io::Result<()>
+where
+    F: FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+{
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+    let temp_path = path.with_file_name(format!(
+        ".{}.{}.tmp",
+        file_name.to_string_lossy(),
+        std::process::id()
+    ));
+
+    let result = File::create(&temp_path).and_then(|file| {
+        let mut writer = BufWriter::new(file);
+        write(&mut writer)?;
+        let file = writer.into_inner().map_err(|e| e.into_error())?;
+        file.sync_all()
+    });
+    match result.and_then(|()| std::fs::rename(&temp_path, path)) {
+        Ok(()) => Ok(()),
+        Err(e) => {
+            let _ = std::fs::remove_file(&temp_path);
+            Err(e)
+        }
+    }
+}
+
+/**
+ * @struct FloatFormat
+ * @brief How floating-point values are written to every output CSV.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FloatFormat {
+    /// Digits after the decimal point (of the mantissa, when scientific).
+    pub precision: usize,
+    /// Write `1.234560e-7` rather than `0.000000`; keeps tiny eigenvalues readable.
+    pub scientific: bool,
+}
+
+impl Default for FloatFormat {
+    fn default() -> Self {
+        FloatFormat {
+            precision: 6,
+            scientific: false,
+        }
+    }
+}
+
+impl FloatFormat {
+    /// `value` with this precision and notation; NaN and infinities as Rust prints them.
+    pub fn format(&self, value: f64) -> String {
+        if self.scientific {
+            format!("{:.*e}", self.precision, value)
+        } else {
+            format!("{:.*}", self.precision, value)
+        }
+    }
+}
+
+/**
+ * @brief Check whether a path looks like a FASTA file (.fa / .fasta, optionally .gz).
+ *
+ * @param path The file path.
+ * @return true if the extension is fa or fasta (case-insensitive).
+ */
+pub fn is_fasta_path(path: &str) -> bool {
+    // Look through a trailing .gz (e.g. sample.fa.gz)
+    let path = path.strip_suffix(".gz").unwrap_or(path);
+    match Path::new(path).extension().and_then(|e| e.to_str()) {
+        Some(ext) => ext.eq_ignore_ascii_case("fa") || ext.eq_ignore_ascii_case("fasta"),
+        None => false,
+    }
+}
+
+/**
+ * @brief Check whether a path looks like a FASTQ file (.fq / .fastq, optionally .gz).
+ *
+ * @param path The file path.
+ * @return true if the extension is fq or fastq (case-insensitive).
+ */
+pub fn is_fastq_path(path: &str) -> bool {
+    let path = path.strip_suffix(".gz").unwrap_or(path);
+    match Path::new(path).extension().and_then(|e| e.to_str()) {
+        Some(ext) => ext.eq_ignore_ascii_case("fq") || ext.eq_ignore_ascii_case("fastq"),
+        None => false,
+    }
+}
+
+/**
+ * @enum InvalidBasePolicy
+ * @brief What sequence readers do with a byte other than A, C, G, T or N.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InvalidBasePolicy {
+    /// Fail with a Parse error naming the byte and its position, so a binary
+    /// or otherwise wrong file is caught before it turns into variants.
+    #[default]
+    Error,
+    /// Drop the byte, shortening the sequence.
+    Skip,
+    /// Replace the byte with `N`, which variant calling treats as missing.
+    Missing,
+}
+
+/**
+ * @brief Apply an InvalidBasePolicy to uppercased bases.
+ *
+ * @param source Path (or path and record) named in the error.
+ * @param bases  Uppercased sequence as returned by the readers.
+ * @param policy What to do with bytes other than A, C, G, T and N.
+ * @return The bases, possibly shortened or with `N` substituted, or a Parse
+ *         error at the first invalid byte under InvalidBasePolicy::Error.
+ */
+pub fn apply_base_policy(
+    source: &str,
+    mut bases: Vec<u8>,
+    policy: InvalidBasePolicy,
+) -> Result<Vec<u8>, PcaError> {
+    let is_valid = |b: &u8| matches!(b, b'A' | b'C' | b'G' | b'T' | b'N');
+    match policy {
+        InvalidBasePolicy::Error => {
+            if let Some(pos) = bases.iter().position(|b| !is_valid(b)) {
+                let byte = bases[pos];
+                let shown = if byte.is_ascii_graphic() {
+                    format!("'{}'", byte as char)
+                } else {
+                    format!("0x{:02X}", byte)
+                };
+                return Err(PcaError::Parse(format!(
+                    "{}: invalid base {} at position {} (expected A, C, G, T or N; is this a sequence file?)",
+                    source,
+                    shown,
+                    pos + 1
+                )));
+            }
+        }
+        InvalidBasePolicy::Skip => bases.retain(is_valid),
+        InvalidBasePolicy::Missing => {
+            for b in bases.iter_mut().filter(|b| !is_valid(b)) {
+                *b = b'N';
+            }
+        }
+    }
+    Ok(bases)
+}
+
+/**
+ * @brief Read a FASTA file and return the concatenated sequence.
+ *
+ * @param path The FASTA file path.
+ * @return A Result<Vec<u8>, PcaError> with the uppercased bases of all records.
+ *
+ * Header lines (starting with '>') are dropped and all whitespace, including
+ * line breaks, is removed, so the returned length equals the base count.
+ * Gzip-compressed files are decompressed transparently (see read_maybe_gzipped).
+ * Bytes other than A, C, G, T and N are an error (see InvalidBasePolicy).
+ */
+pub fn read_fasta(path: &str) -> Result<Vec<u8>, PcaError> {
+    let raw = read_maybe_gzipped(path)?;
+    let bases = sequence_bases(&raw, true)
+        .map(|b| b.to_ascii_uppercase())
+        .collect();
+    apply_base_policy(path, bases, InvalidBasePolicy::Error)
+}
+
+/**
+ * @brief The bases of a file's contents: whitespace (including line breaks)
+ *        removed and, for FASTA, header lines dropped.
+ */
+fn sequence_bases(raw: &[u8], fasta: bool) -> impl Iterator<Item = u8> + '_ {
+    raw.split(|&b| b == b'\n')
+        .filter(move |line| !(fasta && line.first() == Some(&b'>')))
+        .flat_map(|line| line.iter().copied().filter(|b| !b.is_ascii_whitespace()))
+}
+
+/**
+ * @brief Read a genome sequence, parsing FASTA or FASTQ when the extension says so.
+ *
+ * @param path The file path (raw sequence, FASTA or FASTQ, optionally gzipped).
+ * @return A Result<Vec<u8>, PcaError> with the uppercased bases.
+ *
+ * Raw sequence files get the same whitespace stripping as FASTA, so a trailing
+ * newline or CRLF line endings do not change the sequence or its length.
+ * FASTQ records are concatenated without quality masking
+ * (see fastq::read_fastq_sequence). Invalid bytes are an error; see
+ * read_sequence_with for the other policies.
+ */
+pub fn read_sequence(path: &str) -> Result<Vec<u8>, PcaError> {
+    read_sequence_with(path, InvalidBasePolicy::Error)
+}
+
+/**
+ * @brief read_sequence with an explicit policy for bytes other than A, C, G, T and N.
+ */
+pub fn read_sequence_with(path: &str, policy: InvalidBasePolicy) -> Result<Vec<u8>, PcaError> {
+    let bases = if is_fastq_path(path) {
+        crate::fastq::read_fastq_sequence(path, None)?
+    } else {
+        let raw = read_maybe_gzipped(path)?;
+        sequence_bases(&raw, is_fasta_path(path))
+            .map(|b| b.to_ascii_uppercase())
+            .collect()
+    };
+    apply_base_policy(path, bases, policy)
+}
+
+/**
+ * @brief read_sequence_with, also reporting which bases were soft-masked.
+ *
+ * @param path   The file path (raw sequence, FASTA or FASTQ, optionally gzipped).
+ * @param policy What to do with bytes other than A, C, G, T and N.
+ * @return (uppercased bases, mask) where mask[i] is true if base i was
+ *         lowercase in the file (a soft-masked repeat). FASTQ input is
+ *         uppercased by its reader, so its mask is all false.
+ */
+pub fn read_sequence_and_mask(
+    path: &str,
+    policy: InvalidBasePolicy,
+) -> Result<(Vec<u8>, Vec<bool>), PcaError> {
+    let mut bases = if is_fastq_path(path) {
+        crate::fastq::read_fastq_sequence(path, None)?
+    } else {
+        let raw = read_maybe_gzipped(path)?;
+        sequence_bases(&raw, is_fasta_path(path)).collect()
+    };
+    let mut mask: Vec<bool> = bases.iter().map(u8::is_ascii_lowercase).collect();
+    bases.make_ascii_uppercase();
+    if policy == InvalidBasePolicy::Skip {
+        // Keep the mask aligned with the bases that survive
+        let mut kept = bases
+            .iter()
+            .map(|b| matches!(b, b'A' | b'C' | b'G' | b'T' | b'N'));
+        mask.retain(|_| kept.next().unwrap_or(false));
+    }
+    let bases = apply_base_policy(path, bases, policy)?;
+    Ok((bases, mask))
+}
+
+/**
+ * @brief Number of bases read_sequence would return, without keeping them.
+ *
+ * @param path The file path (raw sequence, FASTA or FASTQ, optionally gzipped).
+ * @return The sequence length, which is what the n x d matrix is built from;
+ *         unlike get_file_length it ignores line breaks and FASTA headers.
+ */
+pub fn sequence_length(path: &str) -> Result<usize, PcaError> {
+    sequence_length_with(path, InvalidBasePolicy::Error)
+}
+
+/**
+ * @brief Number of bases read_sequence_with would return under `policy`;
+ *        only Skip changes the count, and Error does not check the bytes.
+ */
+pub fn sequence_length_with(path: &str, policy: InvalidBasePolicy) -> Result<usize, PcaError> {
+    let keep = |b: &u8| {
+        policy != InvalidBasePolicy::Skip
+            || matches!(b.to_ascii_uppercase(), b'A' | b'C' | b'G' | b'T' | b'N')
+    };
+    if is_fastq_path(path) {
+        let bases = crate::fastq::read_fastq_sequence(path, None)?;
+        return Ok(bases.iter().filter(|b| keep(b)).count());
+    }
+    let raw = read_maybe_gzipped(path)?;
+    Ok(sequence_bases(&raw, is_fasta_path(path))
+        .filter(|b| keep(b))
+        .count())
+}
+
+/**
+ * @brief Read a multi-record FASTA where every record is a separate sample.
+ *
+ * @param path The FASTA file path (optionally gzipped).
+ * @return A Result<Vec<(String, Vec<u8>)>, PcaError> of (record id, sequence)
+ *         pairs in file order.
+ *
+ * The id is the header text after '>' up to the first whitespace. Sequences are
+ * uppercased with whitespace removed, so blank lines and CRLF line endings are
+ * harmless. Sequence data before the first header is a parse error, as are
+ * bytes other than A, C, G, T and N (see read_multifasta_with).
+ */
+pub fn read_multifasta(path: &str) -> Result<Vec<(String, Vec<u8>)>, PcaError> {
+    read_multifasta_with(path, InvalidBasePolicy::Error)
+}
+
+/**
+ * @brief read_multifasta with an explicit policy for invalid bytes, applied
+ *        to each record separately.
+ */
+pub fn read_multifasta_with(
+    path: &str,
+    policy: InvalidBasePolicy,
+) -> Result<Vec<(String, Vec<u8>)>, PcaError> {
+    let raw = read_maybe_gzipped(path)?;
+
+    let mut records: Vec<(String, Vec<u8>)> = Vec::new();
+    for (line_no, line) in raw.split(|&b| b == b'\n').enumerate() {
+        if let Some(header) = line.strip_prefix(b">") {
+            let header = String::from_utf8_lossy(header);
+            let id = header.split_whitespace().next().unwrap_or("");
+            if id.is_empty() {
+                return Err(PcaError::Parse(format!(
+                    "{}:{}: record header has no id",
+                    path,
+                    line_no + 1
+                )));
+            }
+            records.push((id.to_string(), Vec::new()));
+            continue;
+        }
+        let mut bases = line
+            .iter()
+            .filter(|b| !b.is_ascii_whitespace())
+            .map(|b| b.to_ascii_uppercase())
+            .peekable();
+        if bases.peek().is_none() {
+            continue;
+        }
+        match records.last_mut() {
+            Some((_, sequence)) => sequence.extend(bases),
+            None => {
+                return Err(PcaError::Parse(format!(
+                    "{}:{}: sequence data before the first '>' header",
+                    path,
+                    line_no + 1
+                )));
+            }
+        }
+    }
+
+    records
+        .into_iter()
+        .map(|(id, sequence)| {
+            let sequence = apply_base_policy(&format!("{} ({})", path, id), sequence, policy)?;
+            Ok((id, sequence))
+        })
+        .collect()
+}
+
+/**
+ * @brief Check whether a file starts with the gzip magic bytes (1f 8b).
+ *
+ * @param path The file path.
+ * @return Ok(true) for gzip content regardless of extension, or a PcaError if
+ *         the file cannot be opened.
+ */
+pub fn is_gzip_file(path: &str) -> Result<bool, PcaError> {
+    let mut magic = [0u8; 2];
+    let mut file = File::open(path).map_err(|e| PcaError::io(path, e))?;
+    Ok(matches!(file.read(&mut magic), Ok(2)) && magic == [0x1f, 0x8b])
+}
+
+/**
+ * @brief Read a whole file, decompressing it if it starts with the gzip magic bytes.
+ *
+ * @param path The file path, or an http(s) URL (see read_url).
+ * @return A Result<Vec<u8>, PcaError> with the (decompressed) contents.
+ *
+ * The length comes from the decompressed stream, not the on-disk size.
+ * Decompression requires the `gzip` feature; without it a gzip file is an error
+ * rather than being misread as sequence.
+ */
+pub fn read_maybe_gzipped(path: &str) -> Result<Vec<u8>, PcaError> {
+    if is_url(path) {
+        let raw = read_url(path)?;
+        if !raw.starts_with(&[0x1f, 0x8b]) {
+            return Ok(raw);
+        }
+        return gunzip(path, raw.as_slice());
+    }
+
+    if !is_gzip_file(path)? {
+        let length = get_file_length(path)?;
+        return read_file_in_chunks(path, length);
+    }
+    let file = File::open(path).map_err(|e| PcaError::io(path, e))?;
+    gunzip(path, file)
+}
+
+/**
+ * @brief Decompress a whole gzip stream (`path` is only used in errors).
+ */
+fn gunzip<R: Read>(path: &str, reader: R) -> Result<Vec<u8>, PcaError> {
+    #[cfg(feature = "gzip")]
+    {
+        let mut decoder = flate2::read::MultiGzDecoder::new(reader);
+        let mut buffer = Vec::new();
+        decoder
+            .read_to_end(&mut buffer)
+            .map_err(|e| PcaError::io(path, e))?;
+        Ok(buffer)
+    }
+    #[cfg(not(feature = "gzip"))]
+    {
+        drop(reader);
+        Err(PcaError::InvalidInput(format!(
+            "{} is gzip-compressed; rebuild with the `gzip` feature to read it",
+            path
+        )))
+    }
+}
+
+/**
+ * @brief Check whether a path is an http:// or https:// URL.
+ */
+pub fn is_url(path: &str) -> bool {
+    let lower = path.get(..8).unwrap_or(path).to_ascii_lowercase();
+    lower.starts_with("http://") || lower.starts_with("https://")
+}
+
+/**
+ * @brief Download the body of an http(s) URL.
+ *
+ * @param url The URL.
+ * @return The response body, or a PcaError for network failures and non-2xx
+ *         statuses.
+ *
+ * Content-Length, when sent, only pre-sizes the buffer; the body is always read
+ * to its end. Requires the `http` feature; without it a URL is an error rather
+ * than being looked up as a local path.
+ */
+pub fn read_url(url: &str) -> Result<Vec<u8>, PcaError> {
+    #[cfg(feature = "http")]
+    {
+        let to_error = |e: reqwest::Error| PcaError::io(url, io::Error::other(e));
+        let mut response = reqwest::blocking::get(url)
+            .and_then(|response| response.error_for_status())
+            .map_err(to_error)?;
+        let capacity = response.content_length().unwrap_or(0) as usize;
+        let mut buffer = Vec::with_capacity(capacity);
+        response
+            .read_to_end(&mut buffer)
+            .map_err(|e| PcaError::io(url, e))?;
+        Ok(buffer)
+    }
+    #[cfg(not(feature = "http"))]
+    {
+        Err(PcaError::InvalidInput(format!(
+            "{} is a URL; rebuild with the `http` feature to download it",
+            url
+        )))
+    }
+}
+
+/**
+ * @struct PackedSequence
+ * @brief A nucleotide sequence stored at 2 bits per base (A=0, C=1, G=2, T=3).
+ *
+ * Four bases share a byte, lowest bits first. Anything that is not A/C/G/T
+ * (e.g. `N`) is kept verbatim in `exceptions` and packed as A.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackedSequence {
+    /// Packed 2-bit codes, ceil(len / 4) bytes.
+    pub bits: Vec<u8>,
+    /// Number of bases.
+    pub len: usize,
+    /// (position, uppercased byte) of every non-ACGT base, by position.
+    pub exceptions: Vec<(usize, u8)>,
+}
+
+impl PackedSequence {
+    /// 2-bit code at a position (0 for exception positions).
+    pub fn code(&self, pos: usize) -> u8 {
+        (self.bits[pos / 4] >> (2 * (pos % 4))) & 0b11
+    }
+
+    /// Uppercased base at a position.
+    pub fn base(&self, pos: usize) -> u8 {
+        match self.exceptions.binary_search_by_key(&pos, |&(p, _)| p) {
+            Ok(i) => self.exceptions[i].1,
+            Err(_) => b"ACGT"[self.code(pos) as usize],
+        }
+    }
+
+    /// The uppercased byte sequence.
+    pub fn unpack(&self) -> Vec<u8> {
+        let mut seq: Vec<u8> = (0..self.len)
+            .map(|pos| b"ACGT"[self.code(pos) as usize])
+            .collect();
+        for &(pos, base) in &self.exceptions {
+            seq[pos] = base;
+        }
+        seq
+    }
+}
+
+/**
+ * @brief Pack a sequence at 2 bits per base, a quarter of its byte size.
+ *
+ * @param seq The sequence (case-insensitive).
+ * @return The PackedSequence; unpack() returns the uppercased input.
+ */
+pub fn pack_2bit(seq: &[u8]) -> PackedSequence {
+    let mut bits = vec![0u8; seq.len().div_ceil(4)];
+    let mut exceptions = Vec::new();
+    for (pos, &b) in seq.iter().enumerate() {
+        let b = b.to_ascii_uppercase();
+        let code = match b {
+            b'A' => 0,
+            b'C' => 1,
+            b'G' => 2,
+            b'T' => 3,
+            _ => {
+                exceptions.push((pos, b));
+                0
+            }
+        };
+        bits[pos / 4] |= code << (2 * (pos % 4));
+    }
+    PackedSequence {
+        bits,
+        len: seq.len(),
+        exceptions,
+    }
+}
+
+/**
+ * @brief Collect the FASTA and FASTQ genomes (.fa, .fasta, .fq, .fastq,
+ *        optionally .gz) in a directory.
+ *
+ * @param dir The directory to scan (not recursive).
+ * @return Paths sorted by file name, so row order is reproducible.
+ */
+pub fn collect_genome_files(dir: &str) -> Result<Vec<String>, PcaError> {
+    let entries = std::fs::read_dir(dir).map_err(|e| PcaError::io(dir, e))?;
+
+    let mut files: Vec<std::path::PathBuf> = Vec::new();
+    for entry in entries {
+        let path = entry.map_err(|e| PcaError::io(dir, e))?.path();
+        let name = path.to_string_lossy();
+        if path.is_file() && (is_fasta_path(&name) || is_fastq_path(&name)) {
+            files.push(path);
+        }
+    }
+    files.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+
+    Ok(files
+        .into_iter()
+        .map(|p| p.to_string_lossy().into_owned())
+        .collect())
+}
+
+/**
+ * @brief Derive a sample identifier from a genome file path.
+ *
+ * @param path The file path.
+ * @return The file name without directory, a trailing .gz, or its extension
+ *         (e.g. "data/NA12878.fa.gz" -> "NA12878").
+ */
+pub fn sample_name(path: &str) -> String {
+    let path = path.strip_suffix(".gz").unwrap_or(path);
+    Path::new(path)
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.to_string())
+}
+
+/**
+ * @brief Sample id and haplotype number of a phased haplotype file.
+ *
+ * @param path A path named `<sample>.hap1.<ext>` or `<sample>.hap2.<ext>`
+ *             (e.g. `NA12878.hap2.fa.gz`).
+ * @return (sample id, 1 or 2), or None if the file name has no haplotype tag.
+ */
+pub fn haplotype_of(path: &str) -> Option<(String, usize)> {
+    let stem = sample_name(path);
+    let (sample, tag) = stem.rsplit_once('.')?;
+    let haplotype = match tag {
+        "hap1" => 1,
+        "hap2" => 2,
+        _ => return None,
+    };
+    if sample.is_empty() {
+        return None;
+    }
+    Some((sample.to_string(), haplotype))
+}
+
+/**
+ * @brief Pair up phased haplotype files by sample id (see haplotype_of).
+ *
+ * @param files Paths of every haplotype file, in any order.
+ * @return (sample id, hap1 path, hap2 path) per sample in order of first
+ *         appearance, or a PcaError naming a file without a `.hap1`/`.hap2`
+ *         tag, a haplotype given twice, or a sample missing one of its two.
+ */
+pub fn group_haplotype_files(files: &[String]) -> Result<Vec<(String, String, String)>, PcaError> {
+    let mut samples: Vec<(String, [Option<String>; 2])> = Vec::new();
+    for path in files {
+        let (sample, haplotype) = haplotype_of(path).ok_or_else(|| {
+            PcaError::InvalidInput(format!(
+                "{}: expected a haplotype file named <sample>.hap1.<ext> or <sample>.hap2.<ext>",
+                path
+            ))
+        })?;
+        let index = match samples.iter().position(|(id, _)| *id == sample) {
+            Some(index) => index,
+            None => {
+                samples.push((sample.clone(), [None, None]));
+                samples.len() - 1
+            }
+        };
+        let slot = &mut samples[index].1[haplotype - 1];
+        if let Some(previous) = slot {
+            return Err(PcaError::InvalidInput(format!(
+                "Sample {} has two hap{} files: {} and {}",
+                sample, haplotype, previous, path
+            )));
+        }
+        *slot = Some(path.clone());
+    }
+    samples
+        .into_iter()
+        .map(|(sample, [hap1, hap2])| match (hap1, hap2) {
+            (Some(hap1), Some(hap2)) => Ok((sample, hap1, hap2)),
+            (hap1, _) => Err(PcaError::InvalidInput(format!(
+                "Sample {} has no hap{} file",
+                sample,
+                if hap1.is_none() { 1 } else { 2 }
+            ))),
+        })
+        .collect()
+}
+
+/**
+ * @brief Cap the global rayon pool used by every parallel stage.
+ *
+ * @param threads Number of worker threads; 0 keeps rayon's default (all cores).
+ * @return Ok(()) or a PcaError if the global pool was already built.
+ *
+ * Must run before the first parallel call, since rayon builds its global pool lazily.
+ */
+#[cfg(feature = "rayon")]
+pub fn configure_threads(threads: usize) -> Result<(), PcaError> {
+    if threads == 0 {
+        return Ok(());
+    }
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build_global()
+        .map_err(|e| PcaError::InvalidInput(format!("Cannot use {} threads: {}", threads, e)))
+}
+
+/**
+ * @brief Memory-map a genome file read-only instead of copying it into a Vec.
+ *
+ * @param path The file path.
+ * @return A Result<Mmap, PcaError>; the map derefs to &[u8], so it can be passed
+ *         straight to call_variants.
+ *
+ * An empty file yields an empty map rather than an error or a panic.
+ * The file must not be modified while the map is alive.
+ */
+#[cfg(feature = "mmap")]
+pub fn mmap_file(path: &str) -> Result<memmap2::Mmap, PcaError> {
+    let file = File::open(path).map_err(|e| PcaError::io(path, e))?;
+
+    // Safety: the mapping is read-only and callers are told not to mutate the file.
+    // memmap2 returns an empty map for a zero-length file instead of calling mmap(2).
+    unsafe { memmap2::Mmap::map(&file) }.map_err(|e| PcaError::io(path, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// Write `contents` to `name` in `dir` and return the path.
+    fn write_file(dir: &TempDir, name: &str, contents: &[u8]) -> String {
+        let path = dir.path().join(name);
+        std::fs::write(&path, contents).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn multi_line_fasta_length_is_base_count() {
+        let dir = tempfile::tempdir().unwrap();
+        let contents = b">chr1 test genome\nACGTacgt\nNNAC\n\nGT\n";
+        let path = write_file(&dir, "genome.fa", contents);
+        let sequence = read_fasta(&path).unwrap();
+        assert_eq!(sequence, b"ACGTACGTNNACGT");
+        assert_eq!(sequence.len(), 14);
+        assert!(sequence.len() < contents.len());
+        assert_eq!(sequence_length(&path).unwrap(), 14);
+        assert_eq!(read_sequence(&path).unwrap(), sequence);
+    }
+
+    #[test]
+    #[cfg(feature = "gzip")]
+    fn gzipped_fasta_round_trips() {
+        use flate2::write::GzEncoder;
+        use std::io::Write;
+
+        let dir = tempfile::tempdir().unwrap();
+        let contents = b">sample\nACGTN\nacgt\n";
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(contents).unwrap();
+        let path = write_file(&dir, "sample.fa.gz", &encoder.finish().unwrap());
+
+        assert!(is_gzip_file(&path).unwrap());
+        assert_eq!(read_maybe_gzipped(&path).unwrap(), contents);
+        assert_eq!(read_fasta(&path).unwrap(), b"ACGTNACGT");
+        assert_eq!(sequence_length(&path).unwrap(), 9);
+    }
+
+    #[test]
+    #[cfg(not(feature = "gzip"))]
+    fn gzip_without_feature_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_file(&dir, "sample.fa.gz", &[0x1f, 0x8b, 0x08, 0x00]);
+        assert!(is_gzip_file(&path).unwrap());
+        assert!(matches!(
+            read_maybe_gzipped(&path),
+            Err(PcaError::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "mmap")]
+    fn mapped_file_matches_chunked_reader() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_file(&dir, "genome.txt", b"ACGTTGCAACGT\n");
+        let mapped = mmap_file(&path).unwrap();
+        let length = get_file_length(&path).unwrap();
+        assert_eq!(&mapped[..], read_file_in_chunks(&path, length).unwrap());
+
+        let empty = write_file(&dir, "empty.txt", b"");
+        assert!(mmap_file(&empty).unwrap().is_empty());
+    }
+
+    #[test]
+    fn readers_report_specific_variants() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("absent.fa").to_string_lossy().into_owned();
+        assert!(matches!(
+            get_file_length(&missing),
+            Err(PcaError::FileNotFound(path)) if path == missing
+        ));
+
+        let path = write_file(&dir, "short.txt", b"ACGT");
+        assert!(matches!(
+            read_file_in_chunks(&path, 8),
+            Err(PcaError::Io { source, .. }) if source.kind() == io::ErrorKind::UnexpectedEof
+        ));
+        assert!(matches!(
+            read_file_in_chunks_with(&path, 4, 0),
+            Err(PcaError::InvalidInput(_))
+        ));
+
+        let binary = write_file(&dir, "binary.fa", b">x\nAC\xFFGT\n");
+        assert!(matches!(read_fasta(&binary), Err(PcaError::Parse(_))));
+    }
+
+    #[test]
+    fn multifasta_yields_one_record_per_header() {
+        let dir = tempfile::tempdir().unwrap();
+        let contents =
+            b">s1 first\r\nACGT\r\nAC\r\n\r\n>s2\nacgtac\n\n>s3 third sample\nTTTT\nGG\n";
+        let path = write_file(&dir, "all.fa", contents);
+        let records = read_multifasta(&path).unwrap();
+        assert_eq!(
+            records,
+            vec![
+                ("s1".to_string(), b"ACGTAC".to_vec()),
+                ("s2".to_string(), b"ACGTAC".to_vec()),
+                ("s3".to_string(), b"TTTTGG".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn pack_2bit_round_trips() {
+        for seq in [&b""[..], b"A", b"acgt", b"ACGTNacgtnRYT", b"TTTTTTTTG"] {
+            let packed = pack_2bit(seq);
+            assert_eq!(packed.bits.len(), seq.len().div_ceil(4));
+            assert_eq!(packed.unpack(), seq.to_ascii_uppercase());
+        }
+        assert_eq!(pack_2bit(b"ACNT").exceptions, vec![(2, b'N')]);
+    }
+
+    #[test]
+    fn seven_byte_chunks_read_the_whole_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let contents: Vec<u8> = (0..40u8).map(|i| b"ACGT"[(i % 4) as usize]).collect();
+        let path = write_file(&dir, "genome.txt", &contents);
+        // 40 bytes = 5 full chunks and a 5-byte partial one
+        assert_eq!(read_file_in_chunks_with(&path, 40, 7).unwrap(), contents);
+        assert_eq!(
+            read_file_in_chunks_with(&path, 0, 7).unwrap(),
+            Vec::<u8>::new()
+        );
+        assert!(matches!(
+            read_file_in_chunks_with(&path, 41, 7),
+            Err(PcaError::Io { .. })
+        ));
+        assert!(matches!(
+            read_file_in_chunks_with(&path, 40, 0),
+            Err(PcaError::InvalidInput(_))
+        ));
+    }
+
+    /// Serve `requests` HTTP/1.1 requests on a local port: `body` for
+    /// /genome.fa, 404 for anything else. Returns the base URL.
+    #[cfg(feature = "http")]
+    fn serve(body: &'static [u8], requests: usize) -> String {
+        use std::io::{BufRead, BufReader, Write};
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for stream in listener.incoming().take(requests) {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut header = String::new();
+                while reader.read_line(&mut header).unwrap() > 2 {
+                    header.clear();
+                }
+                let (status, body) = if request_line.starts_with("GET /genome.fa ") {
+                    ("200 OK", body)
+                } else {
+                    ("404 Not Found", &b""[..])
+                };
+                write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    status,
+                    body.len()
+                )
+                .unwrap();
+                stream.write_all(body).unwrap();
+            }
+        });
+        url
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn fasta_is_read_from_a_local_server() {
+        let url = serve(b">ref\nACGTacgt\nNNAC\n", 2);
+        let genome = format!("{}/genome.fa", url);
+        assert_eq!(read_fasta(&genome).unwrap(), b"ACGTACGTNNAC");
+        assert!(matches!(
+            read_fasta(&format!("{}/missing.fa", url)),
+            Err(PcaError::Io { .. })
+        ));
+    }
+
+    #[cfg(not(feature = "http"))]
+    #[test]
+    fn url_without_feature_is_an_error() {
+        assert!(matches!(
+            read_fasta("http://127.0.0.1:9/genome.fa"),
+            Err(PcaError::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn failed_write_leaves_no_partial_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("results.csv");
+        let fail_midway = |writer: &mut BufWriter<File>| -> io::Result<()> {
+            use std::io::Write;
+            writeln!(writer, "sample,PC1")?;
+            writeln!(writer, "a,0.5")?;
+            Err(io::Error::other("disk full"))
+        };
+
+        assert!(write_atomically(&path, fail_midway).is_err());
+        assert!(!path.exists());
+
+        std::fs::write(&path, "old contents\n").unwrap();
+        assert!(write_atomically(&path, fail_midway).is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "old contents\n");
+
+        // Only the final file is left behind, no temporary
+        let names: Vec<_> = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(names, ["results.csv"]);
+    }
+
+    #[test]
+    fn byte_0xff_follows_the_chosen_policy() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_file(&dir, "genome.fa", b">g\nAC\xffGT\n");
+
+        match read_sequence_with(&path, InvalidBasePolicy::Error) {
+            Err(PcaError::Parse(msg)) => {
+                assert!(
+                    msg.contains("0xFF") && msg.contains("position 3"),
+                    "{}",
+                    msg
+                )
+            }
+            other => panic!("expected a Parse error, got {:?}", other),
+        }
+        assert!(read_sequence(&path).is_err());
+        assert_eq!(
+            read_sequence_with(&path, InvalidBasePolicy::Skip).unwrap(),
+            b"ACGT"
+        );
+        assert_eq!(
+            read_sequence_with(&path, InvalidBasePolicy::Missing).unwrap(),
+            b"ACNGT"
+        );
+    }
+}
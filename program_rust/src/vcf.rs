@@ -0,0 +1,142 @@
+/*!
+ * @file vcf.rs
+ * @brief Minimal VCF reader producing an allele-dosage matrix for PCA.
+ *
+ * Author: Namir Garib
+ * Created: January 2025
+ */
+
+use crate::error::PcaError;
+use crate::utils::read_maybe_gzipped;
+
+/**
+ * @brief Read the GT field of a VCF into an n x d dosage matrix.
+ *
+ * @param path Path to a (optionally gzipped) VCF file.
+ * @return (matrix, n, d) where the matrix is row-major with one row per sample
+ *         and one column per variant record, or a PcaError.
+ *
+ * Each genotype is encoded as its count of non-reference alleles, so `0|0` is 0,
+ * `0/1` is 1 and `1/1` is 2. Any missing allele (e.g. `./.`) gives NaN.
+ */
+pub fn read_vcf(path: &str) -> Result<(Vec<f64>, usize, usize), PcaError> {
+    let raw = read_maybe_gzipped(path)?;
+    let text = String::from_utf8_lossy(&raw);
+
+    let mut n: Option<usize> = None;
+    // Dosages stored variant-major while parsing; transposed at the end
+    let mut by_variant: Vec<f64> = Vec::new();
+    let mut d = 0usize;
+
+    for (line_no, line) in text.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        if line.is_empty() || line.starts_with("##") {
+            continue;
+        }
+        let fields: Vec<&str> = line.split('\t').collect();
+        if line.starts_with('#') {
+            // #CHROM POS ID REF ALT QUAL FILTER INFO FORMAT sample1 ...
+            n = Some(fields.len().saturating_sub(9));
+            continue;
+        }
+
+        let n = n.ok_or_else(|| {
+            PcaError::Parse(format!("{}: variant record before #CHROM header", path))
+        })?;
+        if fields.len() != 9 + n {
+            return Err(PcaError::Parse(format!(
+                "{}:{}: expected {} columns, found {}",
+                path,
+                line_no + 1,
+                9 + n,
+                fields.len()
+            )));
+        }
+        let gt_index = fields[8]
+            .split(':')
+            .position(|key| key == "GT")
+            .ok_or_else(|| {
+                PcaError::Parse(format!("{}:{}: FORMAT has no GT field", path, line_no + 1))
+            })?;
+
+        for sample in &fields[9..] {
+            let gt = sample.split(':').nth(gt_index).unwrap_or(".");
+            by_variant.push(parse_dosage(gt));
+        }
+        d += 1;
+    }
+
+    let n = n.ok_or_else(|| PcaError::Parse(format!("{}: missing #CHROM header", path)))?;
+    let mut matrix = vec![0.0; n * d];
+    for col in 0..d {
+        for row in 0..n {
+            matrix[row * d + col] = by_variant[col * n + row];
+        }
+    }
+
+    Ok((matrix, n, d))
+}
+
+/**
+ * @brief Alt-allele count of a GT string such as "0|1" or "1/1"; NaN if any allele is ".".
+ */
+fn parse_dosage(gt: &str) -> f64 {
+    let mut dosage = 0.0;
+    for allele in gt.split(['/', '|']) {
+        match allele {
+            "0" => {}
+            "." | "" => return f64::NAN,
+            _ => dosage += 1.0,
+        }
+    }
+    dosage
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn three_samples_five_variants() {
+        let vcf = "##fileformat=VCFv4.2\n\
+            #CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tS1\tS2\tS3\n\
+            1\t100\t.\tA\tG\t.\tPASS\t.\tGT\t0|0\t0|1\t1|1\n\
+            1\t200\t.\tC\tT\t.\tPASS\t.\tGT:DP\t1/1:9\t0/0:8\t0/1:7\n\
+            1\t300\t.\tG\tA\t.\tPASS\t.\tDP:GT\t5:./.\t5:1|0\t5:0|0\n\
+            2\t400\t.\tT\tC\t.\tPASS\t.\tGT\t0/1\t0/1\t0/1\r\n\
+            2\t500\t.\tA\tC,T\t.\tPASS\t.\tGT\t1/2\t0/2\t.|1\n";
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("small.vcf");
+        std::fs::write(&path, vcf).unwrap();
+
+        let (matrix, n, d) = read_vcf(path.to_str().unwrap()).unwrap();
+        assert_eq!((n, d), (3, 5));
+        let expected = [
+            [0.0, 2.0, f64::NAN, 1.0, 2.0],
+            [1.0, 0.0, 1.0, 1.0, 1.0],
+            [2.0, 1.0, 0.0, 1.0, f64::NAN],
+        ];
+        for (row, values) in expected.iter().enumerate() {
+            for (col, &value) in values.iter().enumerate() {
+                let got = matrix[row * d + col];
+                assert!(
+                    got == value || (got.is_nan() && value.is_nan()),
+                    "S{} variant {}",
+                    row + 1,
+                    col
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn record_before_header_is_a_parse_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bad.vcf");
+        std::fs::write(&path, "1\t100\t.\tA\tG\t.\tPASS\t.\tGT\t0|1\n").unwrap();
+        assert!(matches!(
+            read_vcf(path.to_str().unwrap()),
+            Err(PcaError::Parse(_))
+        ));
+    }
+}
@@ -0,0 +1,197 @@
+/*!
+ * @file window.rs
+ * @brief Windowed PCA: partition SNP columns by chromosome (and optionally a
+ *        fixed number of base pairs) and run PCA independently per window.
+ *
+ * Author: Namir Garib
+ * Created: January 2025
+ */
+
+use crate::analysis::{run_pca_on_matrix_with, variable_columns, AnalysisOptions, PcaOutput};
+use crate::error::PcaError;
+use crate::utils::read_maybe_gzipped;
+use std::collections::HashMap;
+
+/**
+ * @struct Window
+ * @brief A named group of columns of the call matrix.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Window {
+    /// The chromosome, or `chrom:start-end` when windows have a fixed size.
+    pub name: String,
+    /// Column indices, ascending.
+    pub columns: Vec<usize>,
+}
+
+/**
+ * @brief Read a position map and group its lines into windows.
+ *
+ * @param path        Map file: one `chrom pos` line per column of the call
+ *                    matrix (reference position), in order. Blank lines and
+ *                    `#` comments are skipped; fields may be separated by
+ *                    whitespace or commas.
+ * @param window_size Split each chromosome into windows of this many base
+ *                    pairs ([0, size), [size, 2 size), ...); None gives one
+ *                    window per chromosome.
+ * @return Windows in order of first appearance, or a PcaError for a malformed
+ *         line or a window size of 0.
+ */
+pub fn read_window_map(path: &str, window_size: Option<u64>) -> Result<Vec<Window>, PcaError> {
+    if window_size == Some(0) {
+        return Err(PcaError::InvalidInput(
+            "Window size must be at least 1 base pair.".to_string(),
+        ));
+    }
+    let raw = read_maybe_gzipped(path)?;
+    let mut windows: Vec<Window> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+    let mut column = 0usize;
+    for (line_no, line) in String::from_utf8_lossy(&raw).lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut fields = line
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|field| !field.is_empty());
+        let (chrom, pos) = match (fields.next(), fields.next(), fields.next()) {
+            (Some(chrom), Some(pos), None) => match pos.parse::<u64>() {
+                Ok(pos) => (chrom, pos),
+                Err(_) => {
+                    return Err(PcaError::Parse(format!(
+                        "{}:{}: position '{}' is not a non-negative integer",
+                        path,
+                        line_no + 1,
+                        pos
+                    )))
+                }
+            },
+            _ => {
+                return Err(PcaError::Parse(format!(
+                    "{}:{}: expected 'chrom pos'",
+                    path,
+                    line_no + 1
+                )))
+            }
+        };
+        let name = match window_size {
+            Some(size) => {
+                let start = pos / size * size;
+                format!("{}:{}-{}", chrom, start, start + size)
+            }
+            None => chrom.to_string(),
+        };
+        match index.get(&name) {
+            Some(&i) => windows[i].columns.push(column),
+            None => {
+                index.insert(name.clone(), windows.len());
+                windows.push(Window {
+                    name,
+                    columns: vec![column],
+                });
+            }
+        }
+        column += 1;
+    }
+    Ok(windows)
+}
+
+/**
+ * @brief Run PCA separately on the columns of each window.
+ *
+ * @param data    n x d call or dosage matrix, row-major; NaN marks a missing call.
+ * @param n       Number of samples.
+ * @param d       Number of columns; every window column must be below it.
+ * @param windows Column groups, e.g. from read_window_map.
+ * @param options Passed to run_pca_on_matrix_with for every window.
+ * @return One (window name, PcaOutput) per window with at least one variable
+ *         column, in window order; `positions` are columns of `data`. Windows
+ *         whose columns are all invariant are left out.
+ *
+ * Each window is centered and decomposed on its own, so scores of different
+ * windows are not on a common axis; compare their patterns, not their values.
+ */
+pub fn run_windowed_pca(
+    data: &[f64],
+    n: usize,
+    d: usize,
+    windows: &[Window],
+    options: &AnalysisOptions,
+) -> Result<Vec<(String, PcaOutput)>, PcaError> {
+    let mut outputs = Vec::new();
+    for window in windows {
+        if let Some(&col) = window.columns.iter().find(|&&col| col >= d) {
+            return Err(PcaError::InvalidInput(format!(
+                "Window {} refers to column {}, but the matrix has {} columns.",
+                window.name, col, d
+            )));
+        }
+        let width = window.columns.len();
+        let mut sub = Vec::with_capacity(n * width);
+        for row in data.chunks_exact(d).take(n) {
+            sub.extend(window.columns.iter().map(|&col| row[col]));
+        }
+        if variable_columns(&sub, n, width).is_empty() {
+            continue;
+        }
+        let mut output = run_pca_on_matrix_with(&sub, n, width, options)?;
+        for position in output.positions.iter_mut() {
+            *position = window.columns[*position];
+        }
+        outputs.push((window.name.clone(), output));
+    }
+    Ok(outputs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::run_pca_on_matrix;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn two_windows_give_two_independent_results() {
+        let dir = TempDir::new().unwrap();
+        let map = dir.path().join("map.txt");
+        fs::write(&map, "# chrom pos\nchr1 100\nchr1 200\nchr2,50\nchr2 75\n").unwrap();
+        let windows = read_window_map(map.to_str().unwrap(), None).unwrap();
+        assert_eq!(windows.len(), 2);
+        assert_eq!(windows[0].columns, vec![0, 1]);
+        assert_eq!(windows[1].columns, vec![2, 3]);
+
+        // chr1 splits samples {0, 1} from {2, 3}; chr2 splits {0, 2} from {1, 3}
+        let data = vec![
+            0.0, 0.0, 0.0, 1.0, //
+            0.0, 0.0, 2.0, 2.0, //
+            2.0, 1.0, 0.0, 0.0, //
+            2.0, 2.0, 2.0, 2.0,
+        ];
+        let options = AnalysisOptions {
+            num_components: 1,
+            ..AnalysisOptions::default()
+        };
+        let outputs = run_windowed_pca(&data, 4, 4, &windows, &options).unwrap();
+        assert_eq!(outputs.len(), 2);
+        assert_eq!(outputs[0].0, "chr1");
+        assert_eq!(outputs[1].0, "chr2");
+        assert_eq!(outputs[0].1.positions, vec![0, 1]);
+        assert_eq!(outputs[1].1.positions, vec![2, 3]);
+
+        // Each window equals a PCA of its columns alone
+        for (w, (_, output)) in outputs.iter().enumerate() {
+            let sub: Vec<f64> = data
+                .chunks(4)
+                .flat_map(|row| row[2 * w..2 * w + 2].to_vec())
+                .collect();
+            let alone = run_pca_on_matrix(&sub, 4, 2, 1).unwrap();
+            assert_eq!(output.result.eigenvalues, alone.result.eigenvalues);
+            assert_eq!(output.scores, alone.scores);
+        }
+        let side = |scores: &[f64]| -> Vec<bool> { scores.iter().map(|&s| s > 0.0).collect() };
+        let (chr1, chr2) = (side(&outputs[0].1.scores), side(&outputs[1].1.scores));
+        assert!(chr1[0] == chr1[1] && chr1[0] != chr1[2]);
+        assert!(chr2[0] == chr2[2] && chr2[0] != chr2[1]);
+    }
+}
@@ -0,0 +1,44 @@
+//! The pca_population_strat binary, run as a subprocess.
+
+mod common;
+
+use common::Fixture;
+use std::process::{Command, Output};
+
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_pca_population_strat"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn validate_reports_bad_input_and_writes_nothing() {
+    let fx = Fixture::new();
+    let (reference, files) = fx.genomes(2, 40);
+    let short = fx.write("short.txt", "ACGT");
+    let output = fx.output();
+    let output_arg = output.to_string_lossy();
+    let result = run(&[
+        "--validate",
+        "-r",
+        &reference,
+        "-i",
+        &files[0],
+        &files[1],
+        &short,
+        "-o",
+        &output_arg,
+    ]);
+
+    assert!(!result.status.success());
+    let stdout = String::from_utf8_lossy(&result.stdout);
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    let short_row = stdout
+        .lines()
+        .find(|line| line.starts_with(short.as_str()))
+        .expect("summary row for the short file");
+    assert!(!short_row.ends_with("\tok"), "{}", short_row);
+    assert!(stderr.contains("1 of 4 input(s) have errors"), "{}", stderr);
+    assert!(!output.exists());
+}
@@ -0,0 +1,93 @@
+//! Fixtures shared by the integration tests.
+#![allow(dead_code)]
+
+use std::path::{Path, PathBuf};
+
+/// A temporary directory holding a test's inputs and outputs; removed on drop.
+pub struct Fixture {
+    dir: tempfile::TempDir,
+}
+
+impl Fixture {
+    pub fn new() -> Self {
+        Fixture {
+            dir: tempfile::tempdir().expect("create temp dir"),
+        }
+    }
+
+    /// Path of `name` inside the fixture directory.
+    pub fn path(&self, name: &str) -> String {
+        self.dir.path().join(name).to_string_lossy().into_owned()
+    }
+
+    /// Write `contents` to `name` (creating parent directories) and return its path.
+    pub fn write(&self, name: &str, contents: impl AsRef<[u8]>) -> String {
+        let path = self.dir.path().join(name);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).expect("create fixture subdirectory");
+        }
+        std::fs::write(&path, contents).expect("write fixture file");
+        path.to_string_lossy().into_owned()
+    }
+
+    /// Directory the pipeline writes into.
+    pub fn output(&self) -> PathBuf {
+        self.dir.path().join("out")
+    }
+
+    /// Write structured_genomes(n, len) as ref.txt and ind0.txt.. and return
+    /// (reference path, individual paths).
+    pub fn genomes(&self, n: usize, len: usize) -> (String, Vec<String>) {
+        let (reference, individuals) = structured_genomes(n, len);
+        let ref_path = self.write("ref.txt", &reference);
+        let paths = individuals
+            .iter()
+            .enumerate()
+            .map(|(i, genome)| self.write(&format!("ind{}.txt", i), genome))
+            .collect();
+        (ref_path, paths)
+    }
+}
+
+fn substitute(base: u8) -> u8 {
+    match base {
+        b'A' => b'C',
+        b'C' => b'G',
+        b'G' => b'T',
+        _ => b'A',
+    }
+}
+
+/// A reference of `len` bases and `n` individuals in two groups (even and odd
+/// index). Each carries its group's variants plus a few private ones, so the
+/// first component separates the groups.
+pub fn structured_genomes(n: usize, len: usize) -> (Vec<u8>, Vec<Vec<u8>>) {
+    let reference: Vec<u8> = (0..len).map(|p| b"ACGT"[(p * 7 + 3) % 4]).collect();
+    let individuals = (0..n)
+        .map(|i| {
+            reference
+                .iter()
+                .enumerate()
+                .map(|(p, &base)| {
+                    let group_variant = p % 5 == i % 2;
+                    let private = (p * 31 + i * 17) % 23 == 0;
+                    if group_variant ^ private {
+                        substitute(base)
+                    } else {
+                        base
+                    }
+                })
+                .collect()
+        })
+        .collect();
+    (reference, individuals)
+}
+
+/// Rows of a comma-separated file, split into fields.
+pub fn read_csv(path: impl AsRef<Path>) -> Vec<Vec<String>> {
+    std::fs::read_to_string(path)
+        .expect("read csv")
+        .lines()
+        .map(|line| line.split(',').map(str::to_string).collect())
+        .collect()
+}
@@ -0,0 +1,154 @@
+//! The in-memory API: no files are read or written.
+
+mod common;
+
+use common::structured_genomes;
+use pca_population_strat::analysis::{
+    run_pca, run_pca_on_matrix, run_pca_on_matrix_with, run_pca_scaled, run_pca_streaming,
+    run_pca_streaming_with, run_pca_with_config, MatrixKind, MissingPolicy, PcaConfig, Scaling,
+};
+use pca_population_strat::error::PcaError;
+use pca_population_strat::variant_calling::call_variants;
+
+#[test]
+fn run_pca_returns_scores_in_memory() {
+    let (reference, individuals) = structured_genomes(6, 80);
+    let output = run_pca(&reference, &individuals, 2).unwrap();
+    assert_eq!(output.num_samples, 6);
+    assert_eq!(output.result.num_components, 2);
+    assert_eq!(output.scores.len(), 6 * 2);
+    assert_eq!(output.means.len(), output.positions.len());
+
+    // The two groups (even and odd index) fall on opposite sides of PC1
+    let pc1: Vec<f64> = output.scores.chunks(2).map(|row| row[0]).collect();
+    let even = pc1[0].signum();
+    for (i, score) in pc1.iter().enumerate() {
+        let expected = if i % 2 == 0 { even } else { -even };
+        assert_eq!(score.signum(), expected, "sample {}", i);
+    }
+}
+
+fn assert_close(a: &[f64], b: &[f64], tol: f64) {
+    assert_eq!(a.len(), b.len());
+    for (i, (x, y)) in a.iter().zip(b).enumerate() {
+        assert!((x - y).abs() <= tol, "entry {}: {} vs {}", i, x, y);
+    }
+}
+
+#[test]
+fn streaming_matches_in_memory() {
+    let (reference, individuals) = structured_genomes(7, 60);
+    let in_memory = run_pca(&reference, &individuals, 3).unwrap();
+    let streamed = run_pca_streaming(
+        &reference,
+        individuals.len(),
+        |i| Ok(individuals[i].clone()),
+        3,
+        Scaling::Center,
+        MatrixKind::Covariance,
+        0.0,
+    )
+    .unwrap();
+    assert_eq!(streamed.positions, in_memory.positions);
+    assert_close(&streamed.means, &in_memory.means, 1e-12);
+    assert_close(
+        &streamed.result.eigenvalues,
+        &in_memory.result.eigenvalues,
+        1e-9,
+    );
+    assert_close(&streamed.scores, &in_memory.scores, 1e-8);
+}
+
+#[test]
+fn raw_scaling_projects_uncentered_calls() {
+    let (reference, individuals) = structured_genomes(6, 60);
+    let centered = run_pca_scaled(&reference, &individuals, 2, Scaling::Center).unwrap();
+    let raw = run_pca_scaled(&reference, &individuals, 2, Scaling::Raw).unwrap();
+    assert_eq!(raw.positions, centered.positions);
+    assert!(raw.scores.iter().all(|x| x.is_finite()));
+
+    // The raw second-moment matrix also carries the column means, so its top
+    // eigenvalue is larger and the scores move off the origin
+    assert!(raw.result.eigenvalues[0] > centered.result.eigenvalues[0]);
+    let column_sum = |scores: &[f64]| scores.chunks(2).map(|row| row[0]).sum::<f64>();
+    assert!(column_sum(&centered.scores).abs() < 1e-9);
+    assert!(column_sum(&raw.scores).abs() > 1e-3);
+
+    // Each raw score is the uncentered call vector times the eigenvector
+    let d = raw.positions.len();
+    for (s, genome) in individuals.iter().enumerate() {
+        let calls = call_variants(&reference, genome);
+        for comp in 0..2 {
+            let expected: f64 = raw
+                .positions
+                .iter()
+                .enumerate()
+                .map(|(col, &pos)| calls[pos] * raw.result.eigenvectors[comp * d + col])
+                .sum();
+            assert!((raw.scores[s * 2 + comp] - expected).abs() < 1e-9);
+        }
+    }
+}
+
+#[test]
+fn missing_policies_agree_across_entry_points() {
+    let (reference, mut individuals) = structured_genomes(6, 60);
+    // Position 0 is a group variant; sample 2 has no call there
+    individuals[2][0] = b'N';
+    let calls: Vec<f64> = individuals
+        .iter()
+        .flat_map(|genome| call_variants(&reference, genome))
+        .collect();
+
+    let run_all = |missing: MissingPolicy| {
+        let config = PcaConfig::builder().components(2).missing(missing).build();
+        [
+            run_pca_with_config(&reference, &individuals, &config),
+            run_pca_streaming_with(&reference, 6, |i| Ok(individuals[i].clone()), &config),
+            run_pca_on_matrix_with(&calls, 6, 60, &config),
+        ]
+    };
+
+    for result in run_all(MissingPolicy::Error) {
+        assert!(
+            matches!(result, Err(PcaError::InvalidInput(ref msg)) if msg.contains("position 0"))
+        );
+    }
+
+    let imputed = run_all(MissingPolicy::MeanImpute).map(Result::unwrap);
+    let dropped = run_all(MissingPolicy::DropSnp).map(Result::unwrap);
+    for output in &imputed {
+        assert_eq!(output.positions[0], 0);
+        // Sample 2's missing call is the mean of the five called samples
+        let called: Vec<f64> = (0..6).filter(|&s| s != 2).map(|s| calls[s * 60]).collect();
+        assert!((output.means[0] - called.iter().sum::<f64>() / 5.0).abs() < 1e-12);
+        assert_close(&output.scores, &imputed[0].scores, 1e-8);
+    }
+    for output in &dropped {
+        assert!(!output.positions.contains(&0));
+        assert_eq!(output.positions, dropped[0].positions);
+        assert_close(&output.scores, &dropped[0].scores, 1e-8);
+    }
+}
+
+#[test]
+fn hand_built_matrix_needs_no_reference() {
+    // Columns 0 and 2 split the samples 2/2; column 1 is invariant
+    let data = vec![
+        0.0, 1.0, 0.0, //
+        0.0, 1.0, 0.0, //
+        2.0, 1.0, 2.0, //
+        2.0, 1.0, 2.0,
+    ];
+    let output = run_pca_on_matrix(&data, 4, 3, 2).unwrap();
+    assert_eq!(output.num_samples, 4);
+    assert_eq!(output.positions, vec![0, 2]);
+    assert_eq!(output.means, vec![1.0, 1.0]);
+
+    // Centered rows are (+-1, +-1): covariance 4/3 everywhere, eigenvalues 8/3 and 0
+    assert_close(&output.result.eigenvalues, &[8.0 / 3.0, 0.0], 1e-9);
+    let pc1: Vec<f64> = output.scores.chunks(2).map(|row| row[0].abs()).collect();
+    assert_close(&pc1, &[2f64.sqrt(); 4], 1e-9);
+    assert_eq!(output.scores[0].signum(), output.scores[2].signum());
+    assert_eq!(output.scores[0].signum(), -output.scores[4].signum());
+}
@@ -0,0 +1,613 @@
+//! End-to-end runs of perform_full_analysis on small genomes in a temp dir.
+
+mod common;
+
+use common::{read_csv, structured_genomes, Fixture};
+use pca_population_strat::analysis::{perform_full_analysis, PcaConfig, SoftMask};
+use pca_population_strat::checkpoint::Checkpoint;
+use pca_population_strat::error::PcaError;
+use pca_population_strat::utils::{
+    collect_genome_files, get_file_length, sample_name, sequence_length, FloatFormat,
+};
+
+#[test]
+fn results_have_one_column_per_component() {
+    let fx = Fixture::new();
+    let (reference, files) = fx.genomes(6, 60);
+    let options = PcaConfig::builder().components(2).build();
+    perform_full_analysis(&reference, &files, &fx.output(), &options).unwrap();
+
+    let rows = read_csv(fx.output().join("results.csv"));
+    assert_eq!(rows[0], ["sample", "PC1", "PC2"]);
+    assert_eq!(rows.len(), 1 + files.len());
+    assert!(rows.iter().all(|row| row.len() == 3));
+}
+
+#[test]
+fn directory_of_fasta_files_is_read_in_name_order() {
+    let fx = Fixture::new();
+    let (reference, individuals) = structured_genomes(3, 40);
+    let reference = fx.write("ref.fa", [b">ref\n".as_slice(), &reference].concat());
+    for (name, genome) in ["c.fa", "a.fasta", "b.fa"].iter().zip(&individuals) {
+        let record = [b">".as_slice(), name.as_bytes(), b"\n", genome, b"\n"].concat();
+        fx.write(&format!("genomes/{}", name), record);
+    }
+    fx.write("genomes/notes.txt", "not a genome");
+
+    let files = collect_genome_files(&fx.path("genomes")).unwrap();
+    let names: Vec<String> = files.iter().map(|path| sample_name(path)).collect();
+    assert_eq!(names, ["a", "b", "c"]);
+
+    perform_full_analysis(&reference, &files, &fx.output(), &PcaConfig::default()).unwrap();
+    let samples = std::fs::read_to_string(fx.output().join("samples.txt")).unwrap();
+    let rows: Vec<Vec<&str>> = samples
+        .lines()
+        .map(|line| line.split('\t').collect())
+        .collect();
+    assert_eq!(rows.len(), 3);
+    for (row, (name, file)) in rows.iter().zip(names.iter().zip(&files)) {
+        assert_eq!(row[1], name);
+        assert_eq!(row[2], file);
+    }
+    let labels: Vec<String> = read_csv(fx.output().join("results.csv"))[1..]
+        .iter()
+        .map(|row| row[0].clone())
+        .collect();
+    assert_eq!(labels, names);
+}
+
+#[test]
+fn result_rows_are_labelled_by_file_stem() {
+    let fx = Fixture::new();
+    let (reference, individuals) = structured_genomes(3, 40);
+    let reference = fx.write("ref.txt", &reference);
+    let files: Vec<String> = ["NA001.txt", "HG002.seq", "sample_3.txt"]
+        .iter()
+        .zip(&individuals)
+        .map(|(name, genome)| fx.write(name, genome))
+        .collect();
+
+    perform_full_analysis(&reference, &files, &fx.output(), &PcaConfig::default()).unwrap();
+    let rows = read_csv(fx.output().join("results.csv"));
+    assert_eq!(rows[0][0], "sample");
+    let labels: Vec<&str> = rows[1..].iter().map(|row| row[0].as_str()).collect();
+    assert_eq!(labels, ["NA001", "HG002", "sample_3"]);
+}
+
+#[test]
+fn failures_map_to_error_variants() {
+    let fx = Fixture::new();
+    let (reference, files) = fx.genomes(3, 40);
+    let options = PcaConfig::default();
+
+    let missing = vec![files[0].clone(), fx.path("absent.txt")];
+    assert!(matches!(
+        perform_full_analysis(&reference, &missing, &fx.output(), &options),
+        Err(PcaError::FileNotFound(path)) if path.ends_with("absent.txt")
+    ));
+
+    let short = fx.write("short.txt", "ACGT");
+    let mismatched = vec![files[0].clone(), files[1].clone(), short];
+    assert!(matches!(
+        perform_full_analysis(&reference, &mismatched, &fx.output(), &options),
+        Err(PcaError::LengthMismatches { expected: 40, .. })
+    ));
+
+    let empty = fx.write("empty.txt", "");
+    assert!(matches!(
+        perform_full_analysis(&empty, &files, &fx.output(), &options),
+        Err(PcaError::EmptyFile(_))
+    ));
+
+    // The output "directory" is a file, so it cannot be created
+    let blocked = std::path::PathBuf::from(fx.write("blocked", "file"));
+    assert!(matches!(
+        perform_full_analysis(&reference, &files, &blocked, &options),
+        Err(PcaError::Io { .. })
+    ));
+}
+
+#[test]
+fn loadings_have_one_row_per_retained_position() {
+    let fx = Fixture::new();
+    let (reference, individuals) = structured_genomes(6, 60);
+    let reference_path = fx.write("ref.txt", &reference);
+    let files: Vec<String> = individuals
+        .iter()
+        .enumerate()
+        .map(|(i, genome)| fx.write(&format!("ind{}.txt", i), genome))
+        .collect();
+    let options = PcaConfig::builder()
+        .components(3)
+        .write_loadings(true)
+        .build();
+    perform_full_analysis(&reference_path, &files, &fx.output(), &options).unwrap();
+
+    // Positions where the samples disagree on carrying a variant survive filtering
+    let retained: Vec<String> = (0..reference.len())
+        .filter(|&p| {
+            let carriers = individuals.iter().filter(|g| g[p] != reference[p]).count();
+            carriers > 0 && carriers < individuals.len()
+        })
+        .map(|p| p.to_string())
+        .collect();
+    let rows = read_csv(fx.output().join("loadings.csv"));
+    assert_eq!(rows[0], ["position", "PC1", "PC2", "PC3"]);
+    assert_eq!(rows.len(), 1 + retained.len());
+    assert!(rows[1..].iter().all(|row| row.len() == 1 + 3));
+    let positions: Vec<String> = rows[1..].iter().map(|row| row[0].clone()).collect();
+    assert_eq!(positions, retained);
+}
+
+#[test]
+fn outputs_land_in_a_created_output_dir() {
+    let fx = Fixture::new();
+    let (reference, files) = fx.genomes(4, 40);
+    let output = fx.output().join("nested/run1");
+    perform_full_analysis(&reference, &files, &output, &PcaConfig::default()).unwrap();
+
+    for name in [
+        "results.csv",
+        "eigenvalues.csv",
+        "scree.csv",
+        "samples.txt",
+        "qc.txt",
+    ] {
+        assert!(output.join(name).is_file(), "{} missing", name);
+    }
+}
+
+#[test]
+fn single_individual_is_rejected_before_writing() {
+    let fx = Fixture::new();
+    let (reference, files) = fx.genomes(1, 40);
+    let err =
+        perform_full_analysis(&reference, &files, &fx.output(), &PcaConfig::default()).unwrap_err();
+    assert!(matches!(err, PcaError::InvalidInput(ref msg) if msg.contains("found 1")));
+    assert!(!fx.output().join("results.csv").exists());
+}
+
+#[test]
+fn verbose_output_does_not_change_results() {
+    let fx = Fixture::new();
+    let (reference, files) = fx.genomes(5, 60);
+    let quiet = fx.output().join("quiet");
+    let verbose = fx.output().join("verbose");
+    let options = PcaConfig::builder().components(3);
+    perform_full_analysis(&reference, &files, &quiet, &options.clone().build()).unwrap();
+    perform_full_analysis(&reference, &files, &verbose, &options.verbose(true).build()).unwrap();
+
+    for name in ["results.csv", "eigenvalues.csv", "scree.csv"] {
+        let a = std::fs::read(quiet.join(name)).unwrap();
+        let b = std::fs::read(verbose.join(name)).unwrap();
+        assert_eq!(a, b, "{} differs", name);
+    }
+}
+
+#[test]
+fn length_preflight_reports_only_the_bad_file() {
+    let fx = Fixture::new();
+    let (reference, mut files) = fx.genomes(2, 40);
+    let bad = fx.write("bad.txt", "ACGTACGT");
+    files.insert(1, bad.clone());
+
+    let err =
+        perform_full_analysis(&reference, &files, &fx.output(), &PcaConfig::default()).unwrap_err();
+    match err {
+        PcaError::LengthMismatches { expected, files } => {
+            assert_eq!(expected, 40);
+            assert_eq!(files, vec![(bad, 8)]);
+        }
+        other => panic!("expected LengthMismatches, got {:?}", other),
+    }
+    assert!(!fx.output().join("results.csv").exists());
+}
+
+#[test]
+fn empty_individual_is_named_in_an_empty_file_error() {
+    let fx = Fixture::new();
+    let (reference, mut files) = fx.genomes(2, 40);
+    let empty = fx.write("empty.txt", "");
+    files.push(empty.clone());
+    assert!(matches!(
+        perform_full_analysis(&reference, &files, &fx.output(), &PcaConfig::default()),
+        Err(PcaError::EmptyFile(path)) if path == empty
+    ));
+}
+
+#[test]
+fn file_length_separates_empty_from_unreadable() {
+    let fx = Fixture::new();
+    assert_eq!(get_file_length(&fx.write("empty.txt", "")).unwrap(), 0);
+    assert!(matches!(
+        get_file_length(&fx.path("absent.txt")),
+        Err(PcaError::FileNotFound(_))
+    ));
+}
+
+#[test]
+fn trailing_newline_differences_do_not_change_the_length() {
+    let fx = Fixture::new();
+    let (reference, individuals) = structured_genomes(3, 40);
+    let reference_path = fx.write("ref.txt", [reference.as_slice(), b"\n"].concat());
+    let endings: [&[u8]; 3] = [b"", b"\n", b"\r\n"];
+    let files: Vec<String> = individuals
+        .iter()
+        .zip(endings)
+        .enumerate()
+        .map(|(i, (genome, ending))| fx.write(&format!("ind{}.txt", i), [genome, ending].concat()))
+        .collect();
+    assert_eq!(sequence_length(&reference_path).unwrap(), 40);
+    assert_eq!(sequence_length(&files[2]).unwrap(), 40);
+
+    perform_full_analysis(&reference_path, &files, &fx.output(), &PcaConfig::default()).unwrap();
+    assert_eq!(read_csv(fx.output().join("results.csv")).len(), 1 + 3);
+}
+
+#[test]
+fn one_byte_longer_file_runs_within_the_slack() {
+    let fx = Fixture::new();
+    let (reference, individuals) = structured_genomes(4, 40);
+    let reference = fx.write("ref.txt", &reference);
+    let mut files: Vec<String> = individuals
+        .iter()
+        .enumerate()
+        .map(|(i, genome)| fx.write(&format!("ind{}.txt", i), genome))
+        .collect();
+    perform_full_analysis(&reference, &files, &fx.output(), &PcaConfig::default()).unwrap();
+    let expected = read_csv(fx.output().join("results.csv"));
+
+    // A trailing newline makes the file one byte longer but not the sequence
+    files[0] = fx.write("ind0.txt", [individuals[0].as_slice(), b"\n"].concat());
+    assert_eq!(get_file_length(&files[0]).unwrap(), 41);
+    perform_full_analysis(&reference, &files, &fx.output(), &PcaConfig::default()).unwrap();
+    assert_eq!(
+        read_csv(fx.output().join("results.csv"))[1..],
+        expected[1..]
+    );
+
+    // A stray base is one too many without slack, and trimmed with it
+    files[1] = fx.write("ind1.txt", [individuals[1].as_slice(), b"A"].concat());
+    let err = perform_full_analysis(&reference, &files, &fx.output(), &PcaConfig::default());
+    assert!(
+        matches!(
+            &err,
+            Err(PcaError::LengthMismatches { expected: 40, files })
+                if files.len() == 1 && files[0].1 == 41
+        ),
+        "{:?}",
+        err
+    );
+    let options = PcaConfig::builder().length_slack(1).build();
+    perform_full_analysis(&reference, &files, &fx.output(), &options).unwrap();
+    assert_eq!(
+        read_csv(fx.output().join("results.csv"))[1..],
+        expected[1..]
+    );
+}
+
+#[test]
+fn centroids_are_mean_scores_per_population() {
+    let fx = Fixture::new();
+    let (reference, files) = fx.genomes(6, 60);
+    let labels = fx.write(
+        "labels.txt",
+        "ind0 east\nind1 west\nind2\teast\nind3,west\n",
+    );
+    let options = PcaConfig::builder()
+        .components(2)
+        .labels_file(Some(labels))
+        .build();
+    perform_full_analysis(&reference, &files, &fx.output(), &options).unwrap();
+
+    let results = read_csv(fx.output().join("results.csv"));
+    assert_eq!(results[0], ["sample", "PC1", "PC2", "population"]);
+    let centroids = read_csv(fx.output().join("centroids.csv"));
+    assert_eq!(centroids[0], ["population", "count", "PC1", "PC2"]);
+    assert_eq!(centroids.len(), 1 + 2);
+    for centroid in &centroids[1..] {
+        let members: Vec<&Vec<String>> = results[1..]
+            .iter()
+            .filter(|row| row[3] == centroid[0])
+            .collect();
+        assert_eq!(centroid[1], members.len().to_string());
+        for comp in 1..=2 {
+            let mean = members
+                .iter()
+                .map(|row| row[comp].parse::<f64>().unwrap())
+                .sum::<f64>()
+                / members.len() as f64;
+            let written: f64 = centroid[comp + 1].parse().unwrap();
+            assert!((written - mean).abs() < 2e-6, "{} PC{}", centroid[0], comp);
+        }
+    }
+}
+
+#[test]
+fn soft_masked_region_is_normalized_or_excluded() {
+    let fx = Fixture::new();
+    let (reference, individuals) = structured_genomes(4, 40);
+    // Positions 10..20 are soft-masked (lowercase) in the reference only
+    let mut masked = reference.clone();
+    masked[10..20].make_ascii_lowercase();
+    let reference_path = fx.write("ref.txt", &masked);
+    let files: Vec<String> = individuals
+        .iter()
+        .enumerate()
+        .map(|(i, genome)| fx.write(&format!("ind{}.txt", i), genome))
+        .collect();
+
+    let positions = |mode: SoftMask, out: &str| -> Vec<usize> {
+        let options = PcaConfig::builder()
+            .soft_mask(mode)
+            .write_loadings(true)
+            .build();
+        let output = fx.output().join(out);
+        perform_full_analysis(&reference_path, &files, &output, &options).unwrap();
+        read_csv(output.join("loadings.csv"))[1..]
+            .iter()
+            .map(|row| row[0].parse().unwrap())
+            .collect()
+    };
+    let normalized = positions(SoftMask::Normalize, "normalize");
+    let excluded = positions(SoftMask::Exclude, "exclude");
+
+    // Normalizing treats a lowercase base like its uppercase one
+    let unmasked_path = fx.write("ref_upper.txt", &reference);
+    let output = fx.output().join("upper");
+    let options = PcaConfig::builder().write_loadings(true).build();
+    perform_full_analysis(&unmasked_path, &files, &output, &options).unwrap();
+    let upper: Vec<usize> = read_csv(output.join("loadings.csv"))[1..]
+        .iter()
+        .map(|row| row[0].parse().unwrap())
+        .collect();
+    assert_eq!(normalized, upper);
+
+    assert!(normalized.iter().any(|pos| (10..20).contains(pos)));
+    assert!(excluded.iter().all(|pos| !(10..20).contains(pos)));
+    let outside: Vec<usize> = normalized
+        .into_iter()
+        .filter(|pos| !(10..20).contains(pos))
+        .collect();
+    assert_eq!(excluded, outside);
+}
+
+#[test]
+fn excluded_sample_leaves_two_rows() {
+    let fx = Fixture::new();
+    let (reference, files) = fx.genomes(3, 40);
+    let exclude = fx.write("exclude.txt", "ind1\n");
+    let options = PcaConfig::builder().exclude_file(Some(exclude)).build();
+    perform_full_analysis(&reference, &files, &fx.output(), &options).unwrap();
+
+    let labels: Vec<String> = read_csv(fx.output().join("results.csv"))[1..]
+        .iter()
+        .map(|row| row[0].clone())
+        .collect();
+    assert_eq!(labels, ["ind0", "ind2"]);
+    let samples = std::fs::read_to_string(fx.output().join("samples.txt")).unwrap();
+    assert_eq!(samples.lines().count(), 2);
+
+    // The include list selecting the same two gives the same output
+    let include = fx.write("include.txt", "ind0\nind2\n");
+    let included = fx.output().join("included");
+    let options = PcaConfig::builder().include_file(Some(include)).build();
+    perform_full_analysis(&reference, &files, &included, &options).unwrap();
+    assert_eq!(
+        std::fs::read(fx.output().join("results.csv")).unwrap(),
+        std::fs::read(included.join("results.csv")).unwrap()
+    );
+}
+
+#[test]
+fn normalized_scores_leave_eigenvalues_alone() {
+    let fx = Fixture::new();
+    let (reference, files) = fx.genomes(5, 60);
+    let plain = fx.output().join("plain");
+    let normalized = fx.output().join("normalized");
+    let options = PcaConfig::builder().components(3);
+    perform_full_analysis(&reference, &files, &plain, &options.clone().build()).unwrap();
+    let options = options.normalize_scores(true).build();
+    perform_full_analysis(&reference, &files, &normalized, &options).unwrap();
+
+    assert_eq!(
+        std::fs::read(plain.join("eigenvalues.csv")).unwrap(),
+        std::fs::read(normalized.join("eigenvalues.csv")).unwrap()
+    );
+    let rows = read_csv(normalized.join("results.csv"));
+    for comp in 1..=3 {
+        let max_abs = rows[1..]
+            .iter()
+            .map(|row| row[comp].parse::<f64>().unwrap().abs())
+            .fold(0.0, f64::max);
+        assert_eq!(max_abs, 1.0, "PC{}", comp);
+    }
+}
+
+#[test]
+fn identical_inputs_are_reported_as_duplicates() {
+    let fx = Fixture::new();
+    let (reference, individuals) = structured_genomes(3, 40);
+    let reference = fx.write("ref.txt", &reference);
+    let mut files: Vec<String> = individuals
+        .iter()
+        .enumerate()
+        .map(|(i, genome)| fx.write(&format!("ind{}.txt", i), genome))
+        .collect();
+    files.push(fx.write("copy.txt", &individuals[1]));
+    let options = PcaConfig::builder().duplicate_threshold(Some(0.01)).build();
+    perform_full_analysis(&reference, &files, &fx.output(), &options).unwrap();
+
+    let report = std::fs::read_to_string(fx.output().join("duplicates.txt")).unwrap();
+    let lines: Vec<&str> = report.lines().collect();
+    assert_eq!(lines.len(), 2, "{}", report);
+    assert_eq!(lines[0], "sample1\tsample2\tdistance");
+    let pair: Vec<&str> = lines[1].split('\t').collect();
+    assert_eq!(&pair[..2], ["ind1", "copy"]);
+    assert_eq!(pair[2].parse::<f64>().unwrap(), 0.0);
+}
+
+#[test]
+fn csv_numbers_follow_the_requested_precision() {
+    let fx = Fixture::new();
+    let (reference, files) = fx.genomes(5, 60);
+    let decimals = |field: &str| field.split('.').nth(1).map(str::len);
+
+    let fixed = FloatFormat {
+        precision: 3,
+        scientific: false,
+    };
+    let options = PcaConfig::builder()
+        .components(2)
+        .float_format(fixed)
+        .build();
+    perform_full_analysis(&reference, &files, &fx.output(), &options).unwrap();
+    for name in ["results.csv", "eigenvalues.csv"] {
+        let rows = read_csv(fx.output().join(name));
+        for field in rows[1..].iter().flat_map(|row| &row[1..]) {
+            assert_eq!(decimals(field), Some(3), "{} in {}", field, name);
+        }
+    }
+
+    let scientific = FloatFormat {
+        precision: 2,
+        scientific: true,
+    };
+    let options = PcaConfig::builder()
+        .components(2)
+        .float_format(scientific)
+        .build();
+    perform_full_analysis(&reference, &files, &fx.output(), &options).unwrap();
+    for row in &read_csv(fx.output().join("eigenvalues.csv"))[1..] {
+        let (mantissa, exponent) = row[1].split_once('e').unwrap();
+        assert_eq!(decimals(mantissa), Some(2), "{}", row[1]);
+        assert!(exponent.parse::<i32>().is_ok(), "{}", row[1]);
+    }
+}
+
+#[test]
+fn haplotype_pairs_give_diploid_dosages() {
+    let fx = Fixture::new();
+    let reference = fx.write("ref.txt", "AAAAAA");
+    // Dosages: A = 0 1 2 1 0 0, B = 0 0 0 0 0 2
+    let files = vec![
+        fx.write("B.hap2.txt", "AAAAAC"),
+        fx.write("A.hap1.txt", "AACCAA"),
+        fx.write("A.hap2.txt", "ACCAAA"),
+        fx.write("B.hap1.txt", "AAAAAC"),
+    ];
+    let options = PcaConfig::builder()
+        .components(1)
+        .haplotype_pairs(true)
+        .write_frequencies(true)
+        .write_sfs(true)
+        .build();
+    perform_full_analysis(&reference, &files, &fx.output(), &options).unwrap();
+
+    let results = read_csv(fx.output().join("results.csv"));
+    let samples: Vec<&str> = results[1..].iter().map(|row| row[0].as_str()).collect();
+    assert_eq!(samples, ["B", "A"]);
+
+    // Alternate allele frequency is the mean dosage over 2 samples x 2 copies
+    let frequencies: Vec<f64> = read_csv(fx.output().join("maf.csv"))[1..]
+        .iter()
+        .map(|row| row[1].parse().unwrap())
+        .collect();
+    assert_eq!(frequencies, [0.0, 0.25, 0.5, 0.25, 0.0, 0.5]);
+    // Column dosage sums 0 1 2 1 0 2 over alt counts 0..=4
+    let sites: Vec<String> = read_csv(fx.output().join("sfs.csv"))[1..]
+        .iter()
+        .map(|row| row[1].clone())
+        .collect();
+    assert_eq!(sites, ["2", "2", "2", "0", "0"]);
+}
+
+#[test]
+fn grm_is_symmetric_with_a_non_negative_diagonal() {
+    let fx = Fixture::new();
+    let (reference, files) = fx.genomes(6, 80);
+    let options = PcaConfig::builder().components(2).emit_grm(true).build();
+    perform_full_analysis(&reference, &files, &fx.output(), &options).unwrap();
+
+    let rows = read_csv(fx.output().join("grm.csv"));
+    let n = files.len();
+    assert_eq!(rows.len(), 1 + n);
+    let labels: Vec<String> = rows[1..].iter().map(|row| row[0].clone()).collect();
+    assert_eq!(rows[0][1..], labels[..]);
+    let grm: Vec<Vec<f64>> = rows[1..]
+        .iter()
+        .map(|row| {
+            row[1..]
+                .iter()
+                .map(|value| value.parse().unwrap())
+                .collect()
+        })
+        .collect();
+    for (i, row) in grm.iter().enumerate() {
+        assert!(row[i] >= 0.0, "diagonal {} is {}", i, row[i]);
+        for (j, value) in row.iter().enumerate() {
+            assert_eq!(*value, grm[j][i], "entries ({}, {})", i, j);
+        }
+    }
+    // The trace is the total variance, i.e. the sum of the eigenvalues
+    let trace: f64 = grm.iter().enumerate().map(|(i, row)| row[i]).sum();
+    let eigenvalues: f64 = read_csv(fx.output().join("eigenvalues.csv"))[1..]
+        .iter()
+        .map(|row| row[1].parse::<f64>().unwrap())
+        .sum();
+    assert!(
+        (trace - eigenvalues).abs() < 1e-4,
+        "{} vs {}",
+        trace,
+        eigenvalues
+    );
+}
+
+#[test]
+fn resumed_run_matches_an_uninterrupted_one() {
+    let fx = Fixture::new();
+    let (reference, files) = fx.genomes(5, 60);
+    let options = PcaConfig::builder().components(3);
+    let plain = fx.output().join("plain");
+    perform_full_analysis(&reference, &files, &plain, &options.clone().build()).unwrap();
+
+    // The crashed run stored the first three files, then died partway into the fourth
+    let checkpoint_dir = fx.path("checkpoint");
+    let checkpointed = options.checkpoint_dir(Some(checkpoint_dir.clone()));
+    let crashed = fx.output().join("crashed");
+    perform_full_analysis(
+        &reference,
+        &files[..3],
+        &crashed,
+        &checkpointed.clone().build(),
+    )
+    .unwrap();
+    let sequence = std::fs::read(&reference).unwrap();
+    let mut checkpoint =
+        Checkpoint::open(std::path::Path::new(&checkpoint_dir), &sequence, true).unwrap();
+    assert_eq!(checkpoint.len(), 3);
+    checkpoint
+        .add_sample("ind3", &files[3], &vec![b'N'; sequence.len()])
+        .unwrap();
+
+    // Stored files are not read again, so changing one on disk has no effect
+    fx.write("ind0.txt", vec![b'T'; sequence.len()]);
+    let resumed = fx.output().join("resumed");
+    perform_full_analysis(
+        &reference,
+        &files,
+        &resumed,
+        &checkpointed.resume(true).build(),
+    )
+    .unwrap();
+    for name in ["results.csv", "eigenvalues.csv"] {
+        assert_eq!(
+            std::fs::read(plain.join(name)).unwrap(),
+            std::fs::read(resumed.join(name)).unwrap(),
+            "{} differs",
+            name
+        );
+    }
+    let finished = std::fs::read_to_string(fx.path("checkpoint/files.txt")).unwrap();
+    assert_eq!(finished.lines().collect::<Vec<_>>(), files);
+}